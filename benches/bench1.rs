@@ -1,5 +1,6 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
-use rlox::runner::run_file;
+use rlox::runner::{run_file, run_file_with};
+use rlox::vm::Vm;
 fn criterion_benchmark(c: &mut Criterion) {
     let mut group = c.benchmark_group("benches");
     group
@@ -20,5 +21,18 @@ fn criterion_benchmark(c: &mut Criterion) {
     });
     group.finish();
 }
-criterion_group!(benches, criterion_benchmark);
+fn vm_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("vm_benches");
+    group
+        .measurement_time(std::time::Duration::from_secs(200))
+        .warm_up_time(std::time::Duration::from_secs(10));
+    for name in ["fib", "binary_trees", "equality", "zoo_batch"] {
+        let path = format!("test_data/benches/{name}.lox");
+        group.bench_function(name, |b| {
+            b.iter(|| run_file_with(black_box(&path), &mut Vm::default()))
+        });
+    }
+    group.finish();
+}
+criterion_group!(benches, criterion_benchmark, vm_benchmark);
 criterion_main!(benches);
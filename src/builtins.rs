@@ -0,0 +1,201 @@
+//! Native functions every script can call.
+//!
+//! Each native is written once, against [`NativeValue`], and both backends
+//! define the same tables of them, so they behave and fail alike. The VM's
+//! side of the adapter is in `vm/builtins.rs`.
+use crate::interpreter::Interpreter;
+use crate::syntax::ast::VisitorError;
+use crate::syntax::token::{Literal, Map, MapKey};
+use std::cell::RefCell;
+use std::rc::Rc;
+/// Converts a Lox index into a position in a list of `len` elements.
+pub(crate) fn list_index(index: Option<f64>, len: usize) -> Result<usize, String> {
+    position(index, len, false)
+}
+/// Like [`list_index`], but also accepts `len`, the position after the last
+/// element, as `insert` and `slice` do.
+pub(crate) fn list_position(index: Option<f64>, len: usize) -> Result<usize, String> {
+    position(index, len, true)
+}
+fn position(index: Option<f64>, len: usize, inclusive: bool) -> Result<usize, String> {
+    let Some(index) = index else {
+        return Err("List index must be a number.".to_owned());
+    };
+    if index.fract() != 0.0 {
+        return Err("List index must be an integer.".to_owned());
+    }
+    if index < 0.0 || index > len as f64 || (index == len as f64 && !inclusive) {
+        return Err(format!(
+            "Index {} is out of range for a list of length {}.",
+            index, len
+        ));
+    }
+    Ok(index as usize)
+}
+/// What the natives need to know about a backend's values.
+pub(crate) trait NativeValue: Clone + 'static {
+    fn nil() -> Self;
+    fn number(n: f64) -> Self;
+    fn boolean(b: bool) -> Self;
+    fn from_key(key: &MapKey) -> Self;
+    fn to_number(&self) -> Option<f64>;
+    /// The length in characters, if the value is a string.
+    fn str_len(&self) -> Option<usize>;
+    fn as_list(&self) -> Option<&Rc<RefCell<Vec<Self>>>>;
+    fn as_map(&self) -> Option<&Rc<RefCell<Map<Self>>>>;
+    fn to_key(&self) -> Result<MapKey, String>;
+}
+/// A native returning a value, or the message of a runtime error.
+pub(crate) type Native<V> = fn(&[V]) -> Result<V, String>;
+/// A native returning the items of a new list, which each backend allocates
+/// its own way.
+pub(crate) type ListNative<V> = fn(&[V]) -> Result<Vec<V>, String>;
+/// The natives returning a value, with their names and arities.
+pub(crate) fn natives<V: NativeValue>() -> [(&'static str, usize, Native<V>); 7] {
+    [
+        ("len", 1, len),
+        ("push", 2, push),
+        ("pop", 1, pop),
+        ("insert", 3, insert),
+        ("remove", 2, remove),
+        ("has", 2, has),
+        ("delete", 2, delete),
+    ]
+}
+/// The natives returning a new list, with their names and arities.
+pub(crate) fn list_natives<V: NativeValue>() -> [(&'static str, usize, ListNative<V>); 3] {
+    [
+        ("slice", 3, slice),
+        ("keys", 1, keys),
+        ("values", 1, values),
+    ]
+}
+pub(crate) fn define(interpreter: &mut Interpreter) {
+    for (name, arity, func) in natives() {
+        interpreter.define_native(name, arity, move |_, args| {
+            func(args).map_err(VisitorError::runtime)
+        });
+    }
+    for (name, arity, func) in list_natives() {
+        interpreter.define_native(name, arity, move |interpreter, args| {
+            let items = func(args).map_err(VisitorError::runtime)?;
+            Ok(interpreter.new_list(items))
+        });
+    }
+}
+impl NativeValue for Literal {
+    fn nil() -> Self {
+        Literal::Nil
+    }
+    fn number(n: f64) -> Self {
+        Literal::Number(n)
+    }
+    fn boolean(b: bool) -> Self {
+        Literal::Boolean(b)
+    }
+    fn from_key(key: &MapKey) -> Self {
+        Literal::from(key)
+    }
+    fn to_number(&self) -> Option<f64> {
+        self.as_number()
+    }
+    fn str_len(&self) -> Option<usize> {
+        match self {
+            Literal::String(s) => Some(s.chars().count()),
+            _ => None,
+        }
+    }
+    fn as_list(&self) -> Option<&Rc<RefCell<Vec<Self>>>> {
+        match self {
+            Literal::List(list) => Some(list),
+            _ => None,
+        }
+    }
+    fn as_map(&self) -> Option<&Rc<RefCell<Map<Self>>>> {
+        match self {
+            Literal::Map(map) => Some(map),
+            _ => None,
+        }
+    }
+    fn to_key(&self) -> Result<MapKey, String> {
+        MapKey::try_from(self)
+    }
+}
+fn len<V: NativeValue>(args: &[V]) -> Result<V, String> {
+    let value = &args[0];
+    let len = match (value.as_list(), value.as_map(), value.str_len()) {
+        (Some(list), _, _) => list.borrow().len(),
+        (_, Some(map), _) => map.borrow().len(),
+        (_, _, Some(len)) => len,
+        _ => return Err("len() expects a list, a map or a string.".to_owned()),
+    };
+    Ok(V::number(len as f64))
+}
+fn push<V: NativeValue>(args: &[V]) -> Result<V, String> {
+    list(&args[0], "push")?.borrow_mut().push(args[1].clone());
+    Ok(V::nil())
+}
+fn pop<V: NativeValue>(args: &[V]) -> Result<V, String> {
+    list(&args[0], "pop")?
+        .borrow_mut()
+        .pop()
+        .ok_or_else(|| "Can't pop from an empty list.".to_owned())
+}
+fn insert<V: NativeValue>(args: &[V]) -> Result<V, String> {
+    let list = list(&args[0], "insert")?;
+    let at = list_position(args[1].to_number(), list.borrow().len())?;
+    list.borrow_mut().insert(at, args[2].clone());
+    Ok(V::nil())
+}
+fn remove<V: NativeValue>(args: &[V]) -> Result<V, String> {
+    let list = list(&args[0], "remove")?;
+    let at = list_index(args[1].to_number(), list.borrow().len())?;
+    let removed = list.borrow_mut().remove(at);
+    Ok(removed)
+}
+fn slice<V: NativeValue>(args: &[V]) -> Result<Vec<V>, String> {
+    let list = list(&args[0], "slice")?;
+    let len = list.borrow().len();
+    let start = list_position(args[1].to_number(), len)?;
+    let end = list_position(args[2].to_number(), len)?;
+    if start > end {
+        return Err(format!("Slice start {} is after its end {}.", start, end));
+    }
+    let items = list.borrow()[start..end].to_vec();
+    Ok(items)
+}
+fn keys<V: NativeValue>(args: &[V]) -> Result<Vec<V>, String> {
+    Ok(map(&args[0], "keys")?
+        .borrow()
+        .keys()
+        .map(V::from_key)
+        .collect())
+}
+fn values<V: NativeValue>(args: &[V]) -> Result<Vec<V>, String> {
+    Ok(map(&args[0], "values")?
+        .borrow()
+        .values()
+        .cloned()
+        .collect())
+}
+fn has<V: NativeValue>(args: &[V]) -> Result<V, String> {
+    let key = args[1].to_key()?;
+    Ok(V::boolean(
+        map(&args[0], "has")?.borrow().contains_key(&key),
+    ))
+}
+fn delete<V: NativeValue>(args: &[V]) -> Result<V, String> {
+    let key = args[1].to_key()?;
+    let removed = map(&args[0], "delete")?.borrow_mut().shift_remove(&key);
+    Ok(V::boolean(removed.is_some()))
+}
+fn list<'a, V: NativeValue>(value: &'a V, name: &str) -> Result<&'a Rc<RefCell<Vec<V>>>, String> {
+    value
+        .as_list()
+        .ok_or_else(|| format!("{}() expects a list.", name))
+}
+fn map<'a, V: NativeValue>(value: &'a V, name: &str) -> Result<&'a Rc<RefCell<Map<V>>>, String> {
+    value
+        .as_map()
+        .ok_or_else(|| format!("{}() expects a map.", name))
+}
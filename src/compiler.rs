@@ -0,0 +1,843 @@
+use crate::resolver::ResolverError;
+use crate::stack::StackGuard;
+use crate::syntax::{ast::*, token::*};
+use crate::vm::chunk::{Chunk, OpCode};
+use crate::vm::value::{ObjFunction, Value};
+use rustc_hash::FxHashMap;
+use std::rc::Rc;
+use thiserror::Error;
+#[derive(Error, Debug)]
+pub enum CompileError {
+    #[error("Too many constants in one chunk.")]
+    TooManyConstants(usize),
+    #[error("Too many local variables in function.")]
+    TooManyLocals(usize),
+    #[error("Too many closure variables in function.")]
+    TooManyUpvalues(usize),
+    #[error("Too much code to jump over.")]
+    JumpTooLarge(usize),
+    #[error("Too many elements in a list, map or string literal.")]
+    TooManyElements(usize),
+    #[error("Code is nested too deeply.")]
+    TooDeep(usize),
+}
+impl CompileError {
+    pub fn line(&self) -> usize {
+        match self {
+            CompileError::TooManyConstants(line)
+            | CompileError::TooManyLocals(line)
+            | CompileError::TooManyUpvalues(line)
+            | CompileError::JumpTooLarge(line)
+            | CompileError::TooManyElements(line)
+            | CompileError::TooDeep(line) => *line,
+        }
+    }
+}
+#[derive(Clone, Copy, PartialEq)]
+enum FunctionType {
+    Script,
+    Function,
+    Method,
+    Initializer,
+}
+struct Local {
+    name: String,
+    depth: usize,
+    is_captured: bool,
+}
+struct UpvalueDesc {
+    index: u8,
+    is_local: bool,
+}
+struct LoopState {
+    // scope depth outside the body; locals deeper than this are dropped
+    // when jumping out
+    scope_depth: usize,
+    // number of enclosing `try` handlers; the rest are unwound when jumping
+    // out
+    tries: usize,
+    breaks: Vec<usize>,
+    continues: Vec<usize>,
+}
+/// A handler the code being compiled runs under. Jumps out of it must drop
+/// the handler and run its `finally` block, if it is one.
+#[derive(Clone)]
+struct TryState {
+    finally: Option<Rc<[Stmt]>>,
+}
+struct FunctionState {
+    function: ObjFunction,
+    ftype: FunctionType,
+    locals: Vec<Local>,
+    upvalues: Vec<UpvalueDesc>,
+    scope_depth: usize,
+    identifiers: FxHashMap<String, u16>,
+    loops: Vec<LoopState>,
+    tries: Vec<TryState>,
+}
+impl FunctionState {
+    fn new(name: Option<&str>, ftype: FunctionType) -> Self {
+        // slot 0 holds the callee, or the receiver for methods
+        let slot_zero = match ftype {
+            FunctionType::Method | FunctionType::Initializer => "this",
+            _ => "",
+        };
+        Self {
+            function: ObjFunction {
+                name: name.map(Rc::from),
+                ..Default::default()
+            },
+            ftype,
+            locals: vec![Local {
+                name: slot_zero.to_owned(),
+                depth: 0,
+                is_captured: false,
+            }],
+            upvalues: vec![],
+            scope_depth: 0,
+            identifiers: FxHashMap::default(),
+            loops: vec![],
+            tries: vec![],
+        }
+    }
+}
+/// Lowers resolved statements into bytecode for [`crate::vm::Vm`].
+///
+/// Local variables live in stack slots and are found by walking the scopes
+/// the compiler tracks itself; anything not found in an enclosing function is
+/// a global, exactly as in the `Resolver`.
+pub struct Compiler {
+    states: Vec<FunctionState>,
+    has_superclass: Vec<bool>,
+    line: usize,
+    stack: StackGuard,
+}
+impl Compiler {
+    /// Compiles `stmts`, failing if nested code would need more than
+    /// `stack_limit` bytes of native stack.
+    pub fn compile(stmts: &[Stmt], stack_limit: usize) -> VisitorResult<Rc<ObjFunction>> {
+        let mut compiler = Self::new(stack_limit);
+        for stmt in stmts {
+            compiler.statement(stmt)?;
+        }
+        compiler.emit_return();
+        Ok(compiler.finish())
+    }
+    /// Like [`Compiler::compile`], but a trailing expression statement
+    /// becomes the script's return value.
+    pub fn compile_eval(stmts: &[Stmt], stack_limit: usize) -> VisitorResult<Rc<ObjFunction>> {
+        let Some((Stmt::Expression(expr), rest)) = stmts.split_last() else {
+            return Self::compile(stmts, stack_limit);
+        };
+        let mut compiler = Self::new(stack_limit);
+        for stmt in rest {
+            compiler.statement(stmt)?;
+        }
+        compiler.expression(expr)?;
+        compiler.emit_op(OpCode::Return);
+        Ok(compiler.finish())
+    }
+    fn new(stack_limit: usize) -> Self {
+        Compiler {
+            states: vec![FunctionState::new(None, FunctionType::Script)],
+            has_superclass: vec![],
+            line: 1,
+            stack: StackGuard::new(stack_limit),
+        }
+    }
+    fn statement(&mut self, stmt: &Stmt) -> VisitorResult<()> {
+        self.check_depth()?;
+        stmt.accept(self)
+    }
+    fn expression(&mut self, expr: &Expr) -> VisitorResult<Literal> {
+        if let Some(line) = expr.line() {
+            self.line = line;
+        }
+        self.check_depth()?;
+        expr.accept(self)
+    }
+    fn check_depth(&self) -> VisitorResult<()> {
+        if self.stack.exhausted() {
+            return Err(CompileError::TooDeep(self.line).into());
+        }
+        Ok(())
+    }
+    fn finish(mut self) -> Rc<ObjFunction> {
+        let state = self.states.pop().unwrap();
+        Rc::new(state.function)
+    }
+    #[inline]
+    fn state(&mut self) -> &mut FunctionState {
+        self.states.last_mut().unwrap()
+    }
+    #[inline]
+    fn chunk(&mut self) -> &mut Chunk {
+        &mut self.state().function.chunk
+    }
+    fn emit_byte(&mut self, byte: u8) {
+        let line = self.line;
+        self.chunk().write(byte, line);
+    }
+    fn emit_op(&mut self, op: OpCode) {
+        let line = self.line;
+        self.chunk().write_op(op, line);
+    }
+    fn emit_op_u16(&mut self, op: OpCode, operand: u16) {
+        let line = self.line;
+        self.chunk().write_op(op, line);
+        self.chunk().write_u16(operand, line);
+    }
+    fn emit_return(&mut self) {
+        if self.state().ftype == FunctionType::Initializer {
+            self.emit_op(OpCode::GetLocal);
+            self.emit_byte(0);
+        } else {
+            self.emit_op(OpCode::Nil);
+        }
+        self.emit_op(OpCode::Return);
+    }
+    fn make_constant(&mut self, value: Value) -> Result<u16, CompileError> {
+        let idx = self.chunk().add_constant(value);
+        u16::try_from(idx).map_err(|_| CompileError::TooManyConstants(self.line))
+    }
+    fn identifier_constant(&mut self, name: &str) -> Result<u16, CompileError> {
+        if let Some(&idx) = self.state().identifiers.get(name) {
+            return Ok(idx);
+        }
+        let idx = self.make_constant(Value::String(Rc::from(name)))?;
+        self.state().identifiers.insert(name.to_owned(), idx);
+        Ok(idx)
+    }
+    fn emit_jump(&mut self, op: OpCode) -> usize {
+        self.emit_op_u16(op, u16::MAX);
+        self.chunk().code.len() - 2
+    }
+    fn patch_jump(&mut self, offset: usize) -> Result<(), CompileError> {
+        let jump = self.chunk().code.len() - offset - 2;
+        let jump = u16::try_from(jump).map_err(|_| CompileError::JumpTooLarge(self.line))?;
+        let code = &mut self.chunk().code;
+        code[offset] = (jump >> 8) as u8;
+        code[offset + 1] = jump as u8;
+        Ok(())
+    }
+    fn emit_loop(&mut self, loop_start: usize) -> Result<(), CompileError> {
+        let offset = self.chunk().code.len() + 3 - loop_start;
+        let offset = u16::try_from(offset).map_err(|_| CompileError::JumpTooLarge(self.line))?;
+        self.emit_op_u16(OpCode::Loop, offset);
+        Ok(())
+    }
+    fn begin_scope(&mut self) {
+        self.state().scope_depth += 1;
+    }
+    fn end_scope(&mut self) {
+        let state = self.state();
+        state.scope_depth -= 1;
+        let depth = state.scope_depth;
+        while let Some(local) = self.state().locals.last() {
+            if local.depth <= depth {
+                break;
+            }
+            let op = if local.is_captured {
+                OpCode::CloseUpvalue
+            } else {
+                OpCode::Pop
+            };
+            self.emit_op(op);
+            self.state().locals.pop();
+        }
+    }
+    /// Pops the locals deeper than `depth` without forgetting them, for
+    /// jumps that leave their scopes early.
+    fn discard_locals(&mut self, depth: usize) {
+        let ops: Vec<_> = self
+            .state()
+            .locals
+            .iter()
+            .rev()
+            .take_while(|local| local.depth > depth)
+            .map(|local| {
+                if local.is_captured {
+                    OpCode::CloseUpvalue
+                } else {
+                    OpCode::Pop
+                }
+            })
+            .collect();
+        for op in ops {
+            self.emit_op(op);
+        }
+    }
+    /// Drops the handlers entered after the first `depth` and runs their
+    /// `finally` blocks, innermost first, for code leaving them early.
+    fn unwind_tries(&mut self, depth: usize) -> VisitorResult<()> {
+        let exited = self.state().tries.split_off(depth);
+        for (i, entry) in exited.iter().enumerate().rev() {
+            self.emit_op(OpCode::PopHandler);
+            if let Some(finally) = &entry.finally {
+                // jumps inside the copy still unwind the handlers around it
+                self.state().tries.extend_from_slice(&exited[..i]);
+                self.visit_block(finally)?;
+                self.state().tries.truncate(depth);
+            }
+        }
+        self.state().tries.extend(exited);
+        Ok(())
+    }
+    /// Emits the unpatched jump for a `break` or `continue`.
+    fn jump_out_of_loop(&mut self, keyword: &Token) -> VisitorResult<usize> {
+        self.line = keyword.line;
+        let Some((depth, tries)) = self.state().loops.last().map(|l| (l.scope_depth, l.tries))
+        else {
+            return Err(ResolverError::OutsideLoop(Box::new(keyword.clone())).into());
+        };
+        self.unwind_tries(tries)?;
+        self.line = keyword.line;
+        self.discard_locals(depth);
+        Ok(self.emit_jump(OpCode::Jump))
+    }
+    fn add_local(&mut self, name: &str) -> Result<(), CompileError> {
+        if self.state().locals.len() > u8::MAX as usize {
+            return Err(CompileError::TooManyLocals(self.line));
+        }
+        let depth = self.state().scope_depth;
+        self.state().locals.push(Local {
+            name: name.to_owned(),
+            depth,
+            is_captured: false,
+        });
+        Ok(())
+    }
+    /// Binds the value on top of the stack to `name` in the current scope.
+    fn define_variable(&mut self, name: &str) -> Result<(), CompileError> {
+        if self.state().scope_depth > 0 {
+            return self.add_local(name);
+        }
+        let idx = self.identifier_constant(name)?;
+        self.emit_op_u16(OpCode::DefineGlobal, idx);
+        Ok(())
+    }
+    fn resolve_local(&self, state: usize, name: &str) -> Option<u8> {
+        self.states[state]
+            .locals
+            .iter()
+            .rposition(|local| local.name == name)
+            .map(|slot| slot as u8)
+    }
+    fn add_upvalue(&mut self, state: usize, index: u8, is_local: bool) -> Result<u8, CompileError> {
+        let upvalues = &mut self.states[state].upvalues;
+        if let Some(i) = upvalues
+            .iter()
+            .position(|u| u.index == index && u.is_local == is_local)
+        {
+            return Ok(i as u8);
+        }
+        if upvalues.len() > u8::MAX as usize {
+            return Err(CompileError::TooManyUpvalues(self.line));
+        }
+        upvalues.push(UpvalueDesc { index, is_local });
+        Ok((upvalues.len() - 1) as u8)
+    }
+    fn resolve_upvalue(&mut self, state: usize, name: &str) -> Result<Option<u8>, CompileError> {
+        if state == 0 {
+            return Ok(None);
+        }
+        if let Some(local) = self.resolve_local(state - 1, name) {
+            self.states[state - 1].locals[local as usize].is_captured = true;
+            return self.add_upvalue(state, local, true).map(Some);
+        }
+        match self.resolve_upvalue(state - 1, name)? {
+            Some(upvalue) => self.add_upvalue(state, upvalue, false).map(Some),
+            None => Ok(None),
+        }
+    }
+    fn named_variable(&mut self, name: &str, assign: bool) -> Result<(), CompileError> {
+        let current = self.states.len() - 1;
+        let (op, operand) = if let Some(slot) = self.resolve_local(current, name) {
+            let op = if assign {
+                OpCode::SetLocal
+            } else {
+                OpCode::GetLocal
+            };
+            (op, slot as u16)
+        } else if let Some(slot) = self.resolve_upvalue(current, name)? {
+            let op = if assign {
+                OpCode::SetUpvalue
+            } else {
+                OpCode::GetUpvalue
+            };
+            (op, slot as u16)
+        } else {
+            let op = if assign {
+                OpCode::SetGlobal
+            } else {
+                OpCode::GetGlobal
+            };
+            return self
+                .identifier_constant(name)
+                .map(|idx| self.emit_op_u16(op, idx));
+        };
+        self.emit_op(op);
+        self.emit_byte(operand as u8);
+        Ok(())
+    }
+    fn function(
+        &mut self,
+        name: &Token,
+        params: &[Token],
+        body: &[Stmt],
+        ftype: FunctionType,
+    ) -> VisitorResult<()> {
+        self.line = name.line;
+        // anonymous functions are named by their `fun` keyword
+        let fname = (name.token_type != TokenType::FUN).then_some(name.lexeme.as_str());
+        self.states.push(FunctionState::new(fname, ftype));
+        self.begin_scope();
+        self.state().function.arity = params.len();
+        for param in params {
+            self.add_local(&param.lexeme)?;
+        }
+        for stmt in body {
+            self.statement(stmt)?;
+        }
+        self.emit_return();
+        let state = self.states.pop().unwrap();
+        let mut function = state.function;
+        function.upvalue_count = state.upvalues.len();
+        let idx = self.make_constant(Value::Function(Rc::new(function)))?;
+        self.emit_op_u16(OpCode::Closure, idx);
+        for upvalue in state.upvalues {
+            self.emit_byte(upvalue.is_local as u8);
+            self.emit_byte(upvalue.index);
+        }
+        Ok(())
+    }
+}
+impl StmtVisitor for Compiler {
+    fn visit_expression(&mut self, expr: &Expr) -> VisitorResult<()> {
+        self.expression(expr)?;
+        self.emit_op(OpCode::Pop);
+        Ok(())
+    }
+    fn visit_print(&mut self, expr: &Expr) -> VisitorResult<()> {
+        self.expression(expr)?;
+        self.emit_op(OpCode::Print);
+        Ok(())
+    }
+    fn visit_var(&mut self, token: &Token, expr: Option<&Expr>) -> VisitorResult<()> {
+        match expr {
+            Some(expr) => {
+                self.expression(expr)?;
+            }
+            None => self.emit_op(OpCode::Nil),
+        }
+        self.line = token.line;
+        self.define_variable(&token.lexeme)?;
+        Ok(())
+    }
+    fn visit_block(&mut self, stmts: &[Stmt]) -> VisitorResult<()> {
+        self.begin_scope();
+        for stmt in stmts {
+            self.statement(stmt)?;
+        }
+        self.end_scope();
+        Ok(())
+    }
+    fn visit_if(&mut self, cond: &Expr, body: &(Stmt, Option<Stmt>)) -> VisitorResult<()> {
+        self.expression(cond)?;
+        let then_jump = self.emit_jump(OpCode::JumpIfFalse);
+        self.emit_op(OpCode::Pop);
+        self.statement(&body.0)?;
+        let else_jump = self.emit_jump(OpCode::Jump);
+        self.patch_jump(then_jump)?;
+        self.emit_op(OpCode::Pop);
+        if let Some(else_stmt) = &body.1 {
+            self.statement(else_stmt)?;
+        }
+        self.patch_jump(else_jump)?;
+        Ok(())
+    }
+    fn visit_while(
+        &mut self,
+        cond: &Expr,
+        body: &Stmt,
+        increment: Option<&Expr>,
+    ) -> VisitorResult<()> {
+        let loop_start = self.chunk().code.len();
+        self.expression(cond)?;
+        let exit_jump = self.emit_jump(OpCode::JumpIfFalse);
+        self.emit_op(OpCode::Pop);
+        let scope_depth = self.state().scope_depth;
+        let tries = self.state().tries.len();
+        self.state().loops.push(LoopState {
+            scope_depth,
+            tries,
+            breaks: vec![],
+            continues: vec![],
+        });
+        self.statement(body)?;
+        let state = self.state().loops.pop().unwrap();
+        for jump in state.continues {
+            self.patch_jump(jump)?;
+        }
+        if let Some(increment) = increment {
+            self.expression(increment)?;
+            self.emit_op(OpCode::Pop);
+        }
+        self.emit_loop(loop_start)?;
+        self.patch_jump(exit_jump)?;
+        self.emit_op(OpCode::Pop);
+        for jump in state.breaks {
+            self.patch_jump(jump)?;
+        }
+        Ok(())
+    }
+    fn visit_throw(&mut self, keyword: &Token, value: &Expr) -> VisitorResult<()> {
+        self.expression(value)?;
+        self.line = keyword.line;
+        self.emit_op(OpCode::Throw);
+        Ok(())
+    }
+    fn visit_try(&mut self, stmt: &TryStmt) -> VisitorResult<()> {
+        let finally_handler = match &stmt.finally {
+            Some(finally) => {
+                let handler = self.emit_jump(OpCode::PushFinally);
+                self.state().tries.push(TryState {
+                    finally: Some(Rc::clone(finally)),
+                });
+                Some((handler, finally))
+            }
+            None => None,
+        };
+        match &stmt.catch {
+            Some((name, body)) => {
+                let handler = self.emit_jump(OpCode::PushHandler);
+                self.state().tries.push(TryState { finally: None });
+                self.visit_block(&stmt.body)?;
+                self.state().tries.pop();
+                self.emit_op(OpCode::PopHandler);
+                let skip_catch = self.emit_jump(OpCode::Jump);
+                self.patch_jump(handler)?;
+                // the VM leaves the caught value where the handler was pushed
+                self.begin_scope();
+                self.add_local(&name.lexeme)?;
+                for stmt in body {
+                    self.statement(stmt)?;
+                }
+                self.end_scope();
+                self.patch_jump(skip_catch)?;
+            }
+            None => self.visit_block(&stmt.body)?,
+        }
+        if let Some((handler, finally)) = finally_handler {
+            self.state().tries.pop();
+            self.emit_op(OpCode::PopHandler);
+            self.visit_block(finally)?;
+            let skip_finally = self.emit_jump(OpCode::Jump);
+            self.patch_jump(handler)?;
+            // on an error the VM leaves a handle to it instead, which
+            // `EndFinally` consumes to raise the error again
+            self.begin_scope();
+            self.add_local("")?;
+            self.visit_block(finally)?;
+            self.emit_op(OpCode::EndFinally);
+            self.state().locals.pop();
+            self.state().scope_depth -= 1;
+            self.patch_jump(skip_finally)?;
+        }
+        Ok(())
+    }
+    fn visit_break(&mut self, keyword: &Token) -> VisitorResult<()> {
+        let jump = self.jump_out_of_loop(keyword)?;
+        self.state().loops.last_mut().unwrap().breaks.push(jump);
+        Ok(())
+    }
+    fn visit_continue(&mut self, keyword: &Token) -> VisitorResult<()> {
+        let jump = self.jump_out_of_loop(keyword)?;
+        self.state().loops.last_mut().unwrap().continues.push(jump);
+        Ok(())
+    }
+    fn visit_function(&mut self, func: &FnStmt) -> VisitorResult<()> {
+        let FnStmt {
+            name, params, body, ..
+        } = func;
+        // a local function is visible inside its own body so it can recurse
+        if self.state().scope_depth > 0 {
+            self.add_local(&name.lexeme)?;
+            return self.function(name, params, body, FunctionType::Function);
+        }
+        self.function(name, params, body, FunctionType::Function)?;
+        self.define_variable(&name.lexeme)?;
+        Ok(())
+    }
+    fn visit_return(&mut self, token: &Token, expr: Option<&Expr>) -> VisitorResult<()> {
+        self.line = token.line;
+        if !self.state().tries.is_empty() {
+            match expr {
+                Some(expr) => {
+                    self.expression(expr)?;
+                }
+                None if self.state().ftype == FunctionType::Initializer => {
+                    self.emit_op(OpCode::GetLocal);
+                    self.emit_byte(0);
+                }
+                None => self.emit_op(OpCode::Nil),
+            }
+            // the value waits in a hidden local while `finally` blocks run
+            self.begin_scope();
+            self.add_local("")?;
+            self.unwind_tries(0)?;
+            self.line = token.line;
+            self.emit_op(OpCode::Return);
+            self.state().locals.pop();
+            self.state().scope_depth -= 1;
+            return Ok(());
+        }
+        match expr {
+            Some(expr) => {
+                self.expression(expr)?;
+                self.emit_op(OpCode::Return);
+            }
+            None => self.emit_return(),
+        }
+        Ok(())
+    }
+    fn visit_class(&mut self, class: &ClassStmt) -> VisitorResult<()> {
+        self.line = class.name.line;
+        let name = self.identifier_constant(&class.name.lexeme)?;
+        self.emit_op_u16(OpCode::Class, name);
+        self.define_variable(&class.name.lexeme)?;
+        self.has_superclass.push(class.superclass.is_some());
+        if let Some(superclass) = &class.superclass {
+            self.expression(superclass)?;
+            self.begin_scope();
+            self.add_local("super")?;
+            self.named_variable(&class.name.lexeme, false)?;
+            self.line = class.name.line;
+            self.emit_op(OpCode::Inherit);
+        }
+        self.named_variable(&class.name.lexeme, false)?;
+        for method in class.methods.iter() {
+            let ftype = if method.name.lexeme == "init" {
+                FunctionType::Initializer
+            } else {
+                FunctionType::Method
+            };
+            self.function(&method.name, &method.params, &method.body, ftype)?;
+            let name = self.identifier_constant(&method.name.lexeme)?;
+            self.emit_op_u16(OpCode::Method, name);
+        }
+        self.emit_op(OpCode::Pop);
+        if self.has_superclass.pop().unwrap_or(false) {
+            self.end_scope();
+        }
+        Ok(())
+    }
+}
+impl ExprVisitor for Compiler {
+    fn visit_binary(&mut self, token: &Token, e1: &Expr, e2: &Expr) -> VisitorResult<Literal> {
+        self.expression(e1)?;
+        self.expression(e2)?;
+        self.line = token.line;
+        let op = match token.token_type {
+            TokenType::PLUS => OpCode::Add,
+            TokenType::MINUS => OpCode::Subtract,
+            TokenType::STAR => OpCode::Multiply,
+            TokenType::SLASH => OpCode::Divide,
+            TokenType::GREATER => OpCode::Greater,
+            TokenType::GREATER_EQUAL => OpCode::GreaterEqual,
+            TokenType::LESS => OpCode::Less,
+            TokenType::LESS_EQUAL => OpCode::LessEqual,
+            TokenType::BANG_EQUAL => OpCode::NotEqual,
+            TokenType::EQUAL_EQUAL => OpCode::Equal,
+            _ => {
+                return Err(VisitorError::UnknownOperator(
+                    Box::new(token.clone()),
+                    "binary",
+                ))
+            }
+        };
+        self.emit_op(op);
+        Ok(Literal::Nil)
+    }
+    fn visit_grouping(&mut self, expr: &Expr) -> VisitorResult<Literal> {
+        self.expression(expr)
+    }
+    fn visit_literal(&mut self, ltr: &Literal) -> VisitorResult<Literal> {
+        match ltr {
+            Literal::Nil => self.emit_op(OpCode::Nil),
+            Literal::Boolean(true) => self.emit_op(OpCode::True),
+            Literal::Boolean(false) => self.emit_op(OpCode::False),
+            Literal::Number(n) => {
+                let idx = self.make_constant(Value::Number(*n))?;
+                self.emit_op_u16(OpCode::Constant, idx);
+            }
+            Literal::String(s) => {
+                let idx = self.identifier_constant(s)?;
+                self.emit_op_u16(OpCode::Constant, idx);
+            }
+            _ => return Err(VisitorError::VistorError),
+        }
+        Ok(Literal::Nil)
+    }
+    fn visit_unary(&mut self, token: &Token, expr: &Expr) -> VisitorResult<Literal> {
+        self.expression(expr)?;
+        self.line = token.line;
+        match token.token_type {
+            TokenType::MINUS => self.emit_op(OpCode::Negate),
+            TokenType::BANG => self.emit_op(OpCode::Not),
+            _ => {
+                return Err(VisitorError::UnknownOperator(
+                    Box::new(token.clone()),
+                    "unary",
+                ))
+            }
+        }
+        Ok(Literal::Nil)
+    }
+    fn visit_variable(&mut self, variable: &Variable) -> VisitorResult<Literal> {
+        self.line = variable.name.line;
+        self.named_variable(&variable.name.lexeme, false)?;
+        Ok(Literal::Nil)
+    }
+    fn visit_assign(&mut self, assign: &Assign) -> VisitorResult<Literal> {
+        self.expression(&assign.value)?;
+        self.line = assign.name.line;
+        self.named_variable(&assign.name.lexeme, true)?;
+        Ok(Literal::Nil)
+    }
+    fn visit_logical(
+        &mut self,
+        left: &Expr,
+        token: &Token,
+        right: &Expr,
+    ) -> VisitorResult<Literal> {
+        self.expression(left)?;
+        self.line = token.line;
+        match token.token_type {
+            TokenType::AND => {
+                let end_jump = self.emit_jump(OpCode::JumpIfFalse);
+                self.emit_op(OpCode::Pop);
+                self.expression(right)?;
+                self.patch_jump(end_jump)?;
+            }
+            TokenType::OR => {
+                let else_jump = self.emit_jump(OpCode::JumpIfFalse);
+                let end_jump = self.emit_jump(OpCode::Jump);
+                self.patch_jump(else_jump)?;
+                self.emit_op(OpCode::Pop);
+                self.expression(right)?;
+                self.patch_jump(end_jump)?;
+            }
+            _ => {
+                return Err(VisitorError::UnknownOperator(
+                    Box::new(token.clone()),
+                    "logical",
+                ))
+            }
+        }
+        Ok(Literal::Nil)
+    }
+    fn visit_call(
+        &mut self,
+        callee: &Expr,
+        paren: &Token,
+        args: &[Expr],
+    ) -> VisitorResult<Literal> {
+        // `object.method(...)` skips allocating a bound method
+        if let Expr::Get(get) = callee {
+            self.expression(&get.object)?;
+            for arg in args {
+                self.expression(arg)?;
+            }
+            self.line = paren.line;
+            let name = self.identifier_constant(&get.name.lexeme)?;
+            self.emit_op_u16(OpCode::Invoke, name);
+            self.emit_byte(args.len() as u8);
+            return Ok(Literal::Nil);
+        }
+        self.expression(callee)?;
+        for arg in args {
+            self.expression(arg)?;
+        }
+        self.line = paren.line;
+        self.emit_op(OpCode::Call);
+        self.emit_byte(args.len() as u8);
+        Ok(Literal::Nil)
+    }
+    fn visit_get(&mut self, get: &Get) -> VisitorResult<Literal> {
+        self.expression(&get.object)?;
+        self.line = get.name.line;
+        let name = self.identifier_constant(&get.name.lexeme)?;
+        self.emit_op_u16(OpCode::GetProperty, name);
+        Ok(Literal::Nil)
+    }
+    fn visitor_set(&mut self, set: &Set) -> VisitorResult<Literal> {
+        self.expression(&set.object)?;
+        self.expression(&set.value)?;
+        self.line = set.name.line;
+        let name = self.identifier_constant(&set.name.lexeme)?;
+        self.emit_op_u16(OpCode::SetProperty, name);
+        Ok(Literal::Nil)
+    }
+    fn visit_this(&mut self, this: &This) -> VisitorResult<Literal> {
+        self.line = this.token.line;
+        self.named_variable("this", false)?;
+        Ok(Literal::Nil)
+    }
+    fn visit_lambda(&mut self, func: &Rc<FnStmt>) -> VisitorResult<Literal> {
+        self.function(&func.name, &func.params, &func.body, FunctionType::Function)?;
+        Ok(Literal::Nil)
+    }
+    fn visit_list(&mut self, items: &[Expr]) -> VisitorResult<Literal> {
+        for item in items {
+            self.expression(item)?;
+        }
+        let count =
+            u16::try_from(items.len()).map_err(|_| CompileError::TooManyElements(self.line))?;
+        self.emit_op_u16(OpCode::BuildList, count);
+        Ok(Literal::Nil)
+    }
+    fn visit_map(&mut self, entries: &[(Expr, Expr)], brace: &Token) -> VisitorResult<Literal> {
+        for (key, value) in entries {
+            self.expression(key)?;
+            self.expression(value)?;
+        }
+        self.line = brace.line;
+        let count =
+            u16::try_from(entries.len()).map_err(|_| CompileError::TooManyElements(self.line))?;
+        self.emit_op_u16(OpCode::BuildMap, count);
+        Ok(Literal::Nil)
+    }
+    fn visit_index(&mut self, index: &Index) -> VisitorResult<Literal> {
+        self.expression(&index.object)?;
+        self.expression(&index.index)?;
+        self.line = index.bracket.line;
+        self.emit_op(OpCode::GetIndex);
+        Ok(Literal::Nil)
+    }
+    fn visit_set_index(&mut self, set: &SetIndex) -> VisitorResult<Literal> {
+        self.expression(&set.object)?;
+        self.expression(&set.index)?;
+        self.expression(&set.value)?;
+        self.line = set.bracket.line;
+        self.emit_op(OpCode::SetIndex);
+        Ok(Literal::Nil)
+    }
+    fn visit_interpolation(&mut self, parts: &[Expr]) -> VisitorResult<Literal> {
+        for part in parts {
+            self.expression(part)?;
+        }
+        let count =
+            u16::try_from(parts.len()).map_err(|_| CompileError::TooManyElements(self.line))?;
+        self.emit_op_u16(OpCode::Interpolate, count);
+        Ok(Literal::Nil)
+    }
+    fn visit_super(&mut self, s: &Super) -> VisitorResult<Literal> {
+        self.line = s.method.line;
+        self.named_variable("this", false)?;
+        self.named_variable("super", false)?;
+        let name = self.identifier_constant(&s.method.lexeme)?;
+        self.emit_op_u16(OpCode::GetSuper, name);
+        Ok(Literal::Nil)
+    }
+}
@@ -0,0 +1,280 @@
+use crate::interpreter::CallFrame;
+use crate::syntax::ast::VisitorError;
+use crate::syntax::parser::ParserError;
+use crate::syntax::token::{SourceId, Span, Token};
+use crate::syntax::tokenizer::TokenizerError;
+use std::fmt::{self, Display};
+/// How many times in a row a trace prints the same frame.
+const MAX_REPEATED_FRAMES: usize = 3;
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+/// The pipeline stage that produced a [`Diagnostic`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    /// Loading the script failed before it could be scanned.
+    Reading,
+    Scanning,
+    Parsing,
+    Resolving,
+    Compiling,
+    Runtime,
+    /// Execution ran out of its step budget or time, which is not an error
+    /// in the script itself.
+    Interrupted,
+}
+/// A user-facing error or warning, detached from the pipeline's internal
+/// error types so embedders can render or log it however they like.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub phase: Phase,
+    pub message: String,
+    /// 1-based source line, or 0 when the error has no location.
+    pub line: usize,
+    /// 1-based column where `span` starts.
+    pub column: Option<usize>,
+    /// Byte range of the offending source text.
+    pub span: Option<Span>,
+    /// Lexeme of the offending token; empty for the end of input.
+    pub lexeme: Option<String>,
+    /// The full text of `line`, filled in by [`Diagnostic::with_source`].
+    pub snippet: Option<String>,
+    /// For runtime errors raised inside calls, the calls in progress,
+    /// innermost first.
+    pub trace: Vec<CallFrame>,
+    // number of carets drawn under the snippet
+    underline: usize,
+}
+impl Diagnostic {
+    pub fn error(phase: Phase, line: usize, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Error,
+            phase,
+            message: message.into(),
+            line,
+            column: None,
+            span: None,
+            lexeme: None,
+            snippet: None,
+            trace: Vec::new(),
+            underline: 0,
+        }
+    }
+    pub fn with_span(mut self, span: Span) -> Self {
+        self.span = Some(span);
+        self
+    }
+    fn with_token(self, token: &Token) -> Self {
+        let mut diagnostic = self.with_lexeme(&token.lexeme);
+        if token.has_span() {
+            diagnostic.column = Some(token.column);
+            diagnostic.span = Some(token.span);
+        }
+        diagnostic
+    }
+    pub fn with_lexeme(mut self, lexeme: impl Into<String>) -> Self {
+        self.lexeme = Some(lexeme.into());
+        self
+    }
+    /// Attaches the offending line of `source`, the text scanned with id
+    /// `id`, and, when the span starts on that line, works out where to draw
+    /// the caret underline. A span from another source, like the body of a
+    /// function defined by an earlier REPL line, gets no snippet.
+    pub fn with_source(mut self, source: &str, id: SourceId) -> Self {
+        if self.line == 0 || self.span.is_some_and(|span| span.source != id) {
+            return self;
+        }
+        let mut line_start = 0;
+        let Some(text) = source.split('\n').nth(self.line - 1).map(|text| {
+            line_start = text.as_ptr() as usize - source.as_ptr() as usize;
+            text.strip_suffix('\r').unwrap_or(text)
+        }) else {
+            return self;
+        };
+        self.snippet = Some(text.to_owned());
+        let line_end = line_start + text.len();
+        let underlined = self.span.and_then(|span| {
+            if !(line_start..=line_end).contains(&span.start) {
+                return None;
+            }
+            let end = span.end.clamp(span.start, line_end);
+            let before = source.get(line_start..span.start)?;
+            Some((before, source.get(span.start..end)?))
+        });
+        match underlined {
+            Some((before, underlined)) => {
+                self.column = Some(before.chars().count() + 1);
+                self.underline = underlined.chars().count().max(1);
+            }
+            None => self.underline = 0,
+        }
+        self
+    }
+    pub fn with_trace(mut self, trace: Vec<CallFrame>) -> Self {
+        self.trace = trace;
+        self
+    }
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "Error",
+            Severity::Warning => "Warning",
+        };
+        if self.line > 0 {
+            write!(f, "[line {}] ", self.line)?;
+        }
+        write!(f, "{}", severity)?;
+        match self.lexeme.as_deref() {
+            Some("") => write!(f, " at end")?,
+            Some(lexeme) => write!(f, " at '{}'", lexeme)?,
+            None => {}
+        }
+        write!(f, ": {}", self.message)?;
+        if let Some(snippet) = &self.snippet {
+            write!(f, "\n{:>5} | {}", self.line, snippet)?;
+            if let (Some(column), 1..) = (self.column, self.underline) {
+                write!(
+                    f,
+                    "\n      | {}{}",
+                    " ".repeat(column - 1),
+                    "^".repeat(self.underline)
+                )?;
+            }
+        }
+        if !self.trace.is_empty() {
+            // runaway recursion repeats one frame; show a few of a run
+            let mut frames = self.trace.iter().peekable();
+            while let Some(frame) = frames.next() {
+                let mut repeats = 0;
+                while frames.next_if_eq(&frame).is_some() {
+                    repeats += 1;
+                }
+                for _ in 0..=repeats.min(MAX_REPEATED_FRAMES - 1) {
+                    write!(f, "\n  {}", frame)?;
+                }
+                if repeats >= MAX_REPEATED_FRAMES {
+                    let more = repeats + 1 - MAX_REPEATED_FRAMES;
+                    write!(f, "\n  ... repeated {} more times", more)?;
+                }
+            }
+            write!(f, "\n  at script")?;
+        }
+        Ok(())
+    }
+}
+impl From<&TokenizerError> for Diagnostic {
+    fn from(e: &TokenizerError) -> Self {
+        Diagnostic::error(Phase::Scanning, e.line(), e.to_string()).with_span(e.span())
+    }
+}
+impl From<&ParserError> for Diagnostic {
+    fn from(e: &ParserError) -> Self {
+        Diagnostic::error(Phase::Parsing, e.token.line, e.to_string()).with_token(&e.token)
+    }
+}
+impl From<&VisitorError> for Diagnostic {
+    fn from(e: &VisitorError) -> Self {
+        let phase = match e {
+            VisitorError::Resolver(_) => Phase::Resolving,
+            VisitorError::Compile(_) => Phase::Compiling,
+            e if e.is_interrupt() => Phase::Interrupted,
+            _ => Phase::Runtime,
+        };
+        let diagnostic = Diagnostic::error(phase, e.line(), e.to_string());
+        match e.token() {
+            Some(token) => diagnostic.with_token(token),
+            None => diagnostic,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_display() {
+        let diagnostic = Diagnostic::error(Phase::Parsing, 2, "Expect expression.")
+            .with_lexeme("var")
+            .with_source("// comment\nif (true) var foo;\n", SourceId::default());
+        assert_eq!(
+            diagnostic.to_string(),
+            "[line 2] Error at 'var': Expect expression.\n    2 | if (true) var foo;"
+        );
+    }
+    #[test]
+    fn test_caret() {
+        let source = "var a = 1;\r\nprint \"é\" - a;\n";
+        let start = source.find('"').unwrap();
+        let diagnostic = Diagnostic::error(Phase::Runtime, 2, "Operands must be numbers.")
+            .with_span(Span::new(start, source.rfind('a').unwrap() + 1))
+            .with_source(source, SourceId::default());
+        assert_eq!(diagnostic.column, Some(7));
+        assert_eq!(
+            diagnostic.to_string(),
+            "[line 2] Error: Operands must be numbers.\n    2 | print \"é\" - a;\n      |       ^^^^^^^"
+        );
+    }
+    #[test]
+    fn test_other_source() {
+        let id = SourceId::next();
+        let diagnostic = Diagnostic::error(Phase::Runtime, 1, "Operands must be numbers.")
+            .with_span(Span::in_source(3, 26, id))
+            .with_source("f(); // éééééééééééééééééééé", SourceId::next());
+        assert_eq!(diagnostic.snippet, None);
+        // a span that doesn't fall on character boundaries gets no caret
+        let diagnostic = Diagnostic::error(Phase::Runtime, 1, "Operands must be numbers.")
+            .with_span(Span::in_source(4, 7, id))
+            .with_source("é é é", id);
+        assert_eq!(diagnostic.snippet.as_deref(), Some("é é é"));
+        assert_eq!(diagnostic.underline, 0);
+    }
+    #[test]
+    fn test_trace() {
+        let frame = |name: &str, line| CallFrame {
+            name: name.to_owned(),
+            line,
+        };
+        let diagnostic = Diagnostic::error(Phase::Runtime, 2, "Operands must be two numbers.")
+            .with_lexeme("+")
+            .with_trace(vec![frame("inner", 5), frame("outer", 7)]);
+        assert_eq!(
+            diagnostic.to_string(),
+            "[line 2] Error at '+': Operands must be two numbers.\n  at inner (line 5)\n  at outer (line 7)\n  at script"
+        );
+    }
+    #[test]
+    fn test_repeated_frames() {
+        let frame = |name: &str, line| CallFrame {
+            name: name.to_owned(),
+            line,
+        };
+        let mut trace = vec![frame("f", 2); 10];
+        trace.push(frame("g", 5));
+        let diagnostic = Diagnostic::error(Phase::Runtime, 2, "Stack overflow.").with_trace(trace);
+        assert_eq!(
+            diagnostic.to_string(),
+            "[line 2] Error: Stack overflow.\n  at f (line 2)\n  at f (line 2)\n  at f (line 2)\n  ... repeated 7 more times\n  at g (line 5)\n  at script"
+        );
+    }
+    #[test]
+    fn test_no_line() {
+        let diagnostic = Diagnostic::error(Phase::Interrupted, 0, "Execution timed out.");
+        assert_eq!(diagnostic.to_string(), "Error: Execution timed out.");
+    }
+    #[test]
+    fn test_at_end() {
+        let diagnostic =
+            Diagnostic::error(Phase::Parsing, 1, "Expect ';' after value.").with_lexeme("");
+        assert_eq!(
+            diagnostic.to_string(),
+            "[line 1] Error at end: Expect ';' after value."
+        );
+    }
+}
@@ -0,0 +1,111 @@
+use crate::diagnostic::{Diagnostic, Phase};
+use crate::gc::GcStats;
+use crate::interpreter::Interpreter;
+use crate::output::{Captured, Output};
+use crate::runner;
+use crate::syntax::ast::VisitorResult;
+use crate::syntax::token::Literal;
+use std::time::{Duration, Instant};
+/// An rlox instance for embedding in Rust programs. Globals, functions and
+/// classes defined by one `eval` stay visible to the next.
+#[derive(Default)]
+pub struct Engine {
+    interpreter: Interpreter,
+}
+impl Engine {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Runs `source` and returns the value of its final statement if that
+    /// is an expression statement, or nil otherwise.
+    pub fn eval(&mut self, source: &str) -> Result<Literal, Vec<Diagnostic>> {
+        let (stmts, id) = runner::parse_source(source, self.interpreter.stack_limit())?;
+        self.interpreter.eval(&stmts).map_err(|e| {
+            vec![Diagnostic::from(&e)
+                .with_source(source, id)
+                .with_trace(self.interpreter.stack_trace())]
+        })
+    }
+    pub fn get_global(&self, name: &str) -> Option<Literal> {
+        self.interpreter.get_global(name)
+    }
+    pub fn set_global(&mut self, name: &str, value: impl Into<Literal>) {
+        self.interpreter.define_global(name, value.into());
+    }
+    /// Calls the global function or class `name` with `args`. Errors come
+    /// back as diagnostics, like those from [`Engine::eval`].
+    pub fn call(&mut self, name: &str, args: &[Literal]) -> Result<Literal, Vec<Diagnostic>> {
+        let callee = self.get_global(name).ok_or_else(|| {
+            vec![Diagnostic::error(
+                Phase::Runtime,
+                0,
+                format!("Undefined variable '{name}'."),
+            )]
+        })?;
+        self.interpreter
+            .call(callee, args.to_vec())
+            .map_err(|e| vec![Diagnostic::from(&e).with_trace(self.interpreter.stack_trace())])
+    }
+    /// Sends the text of `print` statements to `output` instead of stdout.
+    pub fn set_output(&mut self, output: Output) {
+        self.interpreter.set_output(output);
+    }
+    /// Collects printed text instead of writing it to stdout.
+    pub fn capture_output(&mut self) -> Captured {
+        self.interpreter.capture_output()
+    }
+    /// Limits how deep Lox calls may nest; see
+    /// [`Interpreter::set_max_call_depth`].
+    pub fn set_max_call_depth(&mut self, depth: usize) {
+        self.interpreter.set_max_call_depth(depth);
+    }
+    /// Limits the native stack Lox calls may use; see
+    /// [`Interpreter::set_stack_limit`].
+    pub fn set_stack_limit(&mut self, bytes: usize) {
+        self.interpreter.set_stack_limit(bytes);
+    }
+    /// Limits execution to `fuel` more steps; see
+    /// [`Interpreter::set_fuel`].
+    pub fn set_fuel(&mut self, fuel: Option<u64>) {
+        self.interpreter.set_fuel(fuel);
+    }
+    pub fn add_fuel(&mut self, fuel: u64) {
+        self.interpreter.add_fuel(fuel);
+    }
+    pub fn fuel(&self) -> Option<u64> {
+        self.interpreter.fuel()
+    }
+    /// Stops execution once `deadline` passes; see
+    /// [`Interpreter::set_deadline`].
+    pub fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.interpreter.set_deadline(deadline);
+    }
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.interpreter.set_timeout(timeout);
+    }
+    /// Frees unreachable reference cycles and returns how many objects were
+    /// freed.
+    pub fn collect_garbage(&mut self) -> usize {
+        self.interpreter.collect_garbage()
+    }
+    pub fn gc_stats(&self) -> GcStats {
+        self.interpreter.gc_stats()
+    }
+    /// Exposes a Rust function to Lox as a global taking `arity` arguments.
+    pub fn register(
+        &mut self,
+        name: &str,
+        arity: usize,
+        func: impl Fn(&mut Interpreter, &[Literal]) -> VisitorResult<Literal> + 'static,
+    ) {
+        self.interpreter.define_native(name, arity, func);
+    }
+    /// Exposes a Rust function to Lox as a global taking any arguments.
+    pub fn register_variadic(
+        &mut self,
+        name: &str,
+        func: impl Fn(&mut Interpreter, &[Literal]) -> VisitorResult<Literal> + 'static,
+    ) {
+        self.interpreter.define_variadic(name, func);
+    }
+}
@@ -1,3 +1,4 @@
+use crate::gc::{GcRef, Trace};
 use crate::syntax::token::{Literal, Token};
 use rustc_hash::FxHashMap;
 use std::{cell::RefCell, rc::Rc};
@@ -5,11 +6,19 @@ pub type EnvironmentRef = Rc<RefCell<Environment>>;
 use thiserror::Error;
 #[derive(Error, Debug)]
 pub enum EnvironmentError {
-    #[error("line {}: {} ** Undefined variable",.0.line,.0.lexeme)]
-    UndefinedVariable(Token),
+    #[error("Undefined variable '{}'.", .0.lexeme)]
+    UndefinedVariable(Box<Token>),
     #[error("Invalid environment distance")]
     InvalidEnvironmentDistance,
 }
+impl EnvironmentError {
+    pub fn token(&self) -> Option<&Token> {
+        match self {
+            EnvironmentError::UndefinedVariable(token) => Some(token),
+            EnvironmentError::InvalidEnvironmentDistance => None,
+        }
+    }
+}
 pub trait Envt {
     fn define(&mut self, name: String, value: Literal);
     fn get(&self, name: &Token) -> Result<Literal, EnvironmentError>;
@@ -56,6 +65,12 @@ impl Environment {
             enclosing,
         }
     }
+    /// The variables defined directly in this scope.
+    pub fn bindings(&self) -> impl Iterator<Item = (&str, &Literal)> {
+        self.values
+            .iter()
+            .map(|(name, value)| (name.as_str(), value))
+    }
     #[inline(always)]
     fn ancestor(&self, distance: usize) -> Result<&Environment, EnvironmentError> {
         unsafe {
@@ -102,7 +117,7 @@ impl Envt for Environment {
             })
             .ok_or_else(|| {
                 // error(name, "Undefined variable");
-                EnvironmentError::UndefinedVariable(name.clone())
+                EnvironmentError::UndefinedVariable(Box::new(name.clone()))
             })
     }
     fn assign(&mut self, name: &Token, value: Literal) -> Result<(), EnvironmentError> {
@@ -118,7 +133,7 @@ impl Envt for Environment {
             })
             .ok_or_else(|| {
                 // error(name, "Undefined variable");
-                EnvironmentError::UndefinedVariable(name.clone())
+                EnvironmentError::UndefinedVariable(Box::new(name.clone()))
             })
     }
     fn get_at(&self, distance: usize, name: &Token) -> Result<Literal, EnvironmentError> {
@@ -139,6 +154,16 @@ impl Envt for Environment {
         )
     }
 }
+impl Trace for Environment {
+    fn trace(&self, visit: &mut dyn FnMut(GcRef<'_>)) {
+        for value in self.values.values() {
+            value.trace(visit);
+        }
+        if let Some(enclosing) = &self.enclosing {
+            visit(GcRef::Environment(enclosing));
+        }
+    }
+}
 
 #[cfg(test)]
 mod tests {}
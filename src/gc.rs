@@ -0,0 +1,395 @@
+//! Cycle collector for the tree-walking interpreter.
+//!
+//! Values are reference counted, so anything that isn't part of a cycle is
+//! freed as soon as it becomes unreachable. Cycles (an instance that stores
+//! itself, a closure stored in the environment it captures) are found by
+//! trial deletion: every object's strong count is compared with the number
+//! of references held by other objects in the heap, and whatever is only
+//! referenced from inside the heap is garbage.
+//!
+//! Only objects that can close a cycle are registered: instances, lists,
+//! maps, and the environments captured by functions and methods. Everything they point at
+//! is discovered while tracing.
+use crate::environment::{Environment, EnvironmentRef};
+use crate::syntax::token::{Class, Instance, ListRef, Literal, Map, MapRef};
+use rustc_hash::{FxHashMap, FxHashSet};
+use std::cell::RefCell;
+use std::rc::{Rc, Weak};
+/// A borrowed reference to a reference-counted object, handed out by
+/// [`Trace::trace`].
+pub enum GcRef<'a> {
+    Environment(&'a EnvironmentRef),
+    Instance(&'a Rc<RefCell<Instance>>),
+    Class(&'a Rc<Class>),
+    List(&'a ListRef),
+    Map(&'a MapRef),
+}
+/// Implemented by everything that can hold references to heap objects.
+pub trait Trace {
+    /// Calls `visit` with each heap object `self` holds a strong reference
+    /// to. Values held inline (a `Class` inside a `Literal`) are traced
+    /// through rather than reported.
+    fn trace(&self, visit: &mut dyn FnMut(GcRef<'_>));
+}
+enum Object {
+    Environment(EnvironmentRef),
+    Instance(Rc<RefCell<Instance>>),
+    Class(Rc<Class>),
+    List(ListRef),
+    Map(MapRef),
+}
+impl Object {
+    fn from_ref(r: GcRef<'_>) -> Self {
+        match r {
+            GcRef::Environment(env) => Object::Environment(Rc::clone(env)),
+            GcRef::Instance(instance) => Object::Instance(Rc::clone(instance)),
+            GcRef::Class(class) => Object::Class(Rc::clone(class)),
+            GcRef::List(list) => Object::List(Rc::clone(list)),
+            GcRef::Map(map) => Object::Map(Rc::clone(map)),
+        }
+    }
+    fn addr(&self) -> usize {
+        match self {
+            Object::Environment(env) => Rc::as_ptr(env) as *const () as usize,
+            Object::Instance(instance) => Rc::as_ptr(instance) as *const () as usize,
+            Object::Class(class) => Rc::as_ptr(class) as *const () as usize,
+            Object::List(list) => Rc::as_ptr(list) as *const () as usize,
+            Object::Map(map) => Rc::as_ptr(map) as *const () as usize,
+        }
+    }
+    fn strong_count(&self) -> usize {
+        match self {
+            Object::Environment(env) => Rc::strong_count(env),
+            Object::Instance(instance) => Rc::strong_count(instance),
+            Object::Class(class) => Rc::strong_count(class),
+            Object::List(list) => Rc::strong_count(list),
+            Object::Map(map) => Rc::strong_count(map),
+        }
+    }
+    /// Traces the object's references, or returns `false` if it is borrowed
+    /// mutably and can't be looked at.
+    fn trace(&self, visit: &mut dyn FnMut(GcRef<'_>)) -> bool {
+        match self {
+            Object::Environment(env) => env.try_borrow().map(|env| env.trace(visit)).is_ok(),
+            Object::Instance(instance) => instance
+                .try_borrow()
+                .map(|instance| instance.trace(visit))
+                .is_ok(),
+            Object::Class(class) => {
+                class.trace(visit);
+                true
+            }
+            Object::List(list) => list
+                .try_borrow()
+                .map(|list| list.iter().for_each(|item| item.trace(visit)))
+                .is_ok(),
+            Object::Map(map) => map
+                .try_borrow()
+                .map(|map| map.values().for_each(|value| value.trace(visit)))
+                .is_ok(),
+        }
+    }
+}
+fn ref_addr(r: &GcRef<'_>) -> usize {
+    match r {
+        GcRef::Environment(env) => Rc::as_ptr(env) as *const () as usize,
+        GcRef::Instance(instance) => Rc::as_ptr(instance) as *const () as usize,
+        GcRef::Class(class) => Rc::as_ptr(class) as *const () as usize,
+        GcRef::List(list) => Rc::as_ptr(list) as *const () as usize,
+        GcRef::Map(map) => Rc::as_ptr(map) as *const () as usize,
+    }
+}
+enum Root {
+    Environment(Weak<RefCell<Environment>>),
+    Instance(Weak<RefCell<Instance>>),
+    List(Weak<RefCell<Vec<Literal>>>),
+    Map(Weak<RefCell<Map<Literal>>>),
+}
+impl Root {
+    fn upgrade(&self) -> Option<Object> {
+        match self {
+            Root::Environment(env) => env.upgrade().map(Object::Environment),
+            Root::Instance(instance) => instance.upgrade().map(Object::Instance),
+            Root::List(list) => list.upgrade().map(Object::List),
+            Root::Map(map) => map.upgrade().map(Object::Map),
+        }
+    }
+    fn is_alive(&self) -> bool {
+        match self {
+            Root::Environment(env) => env.strong_count() > 0,
+            Root::Instance(instance) => instance.strong_count() > 0,
+            Root::List(list) => list.strong_count() > 0,
+            Root::Map(map) => map.strong_count() > 0,
+        }
+    }
+}
+/// Counters describing the collector's work so far.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GcStats {
+    /// Number of collections run.
+    pub collections: usize,
+    /// Objects registered with the collector that are still alive.
+    pub tracked: usize,
+    /// Objects freed by breaking cycles, over all collections.
+    pub collected: usize,
+    /// Registrations left before the next automatic collection.
+    pub next_collection: usize,
+}
+const INITIAL_THRESHOLD: usize = 1024;
+pub struct Heap {
+    roots: Vec<Root>,
+    // registrations since the last collection
+    allocated: usize,
+    threshold: usize,
+    // registrations still alive after the last full collection
+    survivors: usize,
+    collections: usize,
+    collected: usize,
+}
+impl Default for Heap {
+    fn default() -> Self {
+        Self {
+            roots: Vec::new(),
+            allocated: 0,
+            threshold: INITIAL_THRESHOLD,
+            survivors: 0,
+            collections: 0,
+            collected: 0,
+        }
+    }
+}
+impl Heap {
+    /// Registers an environment that a function has captured.
+    pub fn track_environment(&mut self, env: &EnvironmentRef) {
+        // methods of one class all capture the same environment
+        if let Some(Root::Environment(last)) = self.roots.last() {
+            if std::ptr::eq(last.as_ptr(), Rc::as_ptr(env)) {
+                return;
+            }
+        }
+        self.roots.push(Root::Environment(Rc::downgrade(env)));
+        self.allocated += 1;
+    }
+    pub fn track_instance(&mut self, instance: &Rc<RefCell<Instance>>) {
+        self.roots.push(Root::Instance(Rc::downgrade(instance)));
+        self.allocated += 1;
+    }
+    pub fn track_list(&mut self, list: &ListRef) {
+        self.roots.push(Root::List(Rc::downgrade(list)));
+        self.allocated += 1;
+    }
+    pub fn track_map(&mut self, map: &MapRef) {
+        self.roots.push(Root::Map(Rc::downgrade(map)));
+        self.allocated += 1;
+    }
+    /// Runs once enough objects have been registered since the last time.
+    /// Most objects die young without ever being in a cycle, so the
+    /// registrations of freed objects are dropped first, which is cheap, and
+    /// a full [`Heap::collect`] only runs if the live objects have doubled
+    /// since the last one.
+    pub fn collect_if_needed(&mut self) {
+        if self.allocated < self.threshold {
+            return;
+        }
+        self.roots.retain(Root::is_alive);
+        if self.roots.len() >= (2 * self.survivors).max(INITIAL_THRESHOLD) {
+            self.collect();
+        } else {
+            self.allocated = 0;
+            self.threshold = INITIAL_THRESHOLD.max(self.roots.len());
+        }
+    }
+    pub fn stats(&self) -> GcStats {
+        GcStats {
+            collections: self.collections,
+            tracked: self.roots.iter().filter(|root| root.is_alive()).count(),
+            collected: self.collected,
+            next_collection: self.threshold.saturating_sub(self.allocated),
+        }
+    }
+    /// Frees every cycle that is no longer reachable from outside the heap
+    /// and returns how many objects were freed.
+    ///
+    /// References the collector can't see (the interpreter's own
+    /// environments, values on the Rust stack, state captured by native
+    /// functions) count as external, so it is safe to call at any point.
+    pub fn collect(&mut self) -> usize {
+        // discover every object reachable from the registered roots
+        let mut objects: FxHashMap<usize, Object> = FxHashMap::default();
+        let mut order = Vec::new();
+        let mut stack: Vec<Object> = self.roots.iter().filter_map(Root::upgrade).collect();
+        while let Some(object) = stack.pop() {
+            let addr = object.addr();
+            if objects.contains_key(&addr) {
+                continue;
+            }
+            object.trace(&mut |r| {
+                if !objects.contains_key(&ref_addr(&r)) {
+                    stack.push(Object::from_ref(r));
+                }
+            });
+            objects.insert(addr, object);
+            order.push(addr);
+        }
+        // each object is now held once by `objects`; subtract the references
+        // held by other heap objects from what is left
+        let mut external: FxHashMap<usize, usize> = objects
+            .iter()
+            .map(|(&addr, object)| (addr, object.strong_count() - 1))
+            .collect();
+        let mut pinned = FxHashSet::default();
+        for &addr in &order {
+            let traced = objects[&addr].trace(&mut |r| {
+                if let Some(count) = external.get_mut(&ref_addr(&r)) {
+                    *count = count.saturating_sub(1);
+                }
+            });
+            if !traced {
+                pinned.insert(addr);
+            }
+        }
+        // anything referenced from outside, and everything it reaches, lives
+        let mut live: FxHashSet<usize> = FxHashSet::default();
+        let mut stack: Vec<usize> = order
+            .iter()
+            .copied()
+            .filter(|addr| external[addr] > 0 || pinned.contains(addr))
+            .collect();
+        while let Some(addr) = stack.pop() {
+            if !live.insert(addr) {
+                continue;
+            }
+            objects[&addr].trace(&mut |r| {
+                let addr = ref_addr(&r);
+                if objects.contains_key(&addr) && !live.contains(&addr) {
+                    stack.push(addr);
+                }
+            });
+        }
+        // break the remaining cycles; their contents are dropped only once
+        // no borrows are held
+        let mut environments: Vec<Environment> = Vec::new();
+        let mut fields = Vec::new();
+        let mut lists: Vec<Vec<Literal>> = Vec::new();
+        let mut maps: Vec<Map<Literal>> = Vec::new();
+        for addr in order.iter().filter(|addr| !live.contains(addr)) {
+            match &objects[addr] {
+                Object::Environment(env) => {
+                    if let Ok(mut env) = env.try_borrow_mut() {
+                        environments.push(std::mem::take(&mut *env));
+                    }
+                }
+                Object::Instance(instance) => {
+                    if let Ok(mut instance) = instance.try_borrow_mut() {
+                        fields.push(instance.take_fields());
+                    }
+                }
+                Object::List(list) => {
+                    if let Ok(mut list) = list.try_borrow_mut() {
+                        lists.push(std::mem::take(&mut *list));
+                    }
+                }
+                Object::Map(map) => {
+                    if let Ok(mut map) = map.try_borrow_mut() {
+                        maps.push(std::mem::take(&mut *map));
+                    }
+                }
+                Object::Class(_) => {}
+            }
+        }
+        let freed = environments.len() + fields.len() + lists.len() + maps.len();
+        drop(objects);
+        drop(environments);
+        drop(fields);
+        drop(lists);
+        drop(maps);
+        self.roots.retain(Root::is_alive);
+        self.collections += 1;
+        self.collected += freed;
+        self.survivors = self.roots.len();
+        self.allocated = 0;
+        self.threshold = INITIAL_THRESHOLD.max(self.roots.len());
+        freed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interpreter::Interpreter;
+    use crate::runner::run;
+    use crate::syntax::token::{Instance, Literal};
+    use std::cell::RefCell;
+    use std::rc::{Rc, Weak};
+    // defines `watch(instance)`, which keeps a weak reference to its argument
+    fn watched(interpreter: &mut Interpreter) -> Rc<RefCell<Vec<Weak<RefCell<Instance>>>>> {
+        let watched = Rc::new(RefCell::new(Vec::new()));
+        let list = Rc::clone(&watched);
+        interpreter.define_native("watch", 1, move |_, args| {
+            if let Literal::Instance(instance) = &args[0] {
+                list.borrow_mut().push(Rc::downgrade(instance));
+            }
+            Ok(Literal::Nil)
+        });
+        watched
+    }
+    #[test]
+    fn test_instance_cycle() {
+        let mut interpreter = Interpreter::default();
+        let watched = watched(&mut interpreter);
+        let source = "class Node {} { var a = Node(); var b = Node(); a.next = b; b.next = a; watch(a); watch(b); }";
+        run(source, &mut interpreter).unwrap();
+        assert!(watched.borrow().iter().all(|w| w.upgrade().is_some()));
+        assert_eq!(interpreter.collect_garbage(), 2);
+        assert!(watched.borrow().iter().all(|w| w.upgrade().is_none()));
+        assert_eq!(interpreter.gc_stats().collected, 2);
+    }
+    #[test]
+    fn test_closure_cycle() {
+        let mut interpreter = Interpreter::default();
+        let watched = watched(&mut interpreter);
+        let source = "class Box {} { var box = Box(); watch(box); fun get() { return box; } box.get = get; }";
+        run(source, &mut interpreter).unwrap();
+        assert!(interpreter.collect_garbage() >= 2);
+        assert!(watched.borrow()[0].upgrade().is_none());
+    }
+    #[test]
+    fn test_reachable_cycle_survives() {
+        let mut interpreter = Interpreter::default();
+        let watched = watched(&mut interpreter);
+        let source = "class Node { init() { this.me = this; } } var a = Node(); watch(a);";
+        run(source, &mut interpreter).unwrap();
+        assert_eq!(interpreter.collect_garbage(), 0);
+        assert!(watched.borrow()[0].upgrade().is_some());
+        run("a.me.me.init();", &mut interpreter).unwrap();
+    }
+    #[test]
+    fn test_collection_cycle() {
+        let mut interpreter = Interpreter::default();
+        let watched = watched(&mut interpreter);
+        let source = "class Node {} { var n = Node(); watch(n); var xs = [n]; n.xs = xs; } { var ys = []; push(ys, ys); } { var m = {}; m[\"m\"] = m; }";
+        run(source, &mut interpreter).unwrap();
+        assert!(watched.borrow()[0].upgrade().is_some());
+        assert!(interpreter.collect_garbage() >= 4);
+        assert!(watched.borrow()[0].upgrade().is_none());
+    }
+    #[test]
+    fn test_automatic_collection() {
+        let mut interpreter = Interpreter::default();
+        let source =
+            "class Node {} for (var i = 0; i < 5000; i = i + 1) { var n = Node(); n.self = n; }";
+        run(source, &mut interpreter).unwrap();
+        let stats = interpreter.gc_stats();
+        assert!(stats.collections > 0);
+        assert!(stats.collected > 4000);
+        assert!(stats.tracked < 2000);
+    }
+    #[test]
+    fn test_acyclic_garbage_skips_collection() {
+        let mut interpreter = Interpreter::default();
+        let source = "class Node {} for (var i = 0; i < 5000; i = i + 1) { var n = Node(); n.next = Node(); }";
+        run(source, &mut interpreter).unwrap();
+        let stats = interpreter.gc_stats();
+        assert_eq!(stats.collections, 0);
+        assert!(stats.tracked < 2000);
+    }
+}
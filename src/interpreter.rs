@@ -1,51 +1,128 @@
 use rustc_hash::FxHashMap;
 
 use super::environment::{Environment, EnvironmentRef, Envt};
+use crate::builtins::{self, list_index};
 use crate::environment::EnvironmentError;
+use crate::gc::{GcStats, Heap};
+use crate::output::{output_error, Captured, Output};
 use crate::resolver::Resolvable;
 use crate::syntax::ast::*;
 use crate::syntax::token::*;
 use std::cell::RefCell;
+use std::fmt::{self, Write};
 use std::rc::Rc;
+use std::time::{Duration, Instant};
+/// Lox source run before any script. Runtime errors are caught as instances
+/// of `Error`, or of the subclass for their [`ErrorKind`], and scripts can
+/// throw or subclass them.
+pub(crate) const PRELUDE: &str = "class Error { init(message) { this.message = message; } }
+class ArithmeticError < Error {}
+class UndefinedPropertyError < Error {}
+class ArityError < Error {}";
+/// How deep Lox calls may nest before raising a stack overflow. The VM
+/// uses the same default.
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 1000;
+/// How much native stack Lox calls may use by default, in bytes. It leaves
+/// room to spare in the 2 MiB Rust gives spawned threads.
+pub const DEFAULT_STACK_LIMIT: usize = 1 << 20;
+/// Steps between looks at the clock while a deadline is set.
+const DEADLINE_CHECK_INTERVAL: u32 = 256;
+/// A call in progress: who was called, and from which line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CallFrame {
+    pub name: String,
+    /// Line of the call, or 0 for a call made from Rust.
+    pub line: usize,
+}
+impl fmt::Display for CallFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            0 => write!(f, "at {}", self.name),
+            line => write!(f, "at {} (line {})", self.name, line),
+        }
+    }
+}
 pub struct Interpreter {
     global: EnvironmentRef,
     environment: EnvironmentRef,
     locals: FxHashMap<*const Token, usize>,
+    heap: Heap,
+    frames: Vec<CallFrame>,
+    /// The frames active where the error being unwound was raised, taken
+    /// when it leaves its innermost call.
+    error_stack: Option<Vec<CallFrame>>,
+    /// The prelude's error classes, by [`ErrorKind`], kept even if a script
+    /// shadows the globals.
+    error_classes: [Class; 4],
+    max_call_depth: usize,
+    stack_limit: usize,
+    /// Native stack position where the outermost `interpret`, `eval` or
+    /// `call` started.
+    stack_base: Option<usize>,
+    /// Steps left before execution stops, if budgeted.
+    fuel: Option<u64>,
+    deadline: Option<Instant>,
+    // steps since the clock was last checked
+    ticks: u32,
+    output: Output,
 }
 impl Default for Interpreter {
     fn default() -> Self {
         let env = Rc::new(RefCell::new(Environment::new(None)));
-        let mut global = Rc::clone(&env);
-        global.define(
-            "clock".to_string(),
-            Literal::Callable(Function::Native(NativeFunc {
-                arity: 0,
-                func: || {
-                    let now = std::time::SystemTime::now();
-                    let duration = now.duration_since(std::time::UNIX_EPOCH).unwrap();
-                    Literal::Number(duration.as_secs_f64())
-                },
-                name: "clock".to_string(),
-            })),
-        );
-        Self {
+        let global = Rc::clone(&env);
+        let mut interpreter = Self {
             environment: env,
             global,
             locals: FxHashMap::default(),
+            heap: Heap::default(),
+            frames: Vec::new(),
+            error_stack: None,
+            // replaced by the prelude's class below
+            error_classes: ErrorKind::ALL
+                .map(|kind| Class::new(kind.class_name().to_owned(), FxHashMap::default(), None)),
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            stack_limit: DEFAULT_STACK_LIMIT,
+            stack_base: None,
+            fuel: None,
+            deadline: None,
+            ticks: 0,
+            output: Output::default(),
+        };
+        interpreter.define_native("clock", 0, |_, _| {
+            let now = std::time::SystemTime::now();
+            let duration = now.duration_since(std::time::UNIX_EPOCH).unwrap();
+            Ok(Literal::Number(duration.as_secs_f64()))
+        });
+        builtins::define(&mut interpreter);
+        let prelude = crate::runner::parse(PRELUDE).expect("the prelude parses");
+        interpreter.interpret(&prelude).expect("the prelude runs");
+        for kind in ErrorKind::ALL {
+            let Some(Literal::Callable(Function::Class(class))) =
+                interpreter.get_global(kind.class_name())
+            else {
+                unreachable!("the prelude defines {}", kind.class_name())
+            };
+            interpreter.error_classes[kind as usize] = class;
         }
+        interpreter
     }
 }
 trait RloxCallable {
     fn call(self, interpreter: &mut Interpreter, args: Vec<Literal>) -> VisitorResult<Literal>;
 
-    fn arity(&self) -> usize;
+    /// `None` if the callable accepts any number of arguments.
+    fn arity(&self) -> Option<usize>;
+
+    /// The name stack traces show for a call, or `None` for natives, which
+    /// don't appear in them.
+    fn frame_name(&self) -> Option<String>;
 }
 impl RloxCallable for Function {
-    fn arity(&self) -> usize {
+    fn arity(&self) -> Option<usize> {
         match self {
-            Function::Function(func) => func.params().len(),
+            Function::Function(func) => Some(func.params().len()),
             Function::Native(native) => native.arity,
-            Function::Class(class) => class.get_method("init").map_or(0, |e| {
+            Function::Class(class) => class.get_method("init").map_or(Some(0), |e| {
                 let Literal::Callable(ref f) = e else {
                     unreachable!()
                 };
@@ -53,6 +130,16 @@ impl RloxCallable for Function {
             }),
         }
     }
+    fn frame_name(&self) -> Option<String> {
+        match self {
+            Function::Function(func) if func.decl.is_anonymous() => {
+                Some("anonymous function".to_owned())
+            }
+            Function::Function(func) => Some(func.name().to_owned()),
+            Function::Native(_) => None,
+            Function::Class(class) => Some(class.to_string()),
+        }
+    }
     fn call(self, interpreter: &mut Interpreter, args: Vec<Literal>) -> VisitorResult<Literal> {
         match self {
             Function::Function(mut f) => {
@@ -67,12 +154,7 @@ impl RloxCallable for Function {
                                 .closure
                                 .get_at(
                                     0,
-                                    &Token {
-                                        token_type: TokenType::THIS,
-                                        lexeme: "this".to_owned(),
-                                        literal: None,
-                                        line: f.decl.name.line,
-                                    },
+                                    &Token::synthetic(TokenType::THIS, "this", f.decl.name.line),
                                 )
                                 .map_err(|e| e.into());
                         }
@@ -84,12 +166,7 @@ impl RloxCallable for Function {
                                 .closure
                                 .get_at(
                                     0,
-                                    &Token {
-                                        token_type: TokenType::THIS,
-                                        lexeme: "this".to_owned(),
-                                        literal: None,
-                                        line: f.decl.name.line,
-                                    },
+                                    &Token::synthetic(TokenType::THIS, "this", f.decl.name.line),
                                 )
                                 .map_err(|e| e.into());
                         }
@@ -98,9 +175,11 @@ impl RloxCallable for Function {
                     Err(e) => Err(e),
                 }
             }
-            Function::Native(native) => Ok((native.func)()),
+            Function::Native(native) => (native.func)(interpreter, &args),
             Function::Class(class) => {
                 let inner = Rc::new(RefCell::new(Instance::new(class)));
+                interpreter.heap.track_instance(&inner);
+                interpreter.collect_if_needed();
                 let instance = Literal::Instance(Rc::clone(&inner));
                 let ff = inner.borrow().class.get_method("init");
                 if let Some(Literal::Callable(Function::Function(mut init))) = ff {
@@ -111,25 +190,306 @@ impl RloxCallable for Function {
         }
     }
 }
+use crate::stack::stack_position;
 use crate::syntax::ast::{VisitorError, VisitorResult};
 impl Interpreter {
-    pub fn interpret(&mut self, stmts: &[Stmt]) {
-        for stmt in stmts {
-            if let Err(e) = self.execute(stmt) {
-                eprintln!("[Runtime Error] {e:#}");
-                break;
+    pub fn interpret(&mut self, stmts: &[Stmt]) -> VisitorResult<()> {
+        self.error_stack = None;
+        self.measure_stack(|interpreter| {
+            for stmt in stmts {
+                interpreter.execute(stmt)?;
+            }
+            Ok(())
+        })
+    }
+    /// Like [`Interpreter::interpret`], but evaluates a trailing expression
+    /// statement and returns its value. Returns nil otherwise.
+    pub fn eval(&mut self, stmts: &[Stmt]) -> VisitorResult<Literal> {
+        match stmts.split_last() {
+            Some((Stmt::Expression(expr), rest)) => {
+                self.interpret(rest)?;
+                self.measure_stack(|interpreter| interpreter.evaluate(expr))
+            }
+            _ => self.interpret(stmts).map(|_| Literal::Nil),
+        }
+    }
+    pub fn get_global(&self, name: &str) -> Option<Literal> {
+        self.global
+            .get(&Token::synthetic(TokenType::IDENTIFIER, name, 0))
+            .ok()
+    }
+    /// Every global variable, sorted by name.
+    pub fn globals(&self) -> Vec<(String, Literal)> {
+        let mut globals: Vec<_> = self
+            .global
+            .borrow()
+            .bindings()
+            .map(|(name, value)| (name.to_owned(), value.clone()))
+            .collect();
+        globals.sort_by(|a, b| a.0.cmp(&b.0));
+        globals
+    }
+    pub fn define_global(&mut self, name: &str, value: Literal) {
+        self.global.define(name.to_owned(), value);
+    }
+    /// Calls a Lox value from Rust, checking it is callable with `args`.
+    pub fn call(&mut self, callee: Literal, args: Vec<Literal>) -> VisitorResult<Literal> {
+        self.error_stack = None;
+        self.measure_stack(|interpreter| {
+            interpreter.call_value(
+                callee,
+                args,
+                &Token::synthetic(TokenType::RIGHT_PAREN, ")", 0),
+            )
+        })
+    }
+    /// Limits how deep Lox calls may nest. Deeper calls raise a "Stack
+    /// overflow." runtime error.
+    pub fn set_max_call_depth(&mut self, depth: usize) {
+        self.max_call_depth = depth;
+    }
+    /// Limits how many bytes of native stack Lox calls and deeply nested
+    /// code may use, whatever the call depth. Raise it when running on a
+    /// thread with a bigger stack.
+    pub fn set_stack_limit(&mut self, bytes: usize) {
+        self.stack_limit = bytes;
+    }
+    pub fn stack_limit(&self) -> usize {
+        self.stack_limit
+    }
+    /// Sends the text of `print` statements to `output` instead of stdout.
+    pub fn set_output(&mut self, output: Output) {
+        self.output = output;
+    }
+    /// Collects printed text instead of writing it to stdout.
+    pub fn capture_output(&mut self) -> Captured {
+        let (output, captured) = Output::capture();
+        self.output = output;
+        captured
+    }
+    /// Limits execution to `fuel` more steps, or lifts the limit with
+    /// `None`. Every loop iteration and every call is a step. Running out
+    /// stops the script with [`VisitorError::OutOfFuel`], which Lox code
+    /// can't catch; set or add fuel to run more code afterwards.
+    pub fn set_fuel(&mut self, fuel: Option<u64>) {
+        self.fuel = fuel;
+    }
+    /// Adds to the step budget, if there is one.
+    pub fn add_fuel(&mut self, fuel: u64) {
+        if let Some(left) = &mut self.fuel {
+            *left = left.saturating_add(fuel);
+        }
+    }
+    /// Steps left in the budget, or `None` if there is no limit.
+    pub fn fuel(&self) -> Option<u64> {
+        self.fuel
+    }
+    /// Stops execution with [`VisitorError::Timeout`] once `deadline`
+    /// passes, or never with `None`. Like running out of fuel, a timeout
+    /// can't be caught by Lox code.
+    pub fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.deadline = deadline;
+        self.ticks = 0;
+    }
+    /// Sets a deadline `timeout` from now.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.set_deadline(Some(Instant::now() + timeout));
+    }
+    /// Charges one step against the budget and the deadline.
+    fn step(&mut self) -> VisitorResult<()> {
+        if let Some(fuel) = &mut self.fuel {
+            if *fuel == 0 {
+                return Err(VisitorError::OutOfFuel);
+            }
+            *fuel -= 1;
+        }
+        if let Some(deadline) = self.deadline {
+            self.ticks += 1;
+            if self.ticks >= DEADLINE_CHECK_INTERVAL {
+                self.ticks = 0;
+                if Instant::now() >= deadline {
+                    return Err(VisitorError::Timeout);
+                }
+            }
+        }
+        Ok(())
+    }
+    /// Runs `f` with native stack use measured from here, unless an outer
+    /// entry point already is.
+    fn measure_stack<T>(&mut self, f: impl FnOnce(&mut Self) -> T) -> T {
+        if self.stack_base.is_some() {
+            return f(self);
+        }
+        self.stack_base = Some(stack_position());
+        let result = f(self);
+        self.stack_base = None;
+        result
+    }
+    fn stack_exhausted(&self) -> bool {
+        self.frames.len() >= self.max_call_depth || self.native_stack_exhausted()
+    }
+    fn native_stack_exhausted(&self) -> bool {
+        self.stack_base
+            .is_some_and(|base| base.abs_diff(stack_position()) > self.stack_limit)
+    }
+    fn call_value(
+        &mut self,
+        callee: Literal,
+        arguments: Vec<Literal>,
+        paren: &Token,
+    ) -> VisitorResult<Literal> {
+        match callee {
+            Literal::Callable(callable) => {
+                if let Some(arity) = callable.arity() {
+                    if arguments.len() != arity {
+                        return Err(VisitorError::ArityNotMatched(
+                            arity,
+                            arguments.len(),
+                            Box::new(paren.clone()),
+                        ));
+                    }
+                }
+                if self.stack_exhausted() {
+                    return Err(VisitorError::StackOverflow(paren.line));
+                }
+                self.step()?;
+                let framed = match callable.frame_name() {
+                    Some(name) => {
+                        self.frames.push(CallFrame {
+                            name,
+                            line: paren.line,
+                        });
+                        true
+                    }
+                    None => false,
+                };
+                let result = callable.call(self, arguments);
+                if framed {
+                    if matches!(&result, Err(e) if e.is_catchable()) && self.error_stack.is_none() {
+                        self.error_stack = Some(self.frames.clone());
+                    }
+                    self.frames.pop();
+                }
+                result.map_err(|e| match e {
+                    VisitorError::RuntimeError(kind, message, None) => {
+                        VisitorError::RuntimeError(kind, message, Some(Box::new(paren.clone())))
+                    }
+                    // Nesting inside the body ran out of stack: blame the innermost call.
+                    VisitorError::StackOverflow(0) => VisitorError::StackOverflow(paren.line),
+                    e => e,
+                })
             }
+            _ => Err(VisitorError::NotCallable(Box::new(paren.clone()))),
         }
     }
+    /// Defines a global native function taking exactly `arity` arguments.
+    pub fn define_native(
+        &mut self,
+        name: &str,
+        arity: usize,
+        func: impl Fn(&mut Interpreter, &[Literal]) -> VisitorResult<Literal> + 'static,
+    ) {
+        let native = NativeFunc::new(name, arity, func);
+        self.global
+            .define(name.to_owned(), Literal::Callable(Function::Native(native)));
+    }
+    /// Defines a global native function taking any number of arguments.
+    pub fn define_variadic(
+        &mut self,
+        name: &str,
+        func: impl Fn(&mut Interpreter, &[Literal]) -> VisitorResult<Literal> + 'static,
+    ) {
+        let native = NativeFunc::variadic(name, func);
+        self.global
+            .define(name.to_owned(), Literal::Callable(Function::Native(native)));
+    }
+    /// Frees unreachable reference cycles now and returns how many objects
+    /// were freed. Collection also happens automatically as objects are
+    /// created.
+    pub fn collect_garbage(&mut self) -> usize {
+        self.heap.collect()
+    }
+    pub fn gc_stats(&self) -> GcStats {
+        self.heap.stats()
+    }
+    /// The calls in progress, outermost first. Natives can use it to see
+    /// who called them.
+    pub fn call_stack(&self) -> &[CallFrame] {
+        &self.frames
+    }
+    /// The calls in progress where the last uncaught runtime error was
+    /// raised, innermost first. Empty if it was raised outside any call.
+    pub fn stack_trace(&self) -> Vec<CallFrame> {
+        self.error_stack.iter().flatten().rev().cloned().collect()
+    }
+    /// Gives an `Error` instance the line it was raised at and the calls
+    /// active there, innermost first, unless it already has them.
+    fn locate_error(&mut self, error: &Rc<RefCell<Instance>>, line: usize, stack: &[CallFrame]) {
+        if error.borrow().field("line").is_some() {
+            return;
+        }
+        let stack = stack
+            .iter()
+            .rev()
+            .map(|frame| Literal::String(frame.to_string()))
+            .collect();
+        let stack = self.new_list(stack);
+        let mut error = error.borrow_mut();
+        error.set("line", Literal::Number(line as f64));
+        error.set("stack", stack);
+    }
+    /// The value a `catch` clause binds for `error`: the thrown value, or an
+    /// instance of the error class for a runtime error's kind.
+    fn caught_value(&mut self, error: VisitorError) -> VisitorResult<Literal> {
+        let stack = self.error_stack.take();
+        if let VisitorError::Thrown(value, _) = error {
+            return Ok(value);
+        }
+        let stack = stack.unwrap_or_else(|| self.frames.clone());
+        let instance = Function::Class(self.error_classes[error.kind() as usize].clone())
+            .call(self, vec![Literal::String(error.to_string())])?;
+        let Literal::Instance(inner) = &instance else {
+            unreachable!("classes construct instances")
+        };
+        self.locate_error(inner, error.line(), &stack);
+        Ok(instance)
+    }
+    /// Creates a list the cycle collector knows about.
+    pub(crate) fn new_list(&mut self, items: Vec<Literal>) -> Literal {
+        let list = Rc::new(RefCell::new(items));
+        self.heap.track_list(&list);
+        self.collect_if_needed();
+        Literal::List(list)
+    }
+    /// Creates a map the cycle collector knows about.
+    pub(crate) fn new_map(&mut self, map: Map<Literal>) -> Literal {
+        let map = Rc::new(RefCell::new(map));
+        self.heap.track_map(&map);
+        self.collect_if_needed();
+        Literal::Map(map)
+    }
+    fn collect_if_needed(&mut self) {
+        self.heap.collect_if_needed();
+    }
     pub fn resolve(&mut self, token: &Token, depth: usize) {
         self.locals.insert(token as _, depth);
     }
     fn evaluate(&mut self, expr: &Expr) -> VisitorResult<Literal> {
+        self.check_nesting()?;
         expr.accept(self)
     }
     fn execute(&mut self, stmt: &Stmt) -> VisitorResult<()> {
+        self.check_nesting()?;
         stmt.accept(self)
     }
+    // deeply nested code can use up the stack without making any calls
+    #[inline]
+    fn check_nesting(&self) -> VisitorResult<()> {
+        if self.native_stack_exhausted() {
+            return Err(VisitorError::StackOverflow(0));
+        }
+        Ok(())
+    }
     fn execute_block(&mut self, stmts: &[Stmt], block_env: Environment) -> VisitorResult<()> {
         let prev = Rc::clone(&self.environment);
 
@@ -166,19 +526,76 @@ impl Interpreter {
 }
 
 impl StmtVisitor for Interpreter {
-    fn visit_while(&mut self, cond: &Expr, body: &Stmt) -> VisitorResult<()> {
+    fn visit_while(
+        &mut self,
+        cond: &Expr,
+        body: &Stmt,
+        increment: Option<&Expr>,
+    ) -> VisitorResult<()> {
         while self.evaluate(cond)?.is_truthy() {
-            self.execute(body)?;
+            self.step()?;
+            match self.execute(body) {
+                Ok(_) | Err(VisitorError::Continue) => {}
+                Err(VisitorError::Break) => break,
+                Err(e) => return Err(e),
+            }
+            if let Some(increment) = increment {
+                self.evaluate(increment)?;
+            }
         }
         Ok(())
     }
+    fn visit_throw(&mut self, keyword: &Token, value: &Expr) -> VisitorResult<()> {
+        let value = self.evaluate(value)?;
+        if let Literal::Instance(instance) = &value {
+            if instance
+                .borrow()
+                .class
+                .descends_from(&self.error_classes[ErrorKind::Error as usize])
+            {
+                let stack = self.frames.clone();
+                self.locate_error(instance, keyword.line, &stack);
+            }
+        }
+        Err(VisitorError::Thrown(value, Box::new(keyword.clone())))
+    }
+    fn visit_try(&mut self, stmt: &TryStmt) -> VisitorResult<()> {
+        let mut result = self.visit_block(&stmt.body);
+        if let Some((name, body)) = &stmt.catch {
+            result = match result {
+                Err(e) if e.is_catchable() => {
+                    let value = self.caught_value(e)?;
+                    let mut catch_env = Environment::new(Some(Rc::clone(&self.environment)));
+                    catch_env.define(name.lexeme.clone(), value);
+                    self.execute_block(body, catch_env)
+                }
+                result => result,
+            };
+        }
+        // the host stopped the script, so none of it may run any more
+        if matches!(&result, Err(e) if e.is_interrupt()) {
+            return result;
+        }
+        if let Some(body) = &stmt.finally {
+            // an error or jump out of `finally` replaces the pending one
+            let error_stack = self.error_stack.take();
+            self.visit_block(body)?;
+            self.error_stack = error_stack;
+        }
+        result
+    }
+    fn visit_break(&mut self, _keyword: &Token) -> VisitorResult<()> {
+        Err(VisitorError::Break)
+    }
+    fn visit_continue(&mut self, _keyword: &Token) -> VisitorResult<()> {
+        Err(VisitorError::Continue)
+    }
     fn visit_expression(&mut self, expr: &Expr) -> VisitorResult<()> {
         self.evaluate(expr).map(|_| ())
     }
     fn visit_print(&mut self, expr: &Expr) -> VisitorResult<()> {
-        self.evaluate(expr).map(|res| {
-            println!("{}", res);
-        })
+        let value = self.evaluate(expr)?;
+        self.output.print(value).map_err(output_error)
     }
     fn visit_var(&mut self, token: &Token, expr: Option<&Expr>) -> VisitorResult<()> {
         let value = if let Some(expr) = expr {
@@ -201,23 +618,16 @@ impl StmtVisitor for Interpreter {
         }
         Ok(())
     }
-    fn visit_function(
-        &mut self,
-        name: &Token,
-        params: Rc<[Token]>,
-        body: Rc<[Stmt]>,
-    ) -> VisitorResult<()> {
+    fn visit_function(&mut self, func: &FnStmt) -> VisitorResult<()> {
+        self.heap.track_environment(&self.environment);
+        self.collect_if_needed();
         let new_func = Function::Function(Func {
-            decl: Rc::new(FnStmt {
-                name: name.clone(),
-                params,
-                body,
-            }),
+            decl: Rc::new(func.clone()),
             closure: Rc::clone(&self.environment),
             is_initializer: false,
         });
         self.environment
-            .define(name.lexeme.clone(), Literal::Callable(new_func));
+            .define(func.name.lexeme.clone(), Literal::Callable(new_func));
         Ok(())
     }
     fn visit_return(&mut self, _token: &Token, expr: Option<&Expr>) -> VisitorResult<()> {
@@ -243,11 +653,17 @@ impl StmtVisitor for Interpreter {
         };
         self.environment
             .define(class.name.lexeme.clone(), Literal::Nil);
-        if superclass.is_some() {
+        if let Some(superclass) = &superclass {
             self.environment = Rc::new(RefCell::new(Environment::new(Some(Rc::clone(
                 &self.environment,
             )))));
+            self.environment.define(
+                "super".to_owned(),
+                Literal::Callable(Function::Class(Class::clone(superclass))),
+            );
         }
+        self.heap.track_environment(&self.environment);
+        self.collect_if_needed();
         let mut method_table = FxHashMap::default();
         for method in class.methods.iter() {
             let is_initializer = method.name.lexeme == "init";
@@ -276,68 +692,73 @@ impl ExprVisitor for Interpreter {
     fn visit_binary(&mut self, token: &Token, e1: &Expr, e2: &Expr) -> VisitorResult<Literal> {
         let l = self.evaluate(e1)?;
         let r = self.evaluate(e2)?;
+        let operand_error =
+            || VisitorError::ArithmeticError(Box::new(token.spanning(e1.span().to(e2.span()))));
         match token.token_type {
             TokenType::PLUS => match (l, r) {
                 (Literal::Number(n1), Literal::Number(n2)) => Ok(Literal::Number(n1 + n2)),
                 _ => {
                     // error(token, "Operands must be two numbers");
-                    Err(VisitorError::ArithmeticError(token.clone()))
+                    Err(operand_error())
                 }
             },
             TokenType::MINUS => match (l, r) {
                 (Literal::Number(n1), Literal::Number(n2)) => Ok(Literal::Number(n1 - n2)),
                 _ => {
                     // error(token, "Operands must be two numbers");
-                    Err(VisitorError::ArithmeticError(token.clone()))
+                    Err(operand_error())
                 }
             },
             TokenType::STAR => match (l, r) {
                 (Literal::Number(n1), Literal::Number(n2)) => Ok(Literal::Number(n1 * n2)),
                 _ => {
                     // error(token, "Operands must be two numbers");
-                    Err(VisitorError::ArithmeticError(token.clone()))
+                    Err(operand_error())
                 }
             },
             TokenType::SLASH => match (l, r) {
                 (Literal::Number(n1), Literal::Number(n2)) => Ok(Literal::Number(n1 / n2)),
                 _ => {
                     // error(token, "Operands must be two numbers");
-                    Err(VisitorError::ArithmeticError(token.clone()))
+                    Err(operand_error())
                 }
             },
             TokenType::GREATER => match (l, r) {
                 (Literal::Number(n1), Literal::Number(n2)) => Ok(Literal::Boolean(n1 > n2)),
                 _ => {
                     // error(token, "Operands must be two numbers");
-                    Err(VisitorError::ArithmeticError(token.clone()))
+                    Err(operand_error())
                 }
             },
             TokenType::GREATER_EQUAL => match (l, r) {
                 (Literal::Number(n1), Literal::Number(n2)) => Ok(Literal::Boolean(n1 >= n2)),
                 _ => {
                     // error(token, "Operands must be two numbers");
-                    Err(VisitorError::ArithmeticError(token.clone()))
+                    Err(operand_error())
                 }
             },
             TokenType::LESS => match (l, r) {
                 (Literal::Number(n1), Literal::Number(n2)) => Ok(Literal::Boolean(n1 < n2)),
                 _ => {
                     // error(token, "Operands must be two numbers");
-                    Err(VisitorError::ArithmeticError(token.clone()))
+                    Err(operand_error())
                 }
             },
             TokenType::LESS_EQUAL => match (l, r) {
                 (Literal::Number(n1), Literal::Number(n2)) => Ok(Literal::Boolean(n1 <= n2)),
                 _ => {
                     // error(token, "Operands must be two numbers");
-                    Err(VisitorError::ArithmeticError(token.clone()))
+                    Err(operand_error())
                 }
             },
             TokenType::BANG_EQUAL => Ok(Literal::Boolean(l != r)),
             TokenType::EQUAL_EQUAL => Ok(Literal::Boolean(l == r)),
             _ => {
                 // error(token, "Unknown binary operator");
-                Err(VisitorError::UnknownOperator(token.clone(), "binary"))
+                Err(VisitorError::UnknownOperator(
+                    Box::new(token.clone()),
+                    "binary",
+                ))
             }
         }
     }
@@ -352,10 +773,15 @@ impl ExprVisitor for Interpreter {
         match token.token_type {
             TokenType::MINUS => match right {
                 Literal::Number(n) => Ok(Literal::Number(-n)),
-                _ => Err(VisitorError::VistorError),
+                _ => Err(VisitorError::UnaryTypeError(Box::new(
+                    token.spanning(token.span.to(expr.span())),
+                ))),
             },
             TokenType::BANG => Ok(Literal::Boolean(!right.is_truthy())),
-            _ => Err(VisitorError::UnknownOperator(token.clone(), "unary")),
+            _ => Err(VisitorError::UnknownOperator(
+                Box::new(token.clone()),
+                "unary",
+            )),
         }
     }
     fn visit_variable(&mut self, variable: &Variable) -> VisitorResult<Literal> {
@@ -404,7 +830,10 @@ impl ExprVisitor for Interpreter {
             }
             _ => {
                 // error(token, "Unknown logical operator");
-                return Err(VisitorError::UnknownOperator(token.clone(), "logical"));
+                return Err(VisitorError::UnknownOperator(
+                    Box::new(token.clone()),
+                    "logical",
+                ));
             }
         }
         self.evaluate(right)
@@ -415,33 +844,24 @@ impl ExprVisitor for Interpreter {
         paren: &Token,
         args: &[Expr],
     ) -> VisitorResult<Literal> {
+        let span = callee.span().to(paren.span);
         let callee = self.evaluate(callee)?;
         let mut arguments = Vec::new();
         for arg in args {
             arguments.push(self.evaluate(arg)?);
         }
-        match callee {
-            Literal::Callable(callable) => {
-                if arguments.len() != callable.arity() {
-                    return Err(VisitorError::ArityNotMatched(
-                        callable.arity(),
-                        arguments.len(),
-                        paren.clone(),
-                    ));
-                }
-                callable.call(self, arguments)
-            }
-            _ => Err(VisitorError::VistorError),
-        }
+        self.call_value(callee, arguments, &paren.spanning(span))
     }
     fn visit_get(&mut self, get: &Get) -> VisitorResult<Literal> {
         let x = self.evaluate(&get.object)?;
         if let Literal::Instance(instance) = x {
             Instance::get(&get.name, &instance).ok_or_else(|| {
-                VisitorError::UndefinedProperty(get.name.clone(), get.name.lexeme.clone())
+                VisitorError::UndefinedProperty(Box::new(get.name.clone()), get.name.lexeme.clone())
             })
         } else {
-            Err(VisitorError::VistorError)
+            Err(VisitorError::NotAnInstance(Box::new(
+                get.name.spanning(get.object.span().to(get.name.span)),
+            )))
         }
     }
     fn visitor_set(&mut self, set: &Set) -> VisitorResult<Literal> {
@@ -451,12 +871,97 @@ impl ExprVisitor for Interpreter {
             instance.borrow_mut().set(&set.name.lexeme, value.clone());
             Ok(value)
         } else {
-            Err(VisitorError::VistorError)
+            Err(VisitorError::NotAnInstance(Box::new(
+                set.name.spanning(set.object.span().to(set.name.span)),
+            )))
         }
     }
     fn visit_this(&mut self, token: &This) -> VisitorResult<Literal> {
         self.look_up_variable(token)
     }
+    fn visit_lambda(&mut self, func: &Rc<FnStmt>) -> VisitorResult<Literal> {
+        self.heap.track_environment(&self.environment);
+        self.collect_if_needed();
+        Ok(Literal::Callable(Function::Function(Func {
+            decl: Rc::clone(func),
+            closure: Rc::clone(&self.environment),
+            is_initializer: false,
+        })))
+    }
+    fn visit_list(&mut self, items: &[Expr]) -> VisitorResult<Literal> {
+        let items = items
+            .iter()
+            .map(|item| self.evaluate(item))
+            .collect::<VisitorResult<Vec<_>>>()?;
+        Ok(self.new_list(items))
+    }
+    fn visit_map(&mut self, entries: &[(Expr, Expr)], brace: &Token) -> VisitorResult<Literal> {
+        let mut map = Map::default();
+        for (key, value) in entries {
+            let k = MapKey::try_from(&self.evaluate(key)?).map_err(|message| {
+                VisitorError::RuntimeError(
+                    ErrorKind::Error,
+                    message,
+                    Some(Box::new(brace.spanning(key.span()))),
+                )
+            })?;
+            map.insert(k, self.evaluate(value)?);
+        }
+        Ok(self.new_map(map))
+    }
+    fn visit_index(&mut self, index: &Index) -> VisitorResult<Literal> {
+        let object = self.evaluate(&index.object)?;
+        let key = self.evaluate(&index.index)?;
+        let target = index
+            .bracket
+            .spanning(index.object.span().to(index.bracket.span));
+        let error = |message| {
+            VisitorError::RuntimeError(ErrorKind::Error, message, Some(Box::new(target.clone())))
+        };
+        match object {
+            Literal::List(list) => {
+                let list = list.borrow();
+                let at = list_index(key.as_number(), list.len()).map_err(error)?;
+                Ok(list[at].clone())
+            }
+            Literal::Map(map) => {
+                let key = MapKey::try_from(&key).map_err(error)?;
+                let value = map.borrow().get(&key).cloned();
+                value.ok_or_else(|| error(format!("Undefined key {}.", key)))
+            }
+            _ => Err(VisitorError::NotIndexable(Box::new(target))),
+        }
+    }
+    fn visit_set_index(&mut self, set: &SetIndex) -> VisitorResult<Literal> {
+        let object = self.evaluate(&set.object)?;
+        let key = self.evaluate(&set.index)?;
+        let value = self.evaluate(&set.value)?;
+        let target = set.bracket.spanning(set.object.span().to(set.bracket.span));
+        let error = |message| {
+            VisitorError::RuntimeError(ErrorKind::Error, message, Some(Box::new(target.clone())))
+        };
+        match object {
+            Literal::List(list) => {
+                let mut list = list.borrow_mut();
+                let at = list_index(key.as_number(), list.len()).map_err(error)?;
+                list[at] = value.clone();
+            }
+            Literal::Map(map) => {
+                let key = MapKey::try_from(&key).map_err(error)?;
+                map.borrow_mut().insert(key, value.clone());
+            }
+            _ => return Err(VisitorError::NotIndexable(Box::new(target))),
+        }
+        Ok(value)
+    }
+    fn visit_interpolation(&mut self, parts: &[Expr]) -> VisitorResult<Literal> {
+        let mut text = String::new();
+        for part in parts {
+            let value = self.evaluate(part)?;
+            write!(text, "{value}").expect("writing to a String can't fail");
+        }
+        Ok(Literal::String(text))
+    }
     fn visit_super(&mut self, s: &Super) -> VisitorResult<Literal> {
         // self.look_up_variable(s)
         let superclass = s.get_dist().map_or_else(
@@ -469,12 +974,7 @@ impl ExprVisitor for Interpreter {
         let dist = s.get_dist().unwrap(); //safe to unwrap
         let obj = self.environment.get_at(
             dist - 1,
-            &Token {
-                token_type: TokenType::THIS,
-                lexeme: "this".to_owned(),
-                literal: None,
-                line: s.name().line,
-            },
+            &Token::synthetic(TokenType::THIS, "this", s.name().line),
         )?;
         let Literal::Instance(instance) = obj else {
             unreachable!()
@@ -482,7 +982,7 @@ impl ExprVisitor for Interpreter {
         let Literal::Callable(Function::Function(mut method)) = superclass
             .get_method(s.method.lexeme.as_str())
             .ok_or_else(|| {
-                VisitorError::UndefinedProperty(s.method.clone(), s.method.lexeme.clone())
+                VisitorError::UndefinedProperty(Box::new(s.method.clone()), s.method.lexeme.clone())
             })?
         else {
             unreachable!()
@@ -519,7 +1019,8 @@ mod tests {
         print b;
         print c;"#,
             &mut interpreter,
-        );
+        )
+        .unwrap();
     }
     #[should_panic]
     #[test]
@@ -535,7 +1036,8 @@ mod tests {
             print a;
         ",
             &mut interpreter,
-        );
+        )
+        .unwrap();
     }
     #[test]
     fn test_while() {
@@ -549,7 +1051,8 @@ mod tests {
             }
         ",
             &mut interpreter,
-        );
+        )
+        .unwrap();
     }
     #[test]
     fn test_for() {
@@ -566,7 +1069,8 @@ mod tests {
             }
         ",
             &mut interpreter,
-        );
+        )
+        .unwrap();
     }
     #[test]
     fn test_native() {
@@ -578,7 +1082,36 @@ mod tests {
             print clock() - a;
         ",
             &mut interpreter,
+        )
+        .unwrap();
+    }
+    #[test]
+    fn test_native_args() {
+        let mut interpreter = Interpreter::default();
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        let record = Rc::clone(&seen);
+        interpreter.define_native("record", 1, move |_, args| {
+            record.borrow_mut().push(args[0].clone());
+            Ok(Literal::Nil)
+        });
+        interpreter.define_variadic("sum", |_, args| {
+            args.iter()
+                .try_fold(0.0, |acc, arg| match arg {
+                    Literal::Number(n) => Ok(acc + n),
+                    _ => Err(VisitorError::runtime("sum() takes numbers.")),
+                })
+                .map(Literal::Number)
+        });
+        run("record(sum()); record(sum(1, 2, 3));", &mut interpreter).unwrap();
+        assert_eq!(
+            *seen.borrow(),
+            vec![Literal::Number(0.0), Literal::Number(6.0)]
         );
+        let errors = run("sum(1, \"a\");", &mut interpreter).unwrap_err();
+        assert_eq!(errors[0].message, "sum() takes numbers.");
+        assert_eq!(errors[0].lexeme.as_deref(), Some(")"));
+        let errors = run("record();", &mut interpreter).unwrap_err();
+        assert_eq!(errors[0].message, "Expected 1 arguments but got 0.");
     }
     #[test]
     fn test_fib() {
@@ -600,7 +1133,8 @@ mod tests {
             print clock()-a;
         ",
             &mut interpreter,
-        );
+        )
+        .unwrap();
     }
     #[test]
     fn local_fun() {
@@ -622,7 +1156,8 @@ counter(); // "1".
 counter(); // "2".
         "#,
             &mut interpreter,
-        );
+        )
+        .unwrap();
     }
     #[test]
     fn local_fun2() {
@@ -639,7 +1174,8 @@ counter(); // "2".
           showA();
         }"#,
             &mut interpreter,
-        );
+        )
+        .unwrap();
     }
     #[test]
     fn test_rec() {
@@ -655,7 +1191,8 @@ counter(); // "2".
             }
         "#,
             &mut interpreter,
-        );
+        )
+        .unwrap();
     }
     #[test]
     fn test_fn() {
@@ -668,7 +1205,8 @@ counter(); // "2".
             foo();
         "#,
             &mut interpreter,
-        );
+        )
+        .unwrap();
     }
 
     #[test]
@@ -683,7 +1221,8 @@ counter(); // "2".
             }
         "#,
             &mut interpreter,
-        );
+        )
+        .unwrap();
     }
     #[test]
     #[should_panic]
@@ -698,7 +1237,8 @@ counter(); // "2".
             return 2;
         "#,
             &mut interpreter,
-        );
+        )
+        .unwrap();
     }
     #[test]
     fn test_class() {
@@ -728,7 +1268,8 @@ counter(); // "2".
             Bacon().eat(); // Prints "Crunch crunch crunch!".
             "#,
             &mut interpreter,
-        );
+        )
+        .unwrap();
     }
     #[test]
     fn test_this() {
@@ -745,7 +1286,8 @@ counter(); // "2".
             foo.bar();
             "#,
             &mut interpreter,
-        );
+        )
+        .unwrap();
     }
     #[test]
     fn test_init() {
@@ -765,7 +1307,8 @@ counter(); // "2".
             foo.bar();
             "#,
             &mut interpreter,
-        );
+        )
+        .unwrap();
     }
     #[test]
     #[should_panic]
@@ -786,7 +1329,8 @@ counter(); // "2".
             foo.bar();
             "#,
             &mut interpreter,
-        );
+        )
+        .unwrap();
     }
     #[test]
     fn test_init3() {
@@ -809,7 +1353,8 @@ counter(); // "2".
             print bar.x;
             "#,
             &mut interpreter,
-        );
+        )
+        .unwrap();
     }
     #[test]
     fn test_inherit() {
@@ -832,6 +1377,7 @@ counter(); // "2".
             A().bar();
             "#,
             &mut interpreter,
-        );
+        )
+        .unwrap();
     }
 }
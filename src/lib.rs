@@ -1,5 +1,18 @@
+mod builtins;
+pub mod compiler;
+pub mod diagnostic;
+pub mod engine;
 mod environment;
+pub mod gc;
 pub mod interpreter;
+pub mod output;
+pub mod repl;
 pub mod resolver;
 pub mod runner;
+mod stack;
 pub mod syntax;
+pub mod vm;
+pub use diagnostic::Diagnostic;
+pub use engine::Engine;
+pub use syntax::ast::{ErrorKind, VisitorError};
+pub use syntax::token::Literal;
@@ -1,13 +1,59 @@
+use rlox::interpreter::Interpreter;
 use rlox::runner;
+use rlox::vm::Vm;
 use std::env;
-fn main() {
-    let len = env::args().len();
-    match len {
-        1 => runner::run_prompt(),
-        2 => runner::run_file(&env::args().nth(1).unwrap()),
+use std::process::ExitCode;
+/// The CLI runs on a thread with this much stack so scripts can recurse as
+/// deep as the call limit allows, and nest deeper than most hosts permit.
+const STACK_SIZE: usize = 64 << 20;
+fn main() -> ExitCode {
+    let worker = std::thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(run)
+        .expect("failed to start the interpreter thread");
+    match worker.join() {
+        Ok(code) => ExitCode::from(code),
+        Err(panic) => std::panic::resume_unwind(panic),
+    }
+}
+/// Native stack the backends may use, keeping a margin for the frames
+/// below the first Lox call.
+const STACK_LIMIT: usize = STACK_SIZE - (4 << 20);
+fn interpreter() -> Interpreter {
+    let mut interpreter = Interpreter::default();
+    interpreter.set_stack_limit(STACK_LIMIT);
+    interpreter
+}
+fn vm() -> Vm {
+    let mut vm = Vm::default();
+    vm.set_stack_limit(STACK_LIMIT);
+    vm
+}
+fn run() -> u8 {
+    let mut args: Vec<String> = env::args().collect();
+    let use_vm = match args.iter().position(|arg| arg == "--vm") {
+        Some(idx) => {
+            args.remove(idx);
+            true
+        }
+        None => false,
+    };
+    let result = match (args.len(), use_vm) {
+        (1, false) => {
+            runner::run_prompt_with(interpreter);
+            Ok(())
+        }
+        (1, true) => {
+            runner::run_prompt_with(vm);
+            Ok(())
+        }
+        (2, false) => runner::run_file_with(&args[1], &mut interpreter()),
+        (2, true) => runner::run_file_with(&args[1], &mut vm()),
         _ => {
-            let prog = env::args().next().unwrap();
-            println!("{prog} [script]");
+            let prog = &args[0];
+            eprintln!("Usage: {prog} [--vm] [script]");
+            return runner::EX_USAGE;
         }
-    }
+    };
+    runner::finish(result)
 }
@@ -0,0 +1,100 @@
+//! Where `print` statements send their text.
+use crate::syntax::ast::VisitorError;
+use std::cell::RefCell;
+use std::fmt;
+use std::io::{self, Write};
+use std::rc::Rc;
+/// The sink both backends print to; stdout unless the host picks another.
+#[derive(Default)]
+pub enum Output {
+    #[default]
+    Stdout,
+    Writer(Box<dyn Write>),
+    /// Called once per `print` with the line, without its newline.
+    Callback(Box<dyn FnMut(&str)>),
+}
+impl Output {
+    pub fn writer(writer: impl Write + 'static) -> Self {
+        Output::Writer(Box::new(writer))
+    }
+    pub fn callback(callback: impl FnMut(&str) + 'static) -> Self {
+        Output::Callback(Box::new(callback))
+    }
+    /// An output that collects everything printed into the returned
+    /// [`Captured`].
+    pub fn capture() -> (Self, Captured) {
+        let captured = Captured::default();
+        let buffer = Rc::clone(&captured.0);
+        let output = Output::callback(move |line| {
+            let mut buffer = buffer.borrow_mut();
+            buffer.push_str(line);
+            buffer.push('\n');
+        });
+        (output, captured)
+    }
+    pub(crate) fn print(&mut self, value: impl fmt::Display) -> io::Result<()> {
+        match self {
+            Output::Stdout => writeln!(io::stdout().lock(), "{value}"),
+            Output::Writer(writer) => writeln!(writer, "{value}"),
+            Output::Callback(callback) => {
+                callback(&value.to_string());
+                Ok(())
+            }
+        }
+    }
+}
+impl fmt::Debug for Output {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Output::Stdout => write!(f, "Stdout"),
+            Output::Writer(_) => write!(f, "Writer"),
+            Output::Callback(_) => write!(f, "Callback"),
+        }
+    }
+}
+/// The runtime error for a `print` whose output couldn't be written.
+pub(crate) fn output_error(error: io::Error) -> VisitorError {
+    VisitorError::runtime(format!("Could not write output: {error}."))
+}
+/// Text printed to an [`Output::capture`] output so far.
+#[derive(Debug, Default, Clone)]
+pub struct Captured(Rc<RefCell<String>>);
+impl Captured {
+    pub fn text(&self) -> String {
+        self.0.borrow().clone()
+    }
+    /// Returns the text printed so far and clears it.
+    pub fn take(&self) -> String {
+        std::mem::take(&mut self.0.borrow_mut())
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_capture() {
+        let (mut output, captured) = Output::capture();
+        output.print(1).unwrap();
+        output.print("two").unwrap();
+        assert_eq!(captured.take(), "1\ntwo\n");
+        output.print(true).unwrap();
+        assert_eq!(captured.text(), "true\n");
+    }
+    #[test]
+    fn test_writer() {
+        #[derive(Clone, Default)]
+        struct Shared(Rc<RefCell<Vec<u8>>>);
+        impl Write for Shared {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.0.borrow_mut().write(buf)
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+        let shared = Shared::default();
+        let mut output = Output::writer(shared.clone());
+        output.print("hi").unwrap();
+        assert_eq!(shared.0.borrow().as_slice(), b"hi\n");
+    }
+}
@@ -0,0 +1,208 @@
+//! The interactive prompt.
+use crate::diagnostic::Diagnostic;
+use crate::runner::{self, Backend};
+use crate::syntax::token::TokenType;
+use crate::syntax::tokenizer::{Tokenizer, TokenizerError};
+use rustyline::error::ReadlineError;
+use rustyline::DefaultEditor;
+use std::path::PathBuf;
+use std::time::Instant;
+const PROMPT: &str = "> ";
+const CONTINUATION_PROMPT: &str = "... ";
+/// File in the home directory the prompt's history is kept in.
+const HISTORY_FILE: &str = ".rlox_history";
+const HELP: &str = "\
+:load FILE   run a script in this session
+:env         list the global variables
+:ast EXPR    show how an expression parses
+:tokens CODE list the tokens CODE scans to
+:time CODE   run CODE and report how long it took
+:reset       start over with a fresh session
+:quit        leave the prompt
+:help        show this list";
+/// What the prompt does after a command.
+enum Flow {
+    Continue,
+    Reset,
+    Quit,
+}
+/// Reads, evaluates and prints until the end of input or `:quit`. Input
+/// with unclosed brackets, strings or comments carries on over the next
+/// lines, and the value of a trailing expression statement is echoed. Lines
+/// starting with `:` are commands; `:reset` calls `new_backend` for a fresh
+/// session.
+pub fn run<B: Backend>(mut new_backend: impl FnMut() -> B) {
+    let mut editor = match DefaultEditor::new() {
+        Ok(editor) => editor,
+        Err(e) => {
+            eprintln!("Error starting the prompt: {e}");
+            return;
+        }
+    };
+    let history = history_path();
+    if let Some(path) = &history {
+        // there is nothing to load on the first run
+        let _ = editor.load_history(path);
+    }
+    let mut backend = new_backend();
+    let mut input = String::new();
+    loop {
+        let prompt = if input.is_empty() {
+            PROMPT
+        } else {
+            CONTINUATION_PROMPT
+        };
+        match editor.readline(prompt) {
+            Ok(line) => {
+                input.push_str(&line);
+                input.push('\n');
+                if !is_command(&input) && !is_complete(&input) {
+                    continue;
+                }
+                if !input.trim().is_empty() {
+                    let _ = editor.add_history_entry(input.trim_end());
+                }
+                let flow = match input.trim().strip_prefix(':') {
+                    Some(command) => run_command(command, &mut backend),
+                    None => {
+                        evaluate(&input, &mut backend);
+                        Flow::Continue
+                    }
+                };
+                input.clear();
+                match flow {
+                    Flow::Continue => {}
+                    Flow::Reset => backend = new_backend(),
+                    Flow::Quit => break,
+                }
+            }
+            // Ctrl-C abandons the input typed so far
+            Err(ReadlineError::Interrupted) => input.clear(),
+            Err(ReadlineError::Eof) => break,
+            Err(e) => {
+                eprintln!("Error reading input: {e}");
+                break;
+            }
+        }
+    }
+    if let Some(path) = &history {
+        if let Err(e) = editor.save_history(path) {
+            eprintln!("Could not save history to {}: {e}", path.display());
+        }
+    }
+}
+// commands only ever take one line
+fn is_command(input: &str) -> bool {
+    input.trim_start().starts_with(':')
+}
+fn run_command(command: &str, backend: &mut impl Backend) -> Flow {
+    let (name, arg) = command
+        .split_once(char::is_whitespace)
+        .map_or((command, ""), |(name, arg)| (name, arg.trim()));
+    match (name, arg) {
+        ("quit" | "q", _) => return Flow::Quit,
+        ("reset", _) => return Flow::Reset,
+        ("help", _) => println!("{HELP}"),
+        ("env", _) => {
+            for (name, value) in backend.globals() {
+                println!("{name} = {value}");
+            }
+        }
+        ("load" | "ast" | "tokens" | "time", "") => {
+            eprintln!(":{name} needs an argument; see :help")
+        }
+        ("load", path) => {
+            if let Err(diagnostics) = runner::run_file_with(path, backend) {
+                runner::report(&diagnostics);
+            }
+        }
+        ("ast", source) => match runner::parse_expression(source) {
+            Ok(expr) => println!("{expr}"),
+            Err(diagnostics) => runner::report(&diagnostics),
+        },
+        ("tokens", source) => {
+            let mut scanner = Tokenizer::new(source.to_owned());
+            let id = scanner.source_id();
+            let (tokens, errors) = scanner.scan_tokens();
+            for token in tokens {
+                println!("{token}");
+            }
+            let diagnostics: Vec<_> = errors
+                .iter()
+                .map(|e| Diagnostic::from(e).with_source(source, id))
+                .collect();
+            runner::report(&diagnostics);
+        }
+        ("time", source) => {
+            let start = Instant::now();
+            evaluate(source, backend);
+            println!("took {:?}", start.elapsed());
+        }
+        _ => eprintln!("Unknown command ':{name}'; see :help"),
+    }
+    Flow::Continue
+}
+fn evaluate(input: &str, backend: &mut impl Backend) {
+    match runner::eval(input, backend) {
+        Ok(Some(value)) => println!("{value}"),
+        Ok(None) => {}
+        Err(diagnostics) => runner::report(&diagnostics),
+    }
+}
+fn history_path() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(HISTORY_FILE))
+}
+/// Whether `source` has no brackets, strings or comments left open, so the
+/// prompt can run it rather than read another line. Input with other errors
+/// counts as complete so they get reported.
+pub fn is_complete(source: &str) -> bool {
+    let mut scanner = Tokenizer::new(source.to_owned());
+    let (tokens, errors) = scanner.scan_tokens();
+    if errors.iter().any(|e| {
+        matches!(
+            e,
+            TokenizerError::UnterminatedString(..) | TokenizerError::UnterminatedComment(..)
+        )
+    }) {
+        return false;
+    }
+    let mut depth = 0;
+    for token in tokens {
+        match token.token_type {
+            TokenType::LEFT_PAREN | TokenType::LEFT_BRACE | TokenType::LEFT_BRACKET => depth += 1,
+            TokenType::RIGHT_PAREN | TokenType::RIGHT_BRACE | TokenType::RIGHT_BRACKET => {
+                depth -= 1
+            }
+            // a string opening `${` stays open until the one after its `}`
+            // ends, which the scanner doesn't report when input runs out
+            TokenType::INTERPOLATION if token.lexeme.starts_with('"') => depth += 1,
+            TokenType::STRING if token.lexeme.starts_with('}') => depth -= 1,
+            _ => {}
+        }
+    }
+    depth <= 0
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_is_complete() {
+        assert!(is_complete("print 1;\n"));
+        assert!(is_complete(""));
+        assert!(!is_complete("fun f() {\n"));
+        assert!(!is_complete("fun f() {\n  print [1,\n"));
+        assert!(is_complete("fun f() {\n  print [1,\n 2];\n}\n"));
+        assert!(!is_complete("print \"two\nlines"));
+        assert!(is_complete("print \"two\nlines\";"));
+        assert!(!is_complete("print \"a ${"));
+        assert!(!is_complete("print \"a ${b} c ${\n"));
+        assert!(!is_complete("print \"a ${ \"${b}\""));
+        assert!(is_complete("print \"a ${b} c ${\"${d}\"}\";"));
+        assert!(!is_complete("/* a /* b */\n"));
+        assert!(is_complete("/* a /* b */ */\n"));
+        // brackets in strings and comments don't count
+        assert!(is_complete("print \"{\"; // (\n"));
+        // too many closers is an error for the parser to report
+        assert!(is_complete("print 1);\n"));
+    }
+}
@@ -1,30 +1,64 @@
 use super::syntax::{ast::*, token::*};
+use crate::interpreter::DEFAULT_STACK_LIMIT;
+use crate::stack::StackGuard;
 use rustc_hash::FxHashMap;
 use std::rc::Rc;
 pub struct Resolver {
     scopes: Vec<FxHashMap<String, bool>>,
     cur_func: FunctionType,
     cur_class: ClassType,
+    // number of loops around the current statement, within this function
+    loop_depth: usize,
+    stack_limit: usize,
+    stack: StackGuard,
+    // line of the last expression with a token, where running out of stack
+    // is reported
+    line: usize,
 }
 use thiserror::Error;
 #[derive(Error, Debug)]
 pub enum ResolverError {
-    #[error("line {}: ** Variable {} not initialized", .0.line, .0.lexeme)]
-    NotInitialized(Token),
-    #[error("line {}: {} ** Already a variable with this name in this scope.", .0.line, .0.lexeme)]
-    AlreadyDeclared(Token),
-    #[error("line {}: ** Can't return from top-level code.", .0.line)]
-    ReturnFromTopLevel(Token),
-    #[error("line {}: ** Can't use 'this' outside of a class.", .0.line)]
-    InvalidThis(Token),
-    #[error("line {0}: ** Can't return a value from an initializer.")]
+    #[error("Variable {} not initialized.", .0.lexeme)]
+    NotInitialized(Box<Token>),
+    #[error("Already a variable with this name in this scope.")]
+    AlreadyDeclared(Box<Token>),
+    #[error("Can't return from top-level code.")]
+    ReturnFromTopLevel(Box<Token>),
+    #[error("Can't use 'this' outside of a class.")]
+    InvalidThis(Box<Token>),
+    #[error("Can't return a value from an initializer.")]
     ReturnFromInitializer(usize),
-    #[error("line {}: {} ** A class can't inherit from itself.", .0.line, .0.lexeme)]
-    InheritFromSelf(Token),
-    #[error("line {}: ** Can't use 'super' outside of a class.", .0.line)]
-    InvalidSuper(Token),
-    #[error("line {}: ** Can't use 'super' in a class with no superclass.", .0.line)]
-    SuperclassNotInherited(Token),
+    #[error("A class can't inherit from itself.")]
+    InheritFromSelf(Box<Token>),
+    #[error("Can't use 'super' outside of a class.")]
+    InvalidSuper(Box<Token>),
+    #[error("Can't use 'super' in a class with no superclass.")]
+    SuperclassNotInherited(Box<Token>),
+    #[error("Can't use '{}' outside of a loop.", .0.lexeme)]
+    OutsideLoop(Box<Token>),
+    #[error("Code is nested too deeply.")]
+    TooDeep(usize),
+}
+impl ResolverError {
+    pub fn token(&self) -> Option<&Token> {
+        match self {
+            ResolverError::NotInitialized(token)
+            | ResolverError::AlreadyDeclared(token)
+            | ResolverError::ReturnFromTopLevel(token)
+            | ResolverError::InvalidThis(token)
+            | ResolverError::InheritFromSelf(token)
+            | ResolverError::InvalidSuper(token)
+            | ResolverError::SuperclassNotInherited(token)
+            | ResolverError::OutsideLoop(token) => Some(token),
+            ResolverError::ReturnFromInitializer(_) | ResolverError::TooDeep(_) => None,
+        }
+    }
+    pub fn line(&self) -> usize {
+        match self {
+            ResolverError::ReturnFromInitializer(line) | ResolverError::TooDeep(line) => *line,
+            _ => self.token().map_or(0, |token| token.line),
+        }
+    }
 }
 impl Default for Resolver {
     fn default() -> Self {
@@ -50,6 +84,34 @@ impl Resolver {
             scopes: vec![],
             cur_func: FunctionType::None,
             cur_class: ClassType::None,
+            loop_depth: 0,
+            stack_limit: DEFAULT_STACK_LIMIT,
+            stack: StackGuard::new(DEFAULT_STACK_LIMIT),
+            line: 0,
+        }
+    }
+    /// Limits how many bytes of native stack resolving nested code may use.
+    pub fn set_stack_limit(&mut self, bytes: usize) {
+        self.stack_limit = bytes;
+    }
+    /// Resolves each top-level statement on its own so that one error does
+    /// not hide the ones after it.
+    pub fn resolve_all(&mut self, stmts: &[Stmt]) -> Result<(), Vec<VisitorError>> {
+        self.stack = StackGuard::new(self.stack_limit);
+        let mut errors = vec![];
+        for stmt in stmts {
+            if let Err(e) = self.resolve_stmt(stmt) {
+                errors.push(e);
+                self.scopes.clear();
+                self.cur_func = FunctionType::None;
+                self.cur_class = ClassType::None;
+                self.loop_depth = 0;
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
         }
     }
     pub fn resolve(&mut self, stmts: &[Stmt]) -> VisitorResult<()> {
@@ -59,11 +121,22 @@ impl Resolver {
         Ok(())
     }
     fn resolve_stmt(&mut self, stmt: &Stmt) -> VisitorResult<()> {
+        self.check_depth()?;
         stmt.accept(self)
     }
     fn resolve_expr(&mut self, expr: &Expr) -> VisitorResult<()> {
+        if let Some(line) = expr.line() {
+            self.line = line;
+        }
+        self.check_depth()?;
         expr.accept(self).map(|_| ())
     }
+    fn check_depth(&self) -> VisitorResult<()> {
+        if self.stack.exhausted() {
+            return Err(ResolverError::TooDeep(self.line).into());
+        }
+        Ok(())
+    }
     fn resolve_local(&mut self, token: &impl Resolvable) -> VisitorResult<()> {
         for (i, scope) in self.scopes.iter().enumerate().rev() {
             if scope.contains_key(&token.name().lexeme) {
@@ -86,7 +159,7 @@ impl Resolver {
         let scope = self.scopes.last_mut().unwrap();
         match scope.entry(name.lexeme.clone()) {
             std::collections::hash_map::Entry::Occupied(_) => {
-                return Err(ResolverError::AlreadyDeclared(name.clone()));
+                return Err(ResolverError::AlreadyDeclared(Box::new(name.clone())));
             }
             std::collections::hash_map::Entry::Vacant(entry) => {
                 entry.insert(false);
@@ -109,7 +182,9 @@ impl Resolver {
         ftype: FunctionType,
     ) -> VisitorResult<()> {
         let prev = self.cur_func;
+        let loop_depth = self.loop_depth;
         self.cur_func = ftype;
+        self.loop_depth = 0;
         self.begin_scope();
         for param in params.iter() {
             self.declare(param)?;
@@ -118,6 +193,7 @@ impl Resolver {
         self.resolve(&body)?;
         self.end_scope();
         self.cur_func = prev;
+        self.loop_depth = loop_depth;
         Ok(())
     }
 }
@@ -131,18 +207,13 @@ impl StmtVisitor for Resolver {
     fn visit_expression(&mut self, expr: &Expr) -> VisitorResult<()> {
         self.resolve_expr(expr)
     }
-    fn visit_function(
-        &mut self,
-        name: &crate::syntax::token::Token,
-        params: Rc<[crate::syntax::token::Token]>,
-        body: Rc<[Stmt]>,
-    ) -> VisitorResult<()> {
-        self.declare(name)?;
-        self.define(name);
+    fn visit_function(&mut self, func: &FnStmt) -> VisitorResult<()> {
+        self.declare(&func.name)?;
+        self.define(&func.name);
         self.resolve_function(
-            name,
-            Rc::clone(&params),
-            Rc::clone(&body),
+            &func.name,
+            Rc::clone(&func.params),
+            Rc::clone(&func.body),
             FunctionType::Function,
         )
     }
@@ -163,7 +234,7 @@ impl StmtVisitor for Resolver {
         expr: Option<&Expr>,
     ) -> VisitorResult<()> {
         if self.cur_func == FunctionType::None {
-            return Err(ResolverError::ReturnFromTopLevel(ret.clone()).into());
+            return Err(ResolverError::ReturnFromTopLevel(Box::new(ret.clone())).into());
         }
         if let Some(expr) = expr {
             if self.cur_func == FunctionType::Initializer {
@@ -185,9 +256,46 @@ impl StmtVisitor for Resolver {
         self.define(token);
         Ok(())
     }
-    fn visit_while(&mut self, cond: &Expr, body: &Stmt) -> VisitorResult<()> {
+    fn visit_while(
+        &mut self,
+        cond: &Expr,
+        body: &Stmt,
+        increment: Option<&Expr>,
+    ) -> VisitorResult<()> {
         self.resolve_expr(cond)?;
+        self.loop_depth += 1;
         self.resolve_stmt(body)?;
+        self.loop_depth -= 1;
+        if let Some(increment) = increment {
+            self.resolve_expr(increment)?;
+        }
+        Ok(())
+    }
+    fn visit_break(&mut self, keyword: &Token) -> VisitorResult<()> {
+        if self.loop_depth == 0 {
+            return Err(ResolverError::OutsideLoop(Box::new(keyword.clone())).into());
+        }
+        Ok(())
+    }
+    fn visit_continue(&mut self, keyword: &Token) -> VisitorResult<()> {
+        self.visit_break(keyword)
+    }
+    fn visit_throw(&mut self, _keyword: &Token, value: &Expr) -> VisitorResult<()> {
+        self.resolve_expr(value)
+    }
+    fn visit_try(&mut self, stmt: &TryStmt) -> VisitorResult<()> {
+        self.visit_block(&stmt.body)?;
+        if let Some((name, body)) = &stmt.catch {
+            // the caught value lives in the same scope as the handler's body
+            self.begin_scope();
+            self.declare(name)?;
+            self.define(name);
+            self.resolve(body)?;
+            self.end_scope();
+        }
+        if let Some(body) = &stmt.finally {
+            self.visit_block(body)?;
+        }
         Ok(())
     }
     fn visit_class(&mut self, class: &ClassStmt) -> VisitorResult<()> {
@@ -200,7 +308,7 @@ impl StmtVisitor for Resolver {
                 unreachable!()
             };
             if variable.name.lexeme == class.name.lexeme {
-                return Err(ResolverError::InheritFromSelf(class.name.clone()).into());
+                return Err(ResolverError::InheritFromSelf(Box::new(class.name.clone())).into());
             }
             self.cur_class = ClassType::Superclass;
             self.resolve_expr(superclass)?;
@@ -277,9 +385,9 @@ impl ExprVisitor for Resolver {
                 .last()
                 .unwrap()
                 .get(&variable.name.lexeme)
-                .map_or(false, |v| !v)
+                .is_some_and(|v| !v)
         {
-            return Err(ResolverError::NotInitialized(variable.name.clone()).into());
+            return Err(ResolverError::NotInitialized(Box::new(variable.name.clone())).into());
         }
         self.resolve_local(variable)?;
         Ok(Literal::Nil)
@@ -295,18 +403,63 @@ impl ExprVisitor for Resolver {
     }
     fn visit_this(&mut self, this: &This) -> VisitorResult<Literal> {
         if self.cur_class == ClassType::None {
-            return Err(ResolverError::InvalidThis(this.token.clone()).into());
+            return Err(ResolverError::InvalidThis(Box::new(this.token.clone())).into());
         }
         self.resolve_local(this)?;
         Ok(Literal::Nil)
     }
+    fn visit_lambda(&mut self, func: &Rc<FnStmt>) -> VisitorResult<Literal> {
+        self.resolve_function(
+            &func.name,
+            Rc::clone(&func.params),
+            Rc::clone(&func.body),
+            FunctionType::Function,
+        )?;
+        Ok(Literal::Nil)
+    }
+    fn visit_list(&mut self, items: &[Expr]) -> VisitorResult<Literal> {
+        for item in items {
+            self.resolve_expr(item)?;
+        }
+        Ok(Literal::Nil)
+    }
+    fn visit_map(&mut self, entries: &[(Expr, Expr)], _brace: &Token) -> VisitorResult<Literal> {
+        for (key, value) in entries {
+            self.resolve_expr(key)?;
+            self.resolve_expr(value)?;
+        }
+        Ok(Literal::Nil)
+    }
+    fn visit_index(&mut self, index: &Index) -> VisitorResult<Literal> {
+        self.resolve_expr(&index.object)?;
+        self.resolve_expr(&index.index)?;
+        Ok(Literal::Nil)
+    }
+    fn visit_set_index(&mut self, set: &SetIndex) -> VisitorResult<Literal> {
+        self.resolve_expr(&set.object)?;
+        self.resolve_expr(&set.index)?;
+        self.resolve_expr(&set.value)?;
+        Ok(Literal::Nil)
+    }
+    fn visit_interpolation(&mut self, parts: &[Expr]) -> VisitorResult<Literal> {
+        for part in parts {
+            self.resolve_expr(part)?;
+        }
+        Ok(Literal::Nil)
+    }
     fn visit_super(&mut self, s: &Super) -> VisitorResult<Literal> {
         match self.cur_class {
-            ClassType::None => return Err(ResolverError::InvalidSuper(s.name().clone()).into()),
+            ClassType::None => {
+                return Err(ResolverError::InvalidSuper(Box::new(s.name().clone())).into())
+            }
             ClassType::Superclass => {
                 self.resolve_local(s)?;
             }
-            _ => return Err(ResolverError::SuperclassNotInherited(s.name().clone()).into()),
+            _ => {
+                return Err(
+                    ResolverError::SuperclassNotInherited(Box::new(s.name().clone())).into(),
+                )
+            }
         }
         Ok(Literal::Nil)
     }
@@ -1,48 +1,215 @@
-use super::interpreter::Interpreter;
+use super::diagnostic::{Diagnostic, Phase};
+use super::interpreter::{CallFrame, Interpreter, DEFAULT_STACK_LIMIT};
+use super::repl;
 use super::resolver::Resolver;
+use super::syntax::ast::{Expr, Stmt, VisitorResult};
+use super::syntax::token::SourceId;
 use super::syntax::{parser::Parser, tokenizer::Tokenizer};
+use super::vm::Vm;
+use std::fmt::Display;
 use std::fs;
-use std::io::{stdin, Write};
-pub fn run(content: &str, interpreter: &mut Interpreter) {
+/// The command line was used incorrectly.
+pub const EX_USAGE: u8 = 64;
+/// The script failed to scan, parse, resolve or compile.
+pub const EX_DATAERR: u8 = 65;
+/// The script failed at runtime.
+pub const EX_SOFTWARE: u8 = 70;
+/// The script couldn't be read.
+pub const EX_IOERR: u8 = 74;
+/// Something that can execute resolved statements: the tree-walking
+/// [`Interpreter`] or the bytecode [`Vm`].
+pub trait Backend {
+    fn interpret(&mut self, stmts: &[Stmt]) -> VisitorResult<()>;
+
+    /// Like [`Backend::interpret`], but returns the displayed value of a
+    /// trailing expression statement, or "nil" if there is none.
+    fn eval(&mut self, stmts: &[Stmt]) -> VisitorResult<String>;
+
+    /// The calls in progress where the last uncaught runtime error was
+    /// raised, innermost first.
+    fn stack_trace(&self) -> Vec<CallFrame>;
+
+    /// Every global variable and its displayed value, sorted by name.
+    fn globals(&self) -> Vec<(String, String)>;
+
+    /// The native stack, in bytes, parsing and running code may use.
+    fn stack_limit(&self) -> usize;
+}
+impl Backend for Interpreter {
+    fn interpret(&mut self, stmts: &[Stmt]) -> VisitorResult<()> {
+        Interpreter::interpret(self, stmts)
+    }
+    fn eval(&mut self, stmts: &[Stmt]) -> VisitorResult<String> {
+        Interpreter::eval(self, stmts).map(|value| value.to_string())
+    }
+    fn stack_trace(&self) -> Vec<CallFrame> {
+        Interpreter::stack_trace(self)
+    }
+    fn globals(&self) -> Vec<(String, String)> {
+        displayed(Interpreter::globals(self))
+    }
+    fn stack_limit(&self) -> usize {
+        Interpreter::stack_limit(self)
+    }
+}
+impl Backend for Vm {
+    fn interpret(&mut self, stmts: &[Stmt]) -> VisitorResult<()> {
+        Vm::interpret(self, stmts)
+    }
+    fn eval(&mut self, stmts: &[Stmt]) -> VisitorResult<String> {
+        Vm::eval(self, stmts).map(|value| value.to_string())
+    }
+    fn stack_trace(&self) -> Vec<CallFrame> {
+        Vm::stack_trace(self)
+    }
+    fn globals(&self) -> Vec<(String, String)> {
+        displayed(Vm::globals(self))
+    }
+    fn stack_limit(&self) -> usize {
+        Vm::stack_limit(self)
+    }
+}
+fn displayed<T: Display>(globals: Vec<(String, T)>) -> Vec<(String, String)> {
+    globals
+        .into_iter()
+        .map(|(name, value)| (name, value.to_string()))
+        .collect()
+}
+/// Runs `content` through every phase of the pipeline. Errors from any phase
+/// come back as diagnostics instead of being printed.
+pub fn run(content: &str, backend: &mut impl Backend) -> Result<(), Vec<Diagnostic>> {
+    let (stmts, id) = parse_source(content, backend.stack_limit())?;
+    backend.interpret(&stmts).map_err(|e| {
+        vec![Diagnostic::from(&e)
+            .with_source(content, id)
+            .with_trace(backend.stack_trace())]
+    })
+}
+/// Like [`run`], but returns the displayed value of `content` if it ends in
+/// an expression statement.
+pub fn eval(content: &str, backend: &mut impl Backend) -> Result<Option<String>, Vec<Diagnostic>> {
+    let (stmts, id) = parse_source(content, backend.stack_limit())?;
+    let result = match stmts.last() {
+        Some(Stmt::Expression(_)) => backend.eval(&stmts).map(Some),
+        _ => backend.interpret(&stmts).map(|_| None),
+    };
+    result.map_err(|e| {
+        vec![Diagnostic::from(&e)
+            .with_source(content, id)
+            .with_trace(backend.stack_trace())]
+    })
+}
+/// Scans, parses and resolves `content`, stopping short of running it.
+pub fn parse(content: &str) -> Result<Vec<Stmt>, Vec<Diagnostic>> {
+    parse_source(content, DEFAULT_STACK_LIMIT).map(|(stmts, _)| stmts)
+}
+/// Like [`parse`], with the native stack limited to `stack_limit` bytes, and
+/// also returning the id the spans in the statements carry, for attaching
+/// `content` to errors raised when they run.
+pub(crate) fn parse_source(
+    content: &str,
+    stack_limit: usize,
+) -> Result<(Vec<Stmt>, SourceId), Vec<Diagnostic>> {
     let mut scanner = Tokenizer::new(content.to_string());
-    let tokens = scanner.scan_tokens().unwrap();
+    let id = scanner.source_id();
+    let with_source = |d: Diagnostic| d.with_source(content, id);
+    let (tokens, scan_errors) = scanner.scan_tokens();
+    let mut diagnostics: Vec<_> = scan_errors
+        .iter()
+        .map(Diagnostic::from)
+        .map(with_source)
+        .collect();
+    // parse even when scanning failed so both phases report in one pass
     let mut parser = Parser::new(tokens);
-    let stmts = parser.parse().unwrap();
-    // let mut interpreter = Interpreter::default();
-    let mut stmts: Option<Vec<_>> = stmts.into_iter().collect();
-    if stmts.is_none() {
-        eprintln!("Error parsing");
-        return;
+    parser.set_stack_limit(stack_limit);
+    let stmts = match parser.parse() {
+        Ok(stmts) => stmts,
+        Err(errors) => {
+            diagnostics.extend(errors.iter().map(Diagnostic::from).map(with_source));
+            Vec::new()
+        }
+    };
+    if !diagnostics.is_empty() {
+        diagnostics.sort_by_key(|d| d.line);
+        return Err(diagnostics);
     }
     let mut resolver = Resolver::new();
-    resolver.resolve(stmts.as_mut().unwrap()).unwrap();
-    interpreter.interpret(stmts.as_mut().unwrap());
+    resolver.set_stack_limit(stack_limit);
+    resolver.resolve_all(&stmts).map_err(|errors| {
+        errors
+            .iter()
+            .map(Diagnostic::from)
+            .map(with_source)
+            .collect::<Vec<_>>()
+    })?;
+    Ok((stmts, id))
 }
-pub fn run_file(fname: &str) {
-    let mut interpreter = Interpreter::default();
-    let content = fs::read_to_string(fname).expect("File not found");
-    run(&content, &mut interpreter);
+/// Scans and parses `content` as a single expression.
+pub fn parse_expression(content: &str) -> Result<Expr, Vec<Diagnostic>> {
+    let mut scanner = Tokenizer::new(content.to_string());
+    let id = scanner.source_id();
+    let with_source = |d: Diagnostic| d.with_source(content, id);
+    let (tokens, scan_errors) = scanner.scan_tokens();
+    if !scan_errors.is_empty() {
+        return Err(scan_errors
+            .iter()
+            .map(Diagnostic::from)
+            .map(with_source)
+            .collect());
+    }
+    Parser::new(tokens)
+        .parse_expression()
+        .map_err(|e| vec![with_source(Diagnostic::from(&e))])
 }
-pub fn run_prompt() {
-    let mut input = String::new();
-    let mut interpreter = Interpreter::default();
-    loop {
-        print!("> ");
-        std::io::stdout().flush().unwrap();
-        match stdin().read_line(&mut input) {
-            Ok(0) => {
-                println!("EOF");
-                break;
-            }
-            Ok(_) => {
-                run(&input, &mut interpreter);
-            }
-            Err(_) => {
-                println!("Error reading input");
-                continue;
-            }
+/// Prints diagnostics to stderr.
+pub fn report(diagnostics: &[Diagnostic]) {
+    for diagnostic in diagnostics {
+        eprintln!("{diagnostic}");
+    }
+}
+/// Runs the script at `fname` on a fresh tree-walker, printing any
+/// diagnostics, and returns the process exit status for the run.
+pub fn run_file(fname: &str) -> u8 {
+    finish(run_file_with(fname, &mut Interpreter::default()))
+}
+/// Prints the diagnostics of a failed run and returns its exit status.
+pub fn finish(result: Result<(), Vec<Diagnostic>>) -> u8 {
+    match result {
+        Ok(()) => 0,
+        Err(diagnostics) => {
+            report(&diagnostics);
+            exit_code(&diagnostics)
         }
-
-        input.clear();
     }
 }
+/// Like [`run`], reading the script from `fname`. A file that can't be read
+/// is reported as a [`Phase::Reading`] diagnostic.
+pub fn run_file_with(fname: &str, backend: &mut impl Backend) -> Result<(), Vec<Diagnostic>> {
+    let content = fs::read_to_string(fname).map_err(|e| {
+        vec![Diagnostic::error(
+            Phase::Reading,
+            0,
+            format!("Could not read '{fname}': {e}."),
+        )]
+    })?;
+    run(&content, backend)
+}
+/// The process exit status for a run that failed with `diagnostics`,
+/// following the BSD `sysexits.h` codes.
+pub fn exit_code(diagnostics: &[Diagnostic]) -> u8 {
+    let phase = diagnostics.iter().find(|d| d.is_error()).map(|d| d.phase);
+    match phase {
+        None => 0,
+        Some(Phase::Reading) => EX_IOERR,
+        Some(Phase::Runtime | Phase::Interrupted) => EX_SOFTWARE,
+        Some(Phase::Scanning | Phase::Parsing | Phase::Resolving | Phase::Compiling) => EX_DATAERR,
+    }
+}
+pub fn run_prompt() {
+    repl::run(Interpreter::default);
+}
+/// Runs the prompt on backends made by `new_backend`, which `:reset` calls
+/// again for a fresh one.
+pub fn run_prompt_with<B: Backend>(new_backend: impl FnMut() -> B) {
+    repl::run(new_backend);
+}
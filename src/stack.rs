@@ -0,0 +1,33 @@
+//! Native stack measurement for the recursive passes over the syntax tree.
+//!
+//! The parser, resolver, compiler and tree-walker all recurse once per level
+//! of nesting in the script, so a pathological input like a few hundred
+//! thousand nested parentheses would overflow the Rust stack and abort the
+//! host. Each pass measures how far the stack has grown since it started and
+//! fails with an error instead. They share the limit set on the backend with
+//! `set_stack_limit`, as they run one after the other on the same thread.
+
+/// The address of a local, standing in for the native stack pointer.
+#[inline(always)]
+pub(crate) fn stack_position() -> usize {
+    let marker = 0u8;
+    std::hint::black_box(&marker) as *const u8 as usize
+}
+/// Native stack use since the guard was made, checked against a limit.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct StackGuard {
+    base: usize,
+    limit: usize,
+}
+impl StackGuard {
+    /// A guard allowing `limit` bytes from the caller's frame.
+    pub(crate) fn new(limit: usize) -> Self {
+        Self {
+            base: stack_position(),
+            limit,
+        }
+    }
+    pub(crate) fn exhausted(&self) -> bool {
+        self.base.abs_diff(stack_position()) > self.limit
+    }
+}
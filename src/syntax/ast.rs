@@ -1,4 +1,4 @@
-use super::token::{Literal, Token};
+use super::token::{Literal, Span, Token, TokenType};
 use std::cell::Cell;
 use std::fmt::{Display, Formatter};
 use std::rc::Rc;
@@ -7,8 +7,8 @@ use std::rc::Rc;
 pub enum Expr {
     Assign(Assign),
     Binary(Rc<Expr>, Token, Rc<Expr>),
-    Grouping(Rc<Expr>),
-    Literal(Literal),
+    Grouping(Rc<Expr>, Span),
+    Literal(Literal, Span),
     Unary(Token, Rc<Expr>),
     Variable(Variable),
     Logical(Rc<Expr>, Token, Rc<Expr>),
@@ -17,14 +17,23 @@ pub enum Expr {
     Set(Set),
     This(This),
     Super(Super),
+    Lambda(Rc<FnStmt>),
+    List(Rc<[Expr]>, Span),
+    /// Key-value pairs and the closing brace.
+    Map(Rc<[(Expr, Expr)]>, Token, Span),
+    Index(Index),
+    SetIndex(SetIndex),
+    /// The string segments and embedded expressions of an interpolated
+    /// string, in order.
+    Interpolation(Rc<[Expr]>, Span),
 }
 impl Display for Expr {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
-            Expr::Literal(ltr) => {
+            Expr::Literal(ltr, _) => {
                 write!(f, "{}", ltr)
             }
-            Expr::Grouping(expr) => {
+            Expr::Grouping(expr, _) => {
                 write!(f, "( group {})", expr)
             }
             Expr::Unary(tok, expr) => {
@@ -61,8 +70,83 @@ impl Display for Expr {
             Expr::Super(s) => {
                 write!(f, "super.{}", s.method.lexeme)
             }
+            Expr::Lambda(func) => {
+                write!(f, "{}", func)
+            }
+            Expr::List(items, _) => {
+                write!(f, "[")?;
+                for item in items.iter() {
+                    write!(f, "{},", item)?;
+                }
+                write!(f, "]")
+            }
+            Expr::Map(entries, _, _) => {
+                write!(f, "{{")?;
+                for (key, value) in entries.iter() {
+                    write!(f, "{}: {},", key, value)?;
+                }
+                write!(f, "}}")
+            }
+            Expr::Index(index) => {
+                write!(f, "{}", index)
+            }
+            Expr::SetIndex(set) => {
+                write!(f, "{}", set)
+            }
+            Expr::Interpolation(parts, _) => {
+                write!(f, "(str")?;
+                for part in parts.iter() {
+                    write!(f, " {}", part)?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+impl Expr {
+    /// The source range the whole expression was parsed from.
+    pub fn span(&self) -> Span {
+        match self {
+            Expr::Assign(assign) => assign.name.span.to(assign.value.span()),
+            Expr::Binary(left, _, right) | Expr::Logical(left, _, right) => {
+                left.span().to(right.span())
+            }
+            Expr::Grouping(_, span) | Expr::Literal(_, span) => *span,
+            Expr::Unary(token, expr) => token.span.to(expr.span()),
+            Expr::Variable(variable) => variable.name.span,
+            Expr::Call(callee, paren, _) => callee.span().to(paren.span),
+            Expr::Get(get) => get.object.span().to(get.name.span),
+            Expr::Set(set) => set.object.span().to(set.value.span()),
+            Expr::This(this) => this.token.span,
+            Expr::Super(s) => s.token.span.to(s.method.span),
+            Expr::Lambda(func) => func.span,
+            Expr::List(_, span) | Expr::Map(_, _, span) | Expr::Interpolation(_, span) => *span,
+            Expr::Index(index) => index.object.span().to(index.bracket.span),
+            Expr::SetIndex(set) => set.object.span().to(set.value.span()),
         }
     }
+    /// The line of the token the expression keeps, or `None` for groupings,
+    /// literals, lists and interpolations, which keep only a span.
+    pub fn line(&self) -> Option<usize> {
+        let token = match self {
+            Expr::Assign(assign) => &assign.name,
+            Expr::Binary(_, token, _) | Expr::Logical(_, token, _) | Expr::Unary(token, _) => token,
+            Expr::Variable(variable) => &variable.name,
+            Expr::Call(_, paren, _) => paren,
+            Expr::Get(get) => &get.name,
+            Expr::Set(set) => &set.name,
+            Expr::This(this) => &this.token,
+            Expr::Super(s) => &s.method,
+            Expr::Lambda(func) => &func.name,
+            Expr::Map(_, brace, _) => brace,
+            Expr::Index(index) => &index.bracket,
+            Expr::SetIndex(set) => &set.bracket,
+            Expr::Grouping(..) | Expr::Literal(..) | Expr::List(..) | Expr::Interpolation(..) => {
+                return None
+            }
+        };
+        Some(token.line)
+    }
 }
 #[derive(Debug, PartialEq)]
 #[non_exhaustive]
@@ -72,25 +156,82 @@ pub enum Stmt {
     Var(Token, Option<Expr>),
     Block(Vec<Stmt>),
     IfStmt(Expr, Rc<(Stmt, Option<Stmt>)>),
-    WhileStmt(Expr, Rc<Stmt>),
+    /// Condition, body, and the increment clause of a desugared `for`, which
+    /// runs after the body even when it `continue`s.
+    WhileStmt(Expr, Rc<Stmt>, Option<Expr>),
     Function(FnStmt), // name, params, body
     Return(Token, Option<Expr>),
     Class(ClassStmt),
+    Break(Token),
+    Continue(Token),
+    Throw(Token, Expr),
+    Try(TryStmt),
+}
+impl Stmt {
+    /// The source range covered by the statement's tokens, or `None` for an
+    /// empty block. Keywords and semicolons that aren't kept in the tree are
+    /// not included.
+    pub fn span(&self) -> Option<Span> {
+        let join = |a: Option<Span>, b: Option<Span>| match (a, b) {
+            (Some(a), Some(b)) => Some(a.to(b)),
+            (a, b) => a.or(b),
+        };
+        match self {
+            Stmt::Expression(expr) | Stmt::Print(expr) => Some(expr.span()),
+            Stmt::Var(name, init) => {
+                Some(init.as_ref().map_or(name.span, |e| name.span.to(e.span())))
+            }
+            Stmt::Block(stmts) => stmts.iter().map(Stmt::span).fold(None, join),
+            Stmt::IfStmt(cond, body) => {
+                let then_span = join(Some(cond.span()), body.0.span());
+                join(then_span, body.1.as_ref().and_then(Stmt::span))
+            }
+            Stmt::WhileStmt(cond, body, increment) => join(
+                join(Some(cond.span()), body.span()),
+                increment.as_ref().map(Expr::span),
+            ),
+            Stmt::Function(func) => Some(func.span),
+            Stmt::Return(keyword, value) => Some(
+                value
+                    .as_ref()
+                    .map_or(keyword.span, |e| keyword.span.to(e.span())),
+            ),
+            Stmt::Class(class) => Some(class.span),
+            Stmt::Break(keyword) | Stmt::Continue(keyword) => Some(keyword.span),
+            Stmt::Throw(keyword, value) => Some(keyword.span.to(value.span())),
+            Stmt::Try(stmt) => Some(stmt.span),
+        }
+    }
 }
 #[derive(PartialEq, Debug, Clone)]
 pub struct FnStmt {
+    /// The function's name, or the `fun` keyword of an anonymous function.
     pub name: Token,
     pub params: Rc<[Token]>,
     pub body: Rc<[Stmt]>,
+    /// From the name (or `fun` keyword) to the closing brace.
+    pub span: Span,
 }
 impl FnStmt {
-    pub fn new(name: Token, params: Rc<[Token]>, body: Rc<[Stmt]>) -> Self {
-        Self { name, params, body }
+    pub fn new(name: Token, params: Rc<[Token]>, body: Rc<[Stmt]>, span: Span) -> Self {
+        Self {
+            name,
+            params,
+            body,
+            span,
+        }
+    }
+    pub fn is_anonymous(&self) -> bool {
+        self.name.token_type == TokenType::FUN
     }
 }
 impl Display for FnStmt {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "fn {}(", self.name.lexeme)?;
+        if self.is_anonymous() {
+            write!(f, "fn (")?;
+        } else {
+            write!(f, "fn {}(", self.name.lexeme)?;
+        }
         for param in self.params.iter() {
             write!(f, "{},", param.lexeme)?;
         }
@@ -99,6 +240,31 @@ impl Display for FnStmt {
     }
 }
 #[derive(PartialEq, Debug)]
+pub struct TryStmt {
+    pub body: Vec<Stmt>,
+    /// The variable the caught value is bound to, and the handler.
+    pub catch: Option<(Token, Vec<Stmt>)>,
+    /// Shared so the compiler can copy it to every exit from the `try`.
+    pub finally: Option<Rc<[Stmt]>>,
+    /// From the `try` keyword to the last closing brace.
+    pub span: Span,
+}
+impl TryStmt {
+    pub fn new(
+        body: Vec<Stmt>,
+        catch: Option<(Token, Vec<Stmt>)>,
+        finally: Option<Rc<[Stmt]>>,
+        span: Span,
+    ) -> Self {
+        Self {
+            body,
+            catch,
+            finally,
+            span,
+        }
+    }
+}
+#[derive(PartialEq, Debug)]
 pub struct Variable {
     pub name: Token,
     pub dist: Cell<Option<usize>>,
@@ -156,6 +322,8 @@ pub struct ClassStmt {
     pub name: Token,
     pub methods: Rc<[FnStmt]>,
     pub superclass: Option<Expr>,
+    /// From the `class` keyword to the closing brace.
+    pub span: Span,
 }
 impl Display for ClassStmt {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
@@ -167,11 +335,12 @@ impl Display for ClassStmt {
     }
 }
 impl ClassStmt {
-    pub fn new(name: Token, methods: Rc<[FnStmt]>, superclass: Option<Expr>) -> Self {
+    pub fn new(name: Token, methods: Rc<[FnStmt]>, superclass: Option<Expr>, span: Span) -> Self {
         Self {
             name,
             methods,
             superclass,
+            span,
         }
     }
 }
@@ -202,6 +371,49 @@ impl Set {
         }
     }
 }
+/// `object[index]` on a list or map; `bracket` is the closing bracket.
+#[derive(Debug, PartialEq)]
+pub struct Index {
+    pub object: Rc<Expr>,
+    pub bracket: Token,
+    pub index: Rc<Expr>,
+}
+impl Display for Index {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}[{}]", self.object, self.index)
+    }
+}
+impl Index {
+    pub fn new(object: Rc<Expr>, bracket: Token, index: Rc<Expr>) -> Self {
+        Self {
+            object,
+            bracket,
+            index,
+        }
+    }
+}
+#[derive(Debug, PartialEq)]
+pub struct SetIndex {
+    pub object: Rc<Expr>,
+    pub bracket: Token,
+    pub index: Rc<Expr>,
+    pub value: Rc<Expr>,
+}
+impl Display for SetIndex {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}[{}] = {}", self.object, self.index, self.value)
+    }
+}
+impl SetIndex {
+    pub fn from_index(index: Index, value: Rc<Expr>) -> Self {
+        Self {
+            object: index.object,
+            bracket: index.bracket,
+            index: index.index,
+            value,
+        }
+    }
+}
 #[derive(Debug, PartialEq)]
 pub struct This {
     pub token: Token,
@@ -244,12 +456,16 @@ impl Stmt {
             Stmt::Var(token, expr) => visitor.visit_var(token, expr.as_ref()),
             Stmt::Block(stmts) => visitor.visit_block(stmts),
             Stmt::IfStmt(cond, body) => visitor.visit_if(cond, body),
-            Stmt::WhileStmt(cond, body) => visitor.visit_while(cond, body),
-            Stmt::Function(FnStmt { name, params, body }) => {
-                visitor.visit_function(name, Rc::clone(params), Rc::clone(body))
+            Stmt::WhileStmt(cond, body, increment) => {
+                visitor.visit_while(cond, body, increment.as_ref())
             }
+            Stmt::Function(func) => visitor.visit_function(func),
             Stmt::Return(token, expr) => visitor.visit_return(token, expr.as_ref()),
             Stmt::Class(class) => visitor.visit_class(class),
+            Stmt::Break(keyword) => visitor.visit_break(keyword),
+            Stmt::Continue(keyword) => visitor.visit_continue(keyword),
+            Stmt::Throw(keyword, value) => visitor.visit_throw(keyword, value),
+            Stmt::Try(stmt) => visitor.visit_try(stmt),
         }
     }
 }
@@ -257,8 +473,8 @@ impl Expr {
     pub fn accept(&self, visitor: &mut impl ExprVisitor) -> VisitorResult<Literal> {
         match self {
             Expr::Binary(e1, token, e2) => visitor.visit_binary(token, e1, e2),
-            Expr::Grouping(expr) => visitor.visit_grouping(expr),
-            Expr::Literal(ltr) => visitor.visit_literal(ltr),
+            Expr::Grouping(expr, _) => visitor.visit_grouping(expr),
+            Expr::Literal(ltr, _) => visitor.visit_literal(ltr),
             Expr::Unary(token, expr) => visitor.visit_unary(token, expr),
             Expr::Variable(variable) => visitor.visit_variable(variable),
             Expr::Assign(assign) => visitor.visit_assign(assign),
@@ -268,6 +484,12 @@ impl Expr {
             Expr::Set(set) => visitor.visitor_set(set),
             Expr::This(this) => visitor.visit_this(this),
             Expr::Super(s) => visitor.visit_super(s),
+            Expr::Lambda(func) => visitor.visit_lambda(func),
+            Expr::List(items, _) => visitor.visit_list(items),
+            Expr::Map(entries, brace, _) => visitor.visit_map(entries, brace),
+            Expr::Index(index) => visitor.visit_index(index),
+            Expr::SetIndex(set) => visitor.visit_set_index(set),
+            Expr::Interpolation(parts, _) => visitor.visit_interpolation(parts),
         }
     }
 }
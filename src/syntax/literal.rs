@@ -1,8 +1,11 @@
-use rustc_hash::FxHashMap;
+use indexmap::IndexMap;
+use rustc_hash::{FxBuildHasher, FxHashMap};
 
-use super::ast::{FnStmt, Stmt};
+use super::ast::{FnStmt, Stmt, VisitorResult};
 use super::token::Token;
 use crate::environment::{Environment, EnvironmentRef, Envt};
+use crate::gc::{GcRef, Trace};
+use crate::interpreter::Interpreter;
 use std::cell::RefCell;
 use std::fmt::{self, Display};
 use std::rc::Rc;
@@ -37,11 +40,51 @@ impl Func {
         }
     }
 }
-#[derive(Debug, Clone, PartialEq)]
+/// The Rust side of a native function. It gets the interpreter so it can
+/// call back into Lox, and may capture state of its own.
+pub type NativeFn = dyn Fn(&mut Interpreter, &[Literal]) -> VisitorResult<Literal>;
+#[derive(Clone)]
 pub struct NativeFunc {
     pub name: String,
-    pub func: fn() -> Literal,
-    pub arity: usize,
+    pub func: Rc<NativeFn>,
+    /// Number of arguments expected, or `None` for a variadic function.
+    pub arity: Option<usize>,
+}
+impl NativeFunc {
+    pub fn new(
+        name: &str,
+        arity: usize,
+        func: impl Fn(&mut Interpreter, &[Literal]) -> VisitorResult<Literal> + 'static,
+    ) -> Self {
+        Self {
+            name: name.to_owned(),
+            func: Rc::new(func),
+            arity: Some(arity),
+        }
+    }
+    pub fn variadic(
+        name: &str,
+        func: impl Fn(&mut Interpreter, &[Literal]) -> VisitorResult<Literal> + 'static,
+    ) -> Self {
+        Self {
+            name: name.to_owned(),
+            func: Rc::new(func),
+            arity: None,
+        }
+    }
+}
+impl PartialEq for NativeFunc {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name && Rc::ptr_eq(&self.func, &other.func)
+    }
+}
+impl fmt::Debug for NativeFunc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NativeFunc")
+            .field("name", &self.name)
+            .field("arity", &self.arity)
+            .finish_non_exhaustive()
+    }
 }
 #[derive(Clone, PartialEq)]
 pub enum Function {
@@ -52,6 +95,9 @@ pub enum Function {
 impl Function {
     fn display(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
+            Function::Function(func) if func.decl.is_anonymous() => {
+                write!(f, "anonymous function")
+            }
             Function::Function(func) => {
                 write!(f, "function {}", func.name())
             }
@@ -74,7 +120,67 @@ impl fmt::Debug for Function {
         self.display(f)
     }
 }
-#[derive(Debug, Clone, PartialEq)]
+/// A list shared by every value that refers to it.
+pub type ListRef = Rc<RefCell<Vec<Literal>>>;
+/// A map that iterates in insertion order. Overwriting a key keeps its
+/// place; deleting it and inserting it again moves it to the end.
+pub type Map<V> = IndexMap<MapKey, V, FxBuildHasher>;
+pub type MapRef = Rc<RefCell<Map<Literal>>>;
+/// A value that can key a map.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum MapKey {
+    Nil,
+    Boolean(bool),
+    /// The bits of a number, with `-0` stored as `0` so keys that compare
+    /// equal hash the same.
+    Number(u64),
+    String(String),
+}
+impl MapKey {
+    pub fn number(n: f64) -> Result<Self, String> {
+        if n.is_nan() {
+            return Err("Map keys can't be NaN.".to_owned());
+        }
+        Ok(MapKey::Number(if n == 0.0 { 0f64 } else { n }.to_bits()))
+    }
+    pub(crate) fn invalid() -> String {
+        "Map keys must be strings, numbers, booleans or nil.".to_owned()
+    }
+}
+/// Prints strings quoted, the way they appear inside a printed map.
+impl Display for MapKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MapKey::Nil => write!(f, "nil"),
+            MapKey::Boolean(b) => write!(f, "{}", b),
+            MapKey::Number(bits) => write!(f, "{:.}", f64::from_bits(*bits)),
+            MapKey::String(s) => write!(f, "\"{}\"", s),
+        }
+    }
+}
+impl TryFrom<&Literal> for MapKey {
+    type Error = String;
+    fn try_from(value: &Literal) -> Result<Self, String> {
+        match value {
+            Literal::Nil => Ok(MapKey::Nil),
+            Literal::Boolean(b) => Ok(MapKey::Boolean(*b)),
+            Literal::Number(n) => MapKey::number(*n),
+            Literal::String(s) => Ok(MapKey::String(s.clone())),
+            _ => Err(MapKey::invalid()),
+        }
+    }
+}
+impl From<&MapKey> for Literal {
+    fn from(key: &MapKey) -> Self {
+        match key {
+            MapKey::Nil => Literal::Nil,
+            MapKey::Boolean(b) => Literal::Boolean(*b),
+            MapKey::Number(bits) => Literal::Number(f64::from_bits(*bits)),
+            MapKey::String(s) => Literal::String(s.clone()),
+        }
+    }
+}
+#[derive(Debug, Clone)]
 pub enum Literal {
     Number(f64),
     String(String),
@@ -82,9 +188,29 @@ pub enum Literal {
     Callable(Function),
     Nil,
     Instance(Rc<RefCell<Instance>>),
+    List(ListRef),
+    Map(MapRef),
 }
-impl Display for Literal {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+impl PartialEq for Literal {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Literal::Number(a), Literal::Number(b)) => a == b,
+            (Literal::String(a), Literal::String(b)) => a == b,
+            (Literal::Boolean(a), Literal::Boolean(b)) => a == b,
+            (Literal::Callable(a), Literal::Callable(b)) => a == b,
+            (Literal::Nil, Literal::Nil) => true,
+            (Literal::Instance(a), Literal::Instance(b)) => a == b,
+            // lists are equal only to themselves
+            (Literal::List(a), Literal::List(b)) => Rc::ptr_eq(a, b),
+            (Literal::Map(a), Literal::Map(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+impl Literal {
+    // `seen` holds the collections being printed, so a list that contains
+    // itself prints as `[...]` instead of recursing forever
+    fn display(&self, f: &mut fmt::Formatter<'_>, seen: &mut Vec<*const ()>) -> fmt::Result {
         match self {
             Literal::Nil => write!(f, "nil"),
             Literal::Boolean(b) => write!(f, "{}", b),
@@ -92,10 +218,97 @@ impl Display for Literal {
             Literal::String(s) => write!(f, "{}", s),
             Literal::Callable(ff) => write!(f, "{}", ff),
             Literal::Instance(i) => write!(f, "{}", i.borrow()),
+            Literal::List(list) => {
+                let ptr = Rc::as_ptr(list) as *const ();
+                if seen.contains(&ptr) {
+                    return write!(f, "[...]");
+                }
+                seen.push(ptr);
+                write!(f, "[")?;
+                for (i, item) in list.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    match item {
+                        Literal::String(s) => write!(f, "\"{}\"", s)?,
+                        item => item.display(f, seen)?,
+                    }
+                }
+                seen.pop();
+                write!(f, "]")
+            }
+            Literal::Map(map) => {
+                let ptr = Rc::as_ptr(map) as *const ();
+                if seen.contains(&ptr) {
+                    return write!(f, "{{...}}");
+                }
+                seen.push(ptr);
+                write!(f, "{{")?;
+                for (i, (key, value)) in map.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: ", key)?;
+                    match value {
+                        Literal::String(s) => write!(f, "\"{}\"", s)?,
+                        value => value.display(f, seen)?,
+                    }
+                }
+                seen.pop();
+                write!(f, "}}")
+            }
+        }
+    }
+}
+impl Display for Literal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.display(f, &mut Vec::new())
+    }
+}
+impl Trace for Literal {
+    fn trace(&self, visit: &mut dyn FnMut(GcRef<'_>)) {
+        match self {
+            Literal::Callable(Function::Function(func)) => visit(GcRef::Environment(&func.closure)),
+            Literal::Callable(Function::Class(class)) => class.trace(visit),
+            Literal::Instance(instance) => visit(GcRef::Instance(instance)),
+            Literal::List(list) => visit(GcRef::List(list)),
+            Literal::Map(map) => visit(GcRef::Map(map)),
+            _ => {}
         }
     }
 }
+impl From<f64> for Literal {
+    fn from(n: f64) -> Self {
+        Literal::Number(n)
+    }
+}
+impl From<bool> for Literal {
+    fn from(b: bool) -> Self {
+        Literal::Boolean(b)
+    }
+}
+impl From<&str> for Literal {
+    fn from(s: &str) -> Self {
+        Literal::String(s.to_owned())
+    }
+}
+impl From<String> for Literal {
+    fn from(s: String) -> Self {
+        Literal::String(s)
+    }
+}
+impl From<Vec<Literal>> for Literal {
+    fn from(items: Vec<Literal>) -> Self {
+        Literal::List(Rc::new(RefCell::new(items)))
+    }
+}
 impl Literal {
+    pub fn as_number(&self) -> Option<f64> {
+        match self {
+            Literal::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
     pub fn is_truthy(&self) -> bool {
         match self {
             Literal::Nil => false,
@@ -132,6 +345,24 @@ impl Class {
     pub fn superclass(&self) -> Option<Rc<Class>> {
         self.superclass.clone()
     }
+    /// Whether this class is `ancestor` or inherits from it.
+    pub fn descends_from(&self, ancestor: &Class) -> bool {
+        self == ancestor
+            || self
+                .superclass
+                .as_ref()
+                .is_some_and(|superclass| superclass.descends_from(ancestor))
+    }
+}
+impl Trace for Class {
+    fn trace(&self, visit: &mut dyn FnMut(GcRef<'_>)) {
+        for method in self.methods.values() {
+            method.trace(visit);
+        }
+        if let Some(superclass) = &self.superclass {
+            visit(GcRef::Class(superclass));
+        }
+    }
 }
 impl Display for Class {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -167,9 +398,24 @@ impl Instance {
                 )))
             })
     }
+    /// The field called `name`, ignoring methods.
+    pub fn field(&self, name: &str) -> Option<&Literal> {
+        self.fields.get(name)
+    }
     pub fn set(&mut self, name: &str, value: Literal) {
         self.fields.insert(name.to_string(), value);
     }
+    pub(crate) fn take_fields(&mut self) -> FxHashMap<String, Literal> {
+        std::mem::take(&mut self.fields)
+    }
+}
+impl Trace for Instance {
+    fn trace(&self, visit: &mut dyn FnMut(GcRef<'_>)) {
+        for value in self.fields.values() {
+            value.trace(visit);
+        }
+        self.class.trace(visit);
+    }
 }
 impl Display for Instance {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -1,15 +1,24 @@
-use super::ast::{self, Assign, FnStmt, Get, Set, Super, This, Variable};
-use super::token::{Literal, Token, TokenType};
+use super::ast::{self, Assign, FnStmt, Get, Index, Set, SetIndex, Super, This, TryStmt, Variable};
+use super::token::{Literal, Span, Token, TokenType};
+use crate::interpreter::DEFAULT_STACK_LIMIT;
+use crate::stack::StackGuard;
 use std::rc::Rc;
 pub struct Parser<'a> {
     tokens: &'a [Token],
     current: usize,
+    errors: Vec<ParserError>,
+    stack_limit: usize,
+    stack: StackGuard,
+    // set once the input nests too deeply to parse, which ends the parse
+    abandoned: bool,
 }
 use thiserror::Error;
-#[derive(Debug, Error)]
-#[error("ParserError")]
-#[repr(transparent)]
-pub struct ParserError();
+#[derive(Debug, Error, Clone)]
+#[error("{message}")]
+pub struct ParserError {
+    pub token: Box<Token>,
+    pub message: String,
+}
 macro_rules! match_token {
     ($self:ident, [$($token:pat_param),*]) => {
         match_token!($self, $($token),*)
@@ -34,7 +43,18 @@ macro_rules! match_token {
 impl<'a> Parser<'a> {
     #[inline]
     pub fn new(tokens: &'a [Token]) -> Self {
-        Self { tokens, current: 0 }
+        Self {
+            tokens,
+            current: 0,
+            errors: vec![],
+            stack_limit: DEFAULT_STACK_LIMIT,
+            stack: StackGuard::new(DEFAULT_STACK_LIMIT),
+            abandoned: false,
+        }
+    }
+    /// Limits how many bytes of native stack parsing nested code may use.
+    pub fn set_stack_limit(&mut self, bytes: usize) {
+        self.stack_limit = bytes;
     }
     #[inline]
     fn peek(&self) -> &Token {
@@ -42,7 +62,7 @@ impl<'a> Parser<'a> {
     }
     #[inline]
     fn is_at_end(&self) -> bool {
-        return self.peek().token_type == TokenType::EOF;
+        self.peek().token_type == TokenType::EOF
     }
     #[inline]
     fn previous(&self) -> &Token {
@@ -60,23 +80,40 @@ impl<'a> Parser<'a> {
         if self.is_at_end() {
             return false;
         }
-        return self.peek().token_type == *ty;
+        self.peek().token_type == *ty
     }
     #[inline]
-    fn error(&self, t: &Token, msg: &str) {
-        if t.token_type == TokenType::EOF {
-            eprintln!("[line {}] Error at end: {}", t.line, msg);
-        } else {
-            eprintln!("[line {}] Error at '{}': {}", t.line, t.lexeme, msg);
+    fn check_next(&self, ty: &TokenType) -> bool {
+        self.tokens
+            .get(self.current + 1)
+            .is_some_and(|t| t.token_type == *ty)
+    }
+    #[inline]
+    fn error(&self, t: &Token, msg: &str) -> ParserError {
+        ParserError {
+            token: Box::new(t.clone()),
+            message: msg.to_owned(),
+        }
+    }
+    /// Fails once the nesting being parsed has used up the parser's share
+    /// of the native stack. The rest of the input is skipped: recovering
+    /// inside the nesting would report every level around it again.
+    fn check_depth(&mut self) -> Result<(), ParserError> {
+        if !self.stack.exhausted() {
+            return Ok(());
         }
+        let error = self.error(self.peek(), "code is nested too deeply");
+        self.errors.push(error.clone());
+        self.abandoned = true;
+        self.current = self.tokens.len() - 1;
+        Err(error)
     }
     #[inline]
     fn consume(&mut self, ty: TokenType, msg: &str) -> Result<Token, ParserError> {
         if self.check(&ty) {
             return Ok(self.advance().clone());
         }
-        self.error(self.peek(), msg);
-        Err(ParserError())
+        Err(self.error(self.peek(), msg))
     }
     fn synchronize(&mut self) {
         self.advance();
@@ -92,7 +129,9 @@ impl<'a> Parser<'a> {
                 | TokenType::IF
                 | TokenType::WHILE
                 | TokenType::PRINT
-                | TokenType::RETURN => {
+                | TokenType::RETURN
+                | TokenType::THROW
+                | TokenType::TRY => {
                     return;
                 }
                 _ => {}
@@ -100,23 +139,105 @@ impl<'a> Parser<'a> {
             self.advance();
         }
     }
+    /// Parses the rest of an interpolated string whose first
+    /// `INTERPOLATION` token was just matched.
+    fn interpolation(&mut self) -> Result<ast::Expr, ParserError> {
+        let start = self.previous().span;
+        let mut parts = vec![];
+        loop {
+            let segment = self.previous();
+            if let Some(Literal::String(text)) = &segment.literal {
+                if !text.is_empty() {
+                    parts.push(ast::Expr::Literal(
+                        Literal::String(text.clone()),
+                        segment.span,
+                    ));
+                }
+            }
+            if self.previous().token_type == TokenType::STRING {
+                let span = start.to(self.previous().span);
+                return Ok(ast::Expr::Interpolation(parts.into(), span));
+            }
+            // the rest of the string starts at the closing brace
+            let rest = matches!(
+                self.peek().token_type,
+                TokenType::INTERPOLATION | TokenType::STRING
+            ) && self.peek().lexeme.starts_with('}');
+            if rest {
+                return Err(self.error(self.peek(), "expected expression"));
+            }
+            parts.push(self.expression()?);
+            if !match_token!(self, [TokenType::INTERPOLATION, TokenType::STRING]) {
+                return Err(self.error(self.peek(), "expected '}' after interpolated expression"));
+            }
+        }
+    }
     fn primary(&mut self) -> Result<ast::Expr, ParserError> {
         if match_token!(self, [TokenType::FALSE]) {
-            return Ok(ast::Expr::Literal(Literal::Boolean(false)));
+            return Ok(ast::Expr::Literal(
+                Literal::Boolean(false),
+                self.previous().span,
+            ));
         }
         if match_token!(self, [TokenType::TRUE]) {
-            return Ok(ast::Expr::Literal(Literal::Boolean(true)));
+            return Ok(ast::Expr::Literal(
+                Literal::Boolean(true),
+                self.previous().span,
+            ));
         }
         if match_token!(self, [TokenType::NIL]) {
-            return Ok(ast::Expr::Literal(Literal::Nil));
+            return Ok(ast::Expr::Literal(Literal::Nil, self.previous().span));
         }
         if match_token!(self, [TokenType::NUMBER, TokenType::STRING]) {
-            return Ok(ast::Expr::Literal(self.previous().literal.clone().unwrap()));
+            let token = self.previous();
+            return Ok(ast::Expr::Literal(
+                token.literal.clone().unwrap(),
+                token.span,
+            ));
+        }
+        if match_token!(self, [TokenType::INTERPOLATION]) {
+            return self.interpolation();
         }
         if match_token!(self, [TokenType::LEFT_PAREN]) {
+            let start = self.previous().span;
             let expr = self.expression()?;
-            self.consume(TokenType::RIGHT_PAREN, "ecpected ')' after expression")?;
-            return Ok(ast::Expr::Grouping(Rc::new(expr)));
+            let end = self
+                .consume(TokenType::RIGHT_PAREN, "ecpected ')' after expression")?
+                .span;
+            return Ok(ast::Expr::Grouping(Rc::new(expr), start.to(end)));
+        }
+        if match_token!(self, [TokenType::LEFT_BRACKET]) {
+            let start = self.previous().span;
+            let mut items = vec![];
+            if !self.check(&TokenType::RIGHT_BRACKET) {
+                loop {
+                    items.push(self.expression()?);
+                    if !match_token!(self, [TokenType::COMMA]) {
+                        break;
+                    }
+                }
+            }
+            let end = self
+                .consume(TokenType::RIGHT_BRACKET, "expected ']' after list elements")?
+                .span;
+            return Ok(ast::Expr::List(items.into(), start.to(end)));
+        }
+        if match_token!(self, [TokenType::LEFT_BRACE]) {
+            let start = self.previous().span;
+            let mut entries = vec![];
+            if !self.check(&TokenType::RIGHT_BRACE) {
+                loop {
+                    let key = self.expression()?;
+                    self.consume(TokenType::COLON, "expected ':' after map key")?;
+                    entries.push((key, self.expression()?));
+                    if !match_token!(self, [TokenType::COMMA]) {
+                        break;
+                    }
+                }
+            }
+            let brace = self.consume(TokenType::RIGHT_BRACE, "expected '}' after map entries")?;
+            let span = start.to(brace.span);
+            return Ok(ast::Expr::Map(entries.into(), brace, span));
         }
         if match_token!(self, [TokenType::IDENTIFIER]) {
             return Ok(ast::Expr::Variable(Variable::new(self.previous().clone())));
@@ -124,16 +245,18 @@ impl<'a> Parser<'a> {
         if match_token!(self, [TokenType::THIS]) {
             return Ok(ast::Expr::This(This::new(self.previous().clone())));
         }
+        if match_token!(self, [TokenType::FUN]) {
+            let keyword = self.previous().clone();
+            let func = self.function_rest(keyword.clone(), "function", keyword.span)?;
+            return Ok(ast::Expr::Lambda(Rc::new(func)));
+        }
         if match_token!(self, [TokenType::SUPER]) {
+            let keyword = self.previous().clone();
             self.consume(TokenType::DOT, "expected '.' after 'super'")?;
             let method = self.consume(TokenType::IDENTIFIER, "expected superclass method name")?;
-            return Ok(ast::Expr::Super(Super::new(
-                self.previous().clone(),
-                method.clone(),
-            )));
+            return Ok(ast::Expr::Super(Super::new(keyword, method)));
         }
-        self.error(self.peek(), "expected expression");
-        Err(ParserError())
+        Err(self.error(self.peek(), "expected expression"))
     }
     #[inline]
     fn finish_call(&mut self, callee: ast::Expr) -> Result<ast::Expr, ParserError> {
@@ -141,8 +264,7 @@ impl<'a> Parser<'a> {
         if !self.check(&TokenType::RIGHT_PAREN) {
             loop {
                 if args.len() >= 255 {
-                    self.error(self.peek(), "Cannot have more than 255 arguments");
-                    return Err(ParserError());
+                    return Err(self.error(self.peek(), "Cannot have more than 255 arguments"));
                 }
                 args.push(self.expression()?);
                 if !match_token!(self, [TokenType::COMMA]) {
@@ -162,6 +284,10 @@ impl<'a> Parser<'a> {
                 let name =
                     self.consume(TokenType::IDENTIFIER, "expected property name after '.'")?;
                 expr = ast::Expr::Get(Get::new(Rc::new(expr), name.clone()));
+            } else if match_token!(self, [TokenType::LEFT_BRACKET]) {
+                let index = self.expression()?;
+                let bracket = self.consume(TokenType::RIGHT_BRACKET, "expected ']' after index")?;
+                expr = ast::Expr::Index(Index::new(Rc::new(expr), bracket, Rc::new(index)));
             } else {
                 break;
             }
@@ -169,6 +295,7 @@ impl<'a> Parser<'a> {
         Ok(expr)
     }
     fn unary(&mut self) -> Result<ast::Expr, ParserError> {
+        self.check_depth()?;
         if match_token!(self, [TokenType::BANG, TokenType::MINUS]) {
             let operator = self.previous().clone();
             let right = self.unary()?;
@@ -239,6 +366,7 @@ impl<'a> Parser<'a> {
         Ok(expr)
     }
     fn assignment(&mut self) -> Result<ast::Expr, ParserError> {
+        self.check_depth()?;
         let expr = self.or()?;
         if match_token!(self, [TokenType::EQUAL]) {
             let equals = self.previous().clone();
@@ -250,9 +378,14 @@ impl<'a> Parser<'a> {
                 ast::Expr::Get(get) => {
                     return Ok(ast::Expr::Set(Set::from_get(get, Rc::new(value))));
                 }
+                ast::Expr::Index(index) => {
+                    return Ok(ast::Expr::SetIndex(SetIndex::from_index(
+                        index,
+                        Rc::new(value),
+                    )));
+                }
                 _ => {
-                    self.error(&equals, "Invalid assignment target");
-                    return Err(ParserError());
+                    return Err(self.error(&equals, "Invalid assignment target"));
                 }
             }
         }
@@ -292,9 +425,10 @@ impl<'a> Parser<'a> {
         let cond = self.expression()?;
         self.consume(TokenType::RIGHT_PAREN, "expected ')' after condition")?;
         let body = self.statement()?;
-        Ok(ast::Stmt::WhileStmt(cond, Rc::new(body)))
+        Ok(ast::Stmt::WhileStmt(cond, Rc::new(body), None))
     }
     fn for_statement(&mut self) -> Result<ast::Stmt, ParserError> {
+        let keyword = self.previous().span;
         self.consume(TokenType::LEFT_PAREN, "expected '(' after 'for'")?;
         let initializer = if match_token!(self, [TokenType::SEMICOLON]) {
             None
@@ -306,7 +440,7 @@ impl<'a> Parser<'a> {
         let cond = if !self.check(&TokenType::SEMICOLON) {
             self.expression()?
         } else {
-            ast::Expr::Literal(Literal::Boolean(true))
+            ast::Expr::Literal(Literal::Boolean(true), keyword)
         };
         self.consume(TokenType::SEMICOLON, "expected ';' after loop condition")?;
         let increment = if !self.check(&TokenType::RIGHT_PAREN) {
@@ -316,13 +450,7 @@ impl<'a> Parser<'a> {
         };
         self.consume(TokenType::RIGHT_PAREN, "expected ')' after for clauses")?;
         let body = self.statement()?;
-        let mut block = if let Some(increment) = increment {
-            ast::Stmt::Block(vec![body, ast::Stmt::Expression(increment)])
-        } else {
-            ast::Stmt::Block(vec![body])
-        };
-
-        block = ast::Stmt::WhileStmt(cond, Rc::new(block));
+        let mut block = ast::Stmt::WhileStmt(cond, Rc::new(body), increment);
 
         block = if let Some(initializer) = initializer {
             ast::Stmt::Block(vec![initializer, block])
@@ -341,7 +469,33 @@ impl<'a> Parser<'a> {
         self.consume(TokenType::SEMICOLON, "expected ';' after return value")?;
         Ok(ast::Stmt::Return(keyword, value))
     }
+    fn try_statement(&mut self) -> Result<ast::Stmt, ParserError> {
+        let start = self.previous().span;
+        self.consume(TokenType::LEFT_BRACE, "expected '{' after 'try'")?;
+        let body = self.block()?;
+        let catch = if match_token!(self, [TokenType::CATCH]) {
+            self.consume(TokenType::LEFT_PAREN, "expected '(' after 'catch'")?;
+            let name = self.consume(TokenType::IDENTIFIER, "expected variable name")?;
+            self.consume(TokenType::RIGHT_PAREN, "expected ')' after catch variable")?;
+            self.consume(TokenType::LEFT_BRACE, "expected '{' before catch body")?;
+            Some((name, self.block()?))
+        } else {
+            None
+        };
+        let finally = if match_token!(self, [TokenType::FINALLY]) {
+            self.consume(TokenType::LEFT_BRACE, "expected '{' after 'finally'")?;
+            Some(Rc::from(self.block()?))
+        } else {
+            None
+        };
+        if catch.is_none() && finally.is_none() {
+            return Err(self.error(self.peek(), "expected 'catch' or 'finally' after try block"));
+        }
+        let span = start.to(self.previous().span);
+        Ok(ast::Stmt::Try(TryStmt::new(body, catch, finally, span)))
+    }
     fn statement(&mut self) -> Result<ast::Stmt, ParserError> {
+        self.check_depth()?;
         if match_token!(self, [TokenType::PRINT]) {
             return self.print_statement();
         }
@@ -360,6 +514,25 @@ impl<'a> Parser<'a> {
         if match_token!(self, [TokenType::RETURN]) {
             return self.return_statement();
         }
+        if match_token!(self, [TokenType::BREAK]) {
+            let keyword = self.previous().clone();
+            self.consume(TokenType::SEMICOLON, "expected ';' after 'break'")?;
+            return Ok(ast::Stmt::Break(keyword));
+        }
+        if match_token!(self, [TokenType::CONTINUE]) {
+            let keyword = self.previous().clone();
+            self.consume(TokenType::SEMICOLON, "expected ';' after 'continue'")?;
+            return Ok(ast::Stmt::Continue(keyword));
+        }
+        if match_token!(self, [TokenType::THROW]) {
+            let keyword = self.previous().clone();
+            let value = self.expression()?;
+            self.consume(TokenType::SEMICOLON, "expected ';' after thrown value")?;
+            return Ok(ast::Stmt::Throw(keyword, value));
+        }
+        if match_token!(self, [TokenType::TRY]) {
+            return self.try_statement();
+        }
         self.expression_statement()
     }
     fn var_declaration(&mut self) -> Result<ast::Stmt, ParserError> {
@@ -376,7 +549,24 @@ impl<'a> Parser<'a> {
         Ok(ast::Stmt::Var(name.clone(), initializer))
     }
     fn function(&mut self, kind: &str) -> Result<ast::Stmt, ParserError> {
+        // functions start at the `fun` keyword, methods at their name
+        let keyword = self.previous().clone();
         let name = self.consume(TokenType::IDENTIFIER, &format!("expected {} name", kind))?;
+        let start = if keyword.token_type == TokenType::FUN {
+            keyword.span
+        } else {
+            name.span
+        };
+        self.function_rest(name, kind, start)
+            .map(ast::Stmt::Function)
+    }
+    /// Parses a function's parameters and body, after its name.
+    fn function_rest(
+        &mut self,
+        name: Token,
+        kind: &str,
+        start: Span,
+    ) -> Result<FnStmt, ParserError> {
         self.consume(
             TokenType::LEFT_PAREN,
             &format!("expected '(' after {}", kind),
@@ -385,8 +575,7 @@ impl<'a> Parser<'a> {
         if !self.check(&TokenType::RIGHT_PAREN) {
             loop {
                 if params.len() >= 255 {
-                    self.error(self.peek(), "Cannot have more than 255 parameters");
-                    return Err(ParserError());
+                    return Err(self.error(self.peek(), "Cannot have more than 255 parameters"));
                 }
                 params.push(
                     self.consume(TokenType::IDENTIFIER, "expected parameter name")?
@@ -403,13 +592,15 @@ impl<'a> Parser<'a> {
             &format!("expected '{{' before {} body", kind),
         )?;
         let body = self.block()?;
-        Ok(ast::Stmt::Function(FnStmt::new(
-            name.clone(),
+        Ok(FnStmt::new(
+            name,
             params.into(),
             body.into(),
-        )))
+            start.to(self.previous().span),
+        ))
     }
     fn class_declaration(&mut self) -> Result<ast::Stmt, ParserError> {
+        let keyword = self.previous().span;
         let name = self.consume(TokenType::IDENTIFIER, "Expect class name")?;
         let mut superclass = None;
         if match_token!(self, TokenType::LESS) {
@@ -427,11 +618,14 @@ impl<'a> Parser<'a> {
             };
             methods.push(func);
         }
-        self.consume(TokenType::RIGHT_BRACE, "Expect '}' after class body")?;
+        let end = self
+            .consume(TokenType::RIGHT_BRACE, "Expect '}' after class body")?
+            .span;
         Ok(ast::Stmt::Class(ast::ClassStmt::new(
             name.clone(),
             methods.into(),
             superclass,
+            keyword.to(end),
         )))
     }
     fn declaration(&mut self) -> Option<ast::Stmt> {
@@ -445,7 +639,8 @@ impl<'a> Parser<'a> {
                 self.advance();
                 self.var_declaration()
             }
-            TokenType::FUN => {
+            // `fun (` starts an anonymous function expression instead
+            TokenType::FUN if self.check_next(&TokenType::IDENTIFIER) => {
                 self.advance();
                 self.function("function")
             }
@@ -457,7 +652,11 @@ impl<'a> Parser<'a> {
         };
         match res {
             Ok(stmt) => Some(stmt),
-            Err(_) => {
+            Err(e) => {
+                // the scanner has already reported its own error tokens
+                if e.token.token_type != TokenType::ERROR && !self.abandoned {
+                    self.errors.push(e);
+                }
                 self.synchronize();
                 None
             }
@@ -466,17 +665,40 @@ impl<'a> Parser<'a> {
     pub fn block(&mut self) -> Result<Vec<ast::Stmt>, ParserError> {
         let mut stmts = vec![];
         while !self.check(&TokenType::RIGHT_BRACE) && !self.is_at_end() {
-            stmts.push(self.declaration().unwrap());
+            // errors are recorded by declaration()
+            if let Some(stmt) = self.declaration() {
+                stmts.push(stmt);
+            }
         }
         self.consume(TokenType::RIGHT_BRACE, "expected '}' after block")?;
         Ok(stmts)
     }
-    pub fn parse(&mut self) -> Result<Vec<Option<ast::Stmt>>, ParserError> {
+    /// Parses the whole token stream as one expression, optionally followed
+    /// by a semicolon.
+    pub fn parse_expression(&mut self) -> Result<ast::Expr, ParserError> {
+        self.stack = StackGuard::new(self.stack_limit);
+        let expr = self.expression()?;
+        match_token!(self, TokenType::SEMICOLON);
+        if !self.is_at_end() {
+            return Err(self.error(self.peek(), "expected end of expression"));
+        }
+        Ok(expr)
+    }
+    /// Parses the whole token stream, recovering after each syntax error so
+    /// that every error in the input is reported at once.
+    pub fn parse(&mut self) -> Result<Vec<ast::Stmt>, Vec<ParserError>> {
+        self.stack = StackGuard::new(self.stack_limit);
         let mut stmts = vec![];
         while !self.is_at_end() {
-            stmts.push(self.declaration());
+            if let Some(stmt) = self.declaration() {
+                stmts.push(stmt);
+            }
+        }
+        if self.errors.is_empty() {
+            Ok(stmts)
+        } else {
+            Err(std::mem::take(&mut self.errors))
         }
-        Ok(stmts)
     }
 }
 #[cfg(test)]
@@ -487,11 +709,29 @@ mod tests {
     fn test_parse() {
         let content = "print true;";
         let mut scanner = super::super::tokenizer::Tokenizer::new(content.to_string());
-        let tokens = scanner.scan_tokens().unwrap();
+        let (tokens, _) = scanner.scan_tokens();
         let mut parser = Parser::new(tokens);
         let stmts = parser.parse().unwrap();
         let mut interpreter = Interpreter::default();
-        let mut stmts: Option<Vec<_>> = stmts.into_iter().collect();
-        interpreter.interpret(stmts.as_mut().unwrap());
+        interpreter.interpret(&stmts).unwrap();
+    }
+    #[test]
+    fn test_collect_errors() {
+        let content = "var = 1;\nprint 1 +;\n{ var a = ; }\nprint \"ok\";";
+        let mut scanner = super::super::tokenizer::Tokenizer::new(content.to_string());
+        let (tokens, _) = scanner.scan_tokens();
+        let errors = Parser::new(tokens).parse().unwrap_err();
+        let lines: Vec<_> = errors.iter().map(|e| e.token.line).collect();
+        assert_eq!(lines, vec![1, 2, 3]);
+    }
+    #[test]
+    fn test_skip_error_tokens() {
+        let content = "var a = @;\nprint 1 +;";
+        let mut scanner = super::super::tokenizer::Tokenizer::new(content.to_string());
+        let (tokens, scan_errors) = scanner.scan_tokens();
+        assert_eq!(scan_errors.len(), 1);
+        let errors = Parser::new(tokens).parse().unwrap_err();
+        let lines: Vec<_> = errors.iter().map(|e| e.token.line).collect();
+        assert_eq!(lines, vec![2]);
     }
 }
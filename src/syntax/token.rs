@@ -1,4 +1,5 @@
 use std::fmt::{self};
+use std::sync::atomic::{AtomicU32, Ordering};
 #[allow(non_camel_case_types)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum TokenType {
@@ -7,6 +8,9 @@ pub enum TokenType {
     RIGHT_PAREN,
     LEFT_BRACE,
     RIGHT_BRACE,
+    LEFT_BRACKET,
+    RIGHT_BRACKET,
+    COLON,
     COMMA,
     DOT,
     MINUS,
@@ -29,12 +33,20 @@ pub enum TokenType {
     IDENTIFIER,
     STRING,
     NUMBER,
+    /// The part of a string before a `${`. The interpolated expression's
+    /// tokens follow, then the rest of the string as another
+    /// `INTERPOLATION` or a `STRING`.
+    INTERPOLATION,
 
     // Keywords.
     AND,
+    BREAK,
+    CATCH,
     CLASS,
+    CONTINUE,
     ELSE,
     FALSE,
+    FINALLY,
     FUN,
     FOR,
     IF,
@@ -44,10 +56,14 @@ pub enum TokenType {
     RETURN,
     SUPER,
     THIS,
+    THROW,
     TRUE,
+    TRY,
     VAR,
     WHILE,
 
+    // Stands in for input the scanner could not tokenize.
+    ERROR,
     EOF,
 }
 use TokenType::*;
@@ -55,12 +71,79 @@ pub fn get_keywords(s: impl AsRef<str>) -> Option<TokenType> {
     get_keyword_impl(s.as_ref())
 }
 pub use super::literal::*;
+/// Identifies one text given to a [`Tokenizer`](super::tokenizer::Tokenizer),
+/// so a span is only ever applied to the source it came from. Functions
+/// defined by one REPL line or `eval` call can fail during a later one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SourceId(u32);
+impl SourceId {
+    /// A fresh id, different from every other one handed out.
+    pub fn next() -> Self {
+        static NEXT: AtomicU32 = AtomicU32::new(1);
+        SourceId(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+}
+/// A byte range `start..end` into the source text `source`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub source: SourceId,
+}
+impl Span {
+    /// A span in a source with no id of its own.
+    pub fn new(start: usize, end: usize) -> Self {
+        Self::in_source(start, end, SourceId::default())
+    }
+    pub fn in_source(start: usize, end: usize, source: SourceId) -> Self {
+        Self { start, end, source }
+    }
+    /// The smallest span covering both `self` and `other`, which must be in
+    /// the same source.
+    pub fn to(self, other: Span) -> Span {
+        Span::in_source(
+            self.start.min(other.start),
+            self.end.max(other.end),
+            self.source,
+        )
+    }
+}
 #[derive(Debug, Clone, PartialEq)]
 pub struct Token {
     pub token_type: TokenType,
     pub lexeme: String,
     pub literal: Option<Literal>,
     pub line: usize,
+    /// 1-based column of the first character, or 0 for tokens that were
+    /// made up by the interpreter rather than scanned.
+    pub column: usize,
+    pub span: Span,
+}
+impl Token {
+    /// A token with no position in the source, for errors raised on
+    /// values rather than syntax.
+    pub fn synthetic(token_type: TokenType, lexeme: &str, line: usize) -> Self {
+        Self {
+            token_type,
+            lexeme: lexeme.to_owned(),
+            literal: None,
+            line,
+            column: 0,
+            span: Span::default(),
+        }
+    }
+    /// Whether the token was scanned from source and so has a real span.
+    pub fn has_span(&self) -> bool {
+        self.column > 0
+    }
+    /// A copy of this token stretched over `span`, for errors that blame a
+    /// whole expression rather than just the token.
+    pub fn spanning(&self, span: Span) -> Self {
+        Self {
+            span,
+            ..self.clone()
+        }
+    }
 }
 impl fmt::Display for Token {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -84,9 +167,13 @@ macro_rules! define_keywords {
 }
 define_keywords!(
     "and"=>AND,
+    "break"=>BREAK,
+    "catch"=>CATCH,
     "class"=>CLASS,
+    "continue"=>CONTINUE,
     "else"=>ELSE,
     "false"=>FALSE,
+    "finally"=>FINALLY,
     "for"=>FOR,
     "fun"=>FUN,
     "if"=>IF,
@@ -96,7 +183,9 @@ define_keywords!(
     "return"=>RETURN,
     "super"=>SUPER,
     "this"=>THIS,
+    "throw"=>THROW,
     "true"=>TRUE,
+    "try"=>TRY,
     "var"=>VAR,
     "while"=>WHILE
 );
@@ -1,31 +1,87 @@
 use super::token;
 // use crate::Result;
 use thiserror::Error;
-use token::{Literal, Token};
+use token::{Literal, SourceId, Span, Token};
 #[derive(Error, Debug)]
 pub enum TokenizerError {
-    #[error("Error at line {0}")]
-    UnterminatedString(usize),
-    #[error("Unexpected character at line {0}")]
-    UnexpectedCharacter(usize),
+    #[error("Unterminated string.")]
+    UnterminatedString(usize, Span),
+    #[error("Unexpected character.")]
+    UnexpectedCharacter(usize, Span),
+    /// A `/*` comment that is never closed, located at its opening `/*`.
+    #[error("Unterminated comment.")]
+    UnterminatedComment(usize, Span),
+    /// A backslash in a string that doesn't start a known escape, located
+    /// at the backslash and what was read of the escape.
+    #[error("Invalid escape sequence.")]
+    InvalidEscape(usize, Span),
+    /// A number literal that doesn't follow the number syntax, located at
+    /// the whole literal, with what is wrong with it.
+    #[error("Malformed number: {2}.")]
+    MalformedNumber(usize, Span, &'static str),
+}
+impl TokenizerError {
+    pub fn line(&self) -> usize {
+        match self {
+            TokenizerError::UnterminatedString(line, _)
+            | TokenizerError::UnexpectedCharacter(line, _)
+            | TokenizerError::UnterminatedComment(line, _)
+            | TokenizerError::InvalidEscape(line, _)
+            | TokenizerError::MalformedNumber(line, _, _) => *line,
+        }
+    }
+    pub fn span(&self) -> Span {
+        match self {
+            TokenizerError::UnterminatedString(_, span)
+            | TokenizerError::UnexpectedCharacter(_, span)
+            | TokenizerError::UnterminatedComment(_, span)
+            | TokenizerError::InvalidEscape(_, span)
+            | TokenizerError::MalformedNumber(_, span, _) => *span,
+        }
+    }
 }
 pub struct Tokenizer {
     source: Vec<char>,
     tokens: Vec<Token>,
+    errors: Vec<TokenizerError>,
     start: usize,
     current: usize,
     line: usize,
+    // byte offsets of `start` and `current`
+    start_offset: usize,
+    offset: usize,
+    // index of the first character on the current line
+    line_start: usize,
+    start_column: usize,
+    // for each `${` still open, how many braces are open inside it
+    interpolations: Vec<usize>,
+    source_id: SourceId,
 }
 impl Tokenizer {
     pub fn new(source: String) -> Self {
         Self {
             source: source.chars().collect(),
             tokens: Vec::new(),
+            errors: Vec::new(),
             start: 0,
             current: 0,
             line: 1,
+            start_offset: 0,
+            offset: 0,
+            line_start: 0,
+            start_column: 1,
+            interpolations: Vec::new(),
+            source_id: SourceId::next(),
         }
     }
+    /// The id every span this tokenizer makes carries.
+    pub fn source_id(&self) -> SourceId {
+        self.source_id
+    }
+    // from byte offset `start` to the current one
+    fn span_from(&self, start: usize) -> Span {
+        Span::in_source(start, self.offset, self.source_id)
+    }
     fn _add_token(&mut self, ty: token::TokenType, literal: Option<Literal>) {
         let text = self.source[self.start..self.current].iter().collect();
         self.tokens.push(Token {
@@ -33,6 +89,8 @@ impl Tokenizer {
             lexeme: text,
             literal,
             line: self.line,
+            column: self.start_column,
+            span: self.span_from(self.start_offset),
         });
     }
     fn add_token(&mut self, ty: token::TokenType) {
@@ -46,63 +104,232 @@ impl Tokenizer {
             .unwrap_or(b'\0' as char)
             == expected;
         if f {
-            self.current += 1;
+            self.advance();
         }
         f
     }
+    fn newline(&mut self) {
+        self.line += 1;
+        self.line_start = self.current;
+    }
     fn peek(&self) -> char {
         self.source
             .get(self.current)
             .copied()
             .unwrap_or(b'\0' as char)
     }
+    /// Scans a string, or the rest of one after an interpolated expression,
+    /// up to its closing quote or next `${`.
     fn string(&mut self) -> Result<(), TokenizerError> {
+        let mut value = String::new();
+        // the string is scanned to its end even after a bad escape, which is
+        // then reported in place of the token
+        let mut invalid = None;
         while (self.peek() != '"') && !self.is_at_end() {
-            if self.peek() == '\n' {
-                self.line += 1;
+            if self.peek() == '$' && self.peek_next() == '{' {
+                self.advance();
+                self.advance();
+                self.interpolations.push(0);
+                if let Some(error) = invalid {
+                    return Err(error);
+                }
+                self._add_token(
+                    token::TokenType::INTERPOLATION,
+                    Some(Literal::String(value)),
+                );
+                return Ok(());
+            }
+            let (line, start) = (self.line, self.offset);
+            match self.advance() {
+                '\\' => match self.escape() {
+                    Some(c) => value.push(c),
+                    None => {
+                        let span = self.span_from(start);
+                        invalid.get_or_insert(TokenizerError::InvalidEscape(line, span));
+                    }
+                },
+                c => {
+                    if c == '\n' {
+                        self.newline();
+                    }
+                    value.push(c);
+                }
             }
-            self.advance();
         }
         if self.is_at_end() {
-            return Err(TokenizerError::UnterminatedString(self.line));
+            return Err(TokenizerError::UnterminatedString(
+                self.line,
+                self.span_from(self.start_offset),
+            ));
         }
         // closing
         self.advance();
-        let value = self.source[self.start + 1..self.current - 1]
-            .iter()
-            .collect();
+        if let Some(error) = invalid {
+            return Err(error);
+        }
         self._add_token(token::TokenType::STRING, Some(Literal::String(value)));
         Ok(())
     }
+    /// Reads the escape after a backslash and returns the character it
+    /// stands for, or `None` if it isn't valid.
+    fn escape(&mut self) -> Option<char> {
+        // a backslash can't escape the end of a line
+        if self.is_at_end() || self.peek() == '\n' {
+            return None;
+        }
+        match self.advance() {
+            'n' => Some('\n'),
+            't' => Some('\t'),
+            'r' => Some('\r'),
+            '0' => Some('\0'),
+            '\\' => Some('\\'),
+            '"' => Some('"'),
+            '$' => Some('$'),
+            'u' => self.unicode_escape(),
+            _ => None,
+        }
+    }
+    /// Reads the `{XXXX}` of a `\u{XXXX}` escape: one to six hex digits
+    /// naming a Unicode scalar value.
+    fn unicode_escape(&mut self) -> Option<char> {
+        if !self.peek_match('{') {
+            return None;
+        }
+        let mut digits = String::new();
+        while self.peek().is_ascii_hexdigit() && digits.len() < 6 {
+            digits.push(self.advance());
+        }
+        if !self.peek_match('}') || digits.is_empty() {
+            return None;
+        }
+        u32::from_str_radix(&digits, 16)
+            .ok()
+            .and_then(char::from_u32)
+    }
+    /// Skips the rest of a `/* */` comment whose `/*` was just consumed.
+    /// Comments nest, so each `/*` inside needs its own `*/`.
+    fn block_comment(&mut self) -> Result<(), TokenizerError> {
+        let line = self.line;
+        let opener = self.span_from(self.start_offset);
+        let mut depth = 1;
+        while depth > 0 {
+            if self.is_at_end() {
+                return Err(TokenizerError::UnterminatedComment(line, opener));
+            }
+            match self.advance() {
+                '/' if self.peek_match('*') => depth += 1,
+                '*' if self.peek_match('/') => depth -= 1,
+                '\n' => self.newline(),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
     fn peek_next(&self) -> char {
         self.source
             .get(self.current + 1)
             .copied()
             .unwrap_or(b'\0' as char)
     }
-    fn number(&mut self) {
-        while self.peek().is_ascii_digit() {
+    /// Scans a number whose first digit was just consumed: a decimal with
+    /// an optional fraction and exponent, or a `0x` hex or `0b` binary
+    /// integer. Digits may be separated by single underscores.
+    fn number(&mut self) -> Result<(), TokenizerError> {
+        let first = self.source[self.start];
+        let radix = match self.peek() {
+            'x' | 'X' if first == '0' => 16,
+            'b' | 'B' if first == '0' => 2,
+            _ => 10,
+        };
+        let value = if radix == 10 {
+            self.decimal()
+        } else {
             self.advance();
+            self.digits(radix).and_then(|digits| {
+                if digits.is_empty() {
+                    return Err(if radix == 16 {
+                        "expected hex digits after '0x'"
+                    } else {
+                        "expected binary digits after '0b'"
+                    });
+                }
+                // too long for a u64 is still a valid, if inexact, f64
+                Ok(digits
+                    .chars()
+                    .filter_map(|c| c.to_digit(radix))
+                    .fold(0.0, |value, digit| value * radix as f64 + digit as f64))
+            })
+        };
+        // `12ab` or `0b102` is one bad literal, not a number and a name
+        let value = value.and_then(|value| {
+            if is_identifier_continue(self.peek()) {
+                Err("unexpected character")
+            } else {
+                Ok(value)
+            }
+        });
+        match value {
+            Ok(value) => {
+                self._add_token(token::TokenType::NUMBER, Some(Literal::Number(value)));
+                Ok(())
+            }
+            Err(reason) => {
+                while is_identifier_continue(self.peek()) {
+                    self.advance();
+                }
+                Err(TokenizerError::MalformedNumber(
+                    self.line,
+                    self.span_from(self.start_offset),
+                    reason,
+                ))
+            }
         }
+    }
+    fn decimal(&mut self) -> Result<f64, &'static str> {
+        let mut text = self.source[self.start].to_string();
+        text.push_str(&self.digits(10)?);
+        // a `.` not followed by a digit is left for the parser to report
         if self.peek() == '.' && self.peek_next().is_ascii_digit() {
             self.advance();
-            while self.peek().is_ascii_digit() {
+            text.push('.');
+            text.push_str(&self.digits(10)?);
+        }
+        if matches!(self.peek(), 'e' | 'E') {
+            text.push(self.advance());
+            if matches!(self.peek(), '+' | '-') {
+                text.push(self.advance());
+            }
+            let exponent = self.digits(10)?;
+            if exponent.is_empty() {
+                return Err("expected digits in exponent");
+            }
+            text.push_str(&exponent);
+        }
+        text.parse().map_err(|_| "not a valid number")
+    }
+    /// Consumes a run of digits in `radix`, which may be separated by
+    /// single underscores, and returns the digits without them.
+    fn digits(&mut self, radix: u32) -> Result<String, &'static str> {
+        let mut digits = String::new();
+        loop {
+            let c = self.peek();
+            if c.is_digit(radix) {
+                digits.push(self.advance());
+            } else if c == '_' {
                 self.advance();
+                let after_digit = self.source[self.current - 2].is_digit(radix);
+                if !after_digit || !self.peek().is_digit(radix) {
+                    return Err("'_' must be between digits");
+                }
+            } else {
+                return Ok(digits);
             }
         }
-        self._add_token(
-            token::TokenType::NUMBER,
-            Some(Literal::Number(
-                self.source[self.start..self.current]
-                    .iter()
-                    .collect::<String>()
-                    .parse()
-                    .unwrap(),
-            )),
-        );
     }
+    /// Scans a name or keyword. Names may use any script; keywords are all
+    /// ASCII, so lookalikes such as a fullwidth `ｖａｒ` stay names.
     fn identifier(&mut self) {
-        while self.peek().is_ascii_alphanumeric() || self.peek() == '_' {
+        while is_identifier_continue(self.peek()) {
             self.advance();
         }
         let tt = self.source[self.start..self.current]
@@ -111,13 +338,38 @@ impl Tokenizer {
         let ty = token::get_keywords(tt).unwrap_or(token::TokenType::IDENTIFIER);
         self.add_token(ty)
     }
+    /// Records `error` and emits an `ERROR` token covering the offending
+    /// lexeme, so the parser can skip it without reporting it again.
+    fn error(&mut self, error: TokenizerError) {
+        self.errors.push(error);
+        self.add_token(token::TokenType::ERROR);
+    }
     pub fn scan_token(&mut self) -> Result<(), TokenizerError> {
         let c = self.advance();
         match c {
             '(' => self.add_token(token::TokenType::LEFT_PAREN),
             ')' => self.add_token(token::TokenType::RIGHT_PAREN),
-            '{' => self.add_token(token::TokenType::LEFT_BRACE),
-            '}' => self.add_token(token::TokenType::RIGHT_BRACE),
+            '{' => {
+                if let Some(depth) = self.interpolations.last_mut() {
+                    *depth += 1;
+                }
+                self.add_token(token::TokenType::LEFT_BRACE)
+            }
+            '}' => match self.interpolations.last_mut() {
+                // closes the innermost `${`, so the string carries on
+                Some(0) => {
+                    self.interpolations.pop();
+                    self.string()?;
+                }
+                Some(depth) => {
+                    *depth -= 1;
+                    self.add_token(token::TokenType::RIGHT_BRACE)
+                }
+                None => self.add_token(token::TokenType::RIGHT_BRACE),
+            },
+            '[' => self.add_token(token::TokenType::LEFT_BRACKET),
+            ']' => self.add_token(token::TokenType::RIGHT_BRACKET),
+            ':' => self.add_token(token::TokenType::COLON),
             ',' => self.add_token(token::TokenType::COMMA),
             '.' => self.add_token(token::TokenType::DOT),
             '-' => self.add_token(token::TokenType::MINUS),
@@ -161,6 +413,8 @@ impl Tokenizer {
                     while self.peek() != '\n' && !self.is_at_end() {
                         self.advance();
                     }
+                } else if self.peek_match('*') {
+                    self.block_comment()?;
                 } else {
                     self.add_token(token::TokenType::SLASH);
                 }
@@ -169,45 +423,66 @@ impl Tokenizer {
                 // ignore whitespace
             }
             '\n' => {
-                self.line += 1;
+                self.newline();
             }
             '"' => {
                 self.string()?;
             }
             '0'..='9' => {
-                self.number();
+                self.number()?;
             }
-            _ if c.is_ascii_alphabetic() || c == '_' => {
+            _ if is_identifier_start(c) => {
                 self.identifier();
             }
             _ => {
-                return Err(TokenizerError::UnexpectedCharacter(self.line));
+                return Err(TokenizerError::UnexpectedCharacter(
+                    self.line,
+                    self.span_from(self.start_offset),
+                ));
             }
         }
         Ok(())
     }
     fn advance(&mut self) -> char {
+        let c = self.source[self.current];
         self.current += 1;
-        self.source[self.current - 1]
+        self.offset += c.len_utf8();
+        c
     }
-    pub fn scan_tokens(&mut self) -> Result<&[Token], TokenizerError> {
+    /// Scans the whole source. Errors don't stop the scan: each one is
+    /// recorded and leaves an `ERROR` token in the stream.
+    pub fn scan_tokens(&mut self) -> (&[Token], &[TokenizerError]) {
         while !self.is_at_end() {
             self.start = self.current;
-            self.scan_token()?;
-            // self.scan_token()?;
+            self.start_offset = self.offset;
+            self.start_column = self.current - self.line_start + 1;
+            if let Err(e) = self.scan_token() {
+                self.error(e);
+            }
         }
         self.tokens.push(Token {
             token_type: token::TokenType::EOF,
             lexeme: "".to_string(),
             literal: None,
             line: self.line,
+            column: self.current - self.line_start + 1,
+            span: self.span_from(self.offset),
         });
-        Ok(&self.tokens)
+        (&self.tokens, &self.errors)
     }
     pub fn is_at_end(&self) -> bool {
         self.current >= self.source.len()
     }
 }
+/// Whether `c` can start a name: a Unicode `XID_Start` letter or `_`.
+fn is_identifier_start(c: char) -> bool {
+    c == '_' || unicode_ident::is_xid_start(c)
+}
+/// Whether `c` can carry on a name: a Unicode `XID_Continue` character,
+/// which covers digits and `_`.
+fn is_identifier_continue(c: char) -> bool {
+    unicode_ident::is_xid_continue(c)
+}
 
 #[cfg(test)]
 mod tests {
@@ -215,32 +490,270 @@ mod tests {
     #[test]
     fn test_scanner() {
         let mut scanner = Tokenizer::new("(abc=a+b)".to_string());
-        println!("{:?}", scanner.scan_tokens().unwrap());
+        println!("{:?}", scanner.scan_tokens().0);
     }
     #[test]
     fn test_string() {
         let mut scanner = Tokenizer::new("\"abc\"".to_string());
-        println!("{:?}", scanner.scan_tokens().unwrap());
+        println!("{:?}", scanner.scan_tokens().0);
     }
     #[test]
     fn test_number() {
         let mut scanner = Tokenizer::new("   34545 .1234".to_string());
-        println!("{:?}", scanner.scan_tokens());
+        println!("{:?}", scanner.scan_tokens().0);
+    }
+    #[test]
+    fn test_number_syntax() {
+        for (source, value) in [
+            ("0x1F", 31.0),
+            ("0XfF", 255.0),
+            ("0b1010", 10.0),
+            ("1_000_000", 1e6),
+            ("6.02e23", 6.02e23),
+            ("1e-9", 1e-9),
+            ("2E+3", 2000.0),
+            ("1_0.2_5e1_0", 10.25e10),
+            ("007", 7.0),
+        ] {
+            let mut scanner = Tokenizer::new(source.to_string());
+            let (tokens, errors) = scanner.scan_tokens();
+            assert!(errors.is_empty(), "{source}: {errors:?}");
+            assert_eq!(tokens[0].literal, Some(Literal::Number(value)), "{source}");
+            assert_eq!(tokens[0].lexeme, source);
+        }
+        // a dot needs a digit on both sides to be part of a number
+        let mut scanner = Tokenizer::new(".5 + 1.".to_string());
+        let types: Vec<_> = scanner
+            .scan_tokens()
+            .0
+            .iter()
+            .map(|t| t.token_type)
+            .collect();
+        use token::TokenType::*;
+        assert_eq!(types, [DOT, NUMBER, PLUS, NUMBER, DOT, EOF]);
+    }
+    #[test]
+    fn test_malformed_numbers() {
+        for (source, literal, reason) in [
+            ("0x;", "0x", "expected hex digits after '0x'"),
+            ("0b;", "0b", "expected binary digits after '0b'"),
+            ("0b102;", "0b102", "unexpected character"),
+            ("0x1G;", "0x1G", "unexpected character"),
+            ("12ab;", "12ab", "unexpected character"),
+            ("1_;", "1_", "'_' must be between digits"),
+            ("1__0;", "1__0", "'_' must be between digits"),
+            ("0x_1;", "0x_1", "'_' must be between digits"),
+            ("1_.5;", "1_", "'_' must be between digits"),
+            ("1e;", "1e", "expected digits in exponent"),
+            ("1e+;", "1e+", "expected digits in exponent"),
+            ("1.5e_3;", "1.5e_3", "'_' must be between digits"),
+        ] {
+            let mut scanner = Tokenizer::new(source.to_string());
+            let (tokens, errors) = scanner.scan_tokens();
+            let [TokenizerError::MalformedNumber(1, span, found)] = errors else {
+                panic!("{source}: expected a malformed number, got {errors:?}");
+            };
+            assert_eq!(*found, reason, "{source}");
+            assert_eq!(&source[span.start..span.end], literal, "{source}");
+            assert_eq!(tokens[0].token_type, token::TokenType::ERROR);
+        }
+    }
+    #[test]
+    fn test_unicode_identifiers() {
+        let source = "var café = π + _ñ2 + 変数 + ｖａｒ + ǅ;";
+        let mut scanner = Tokenizer::new(source.to_string());
+        let (tokens, errors) = scanner.scan_tokens();
+        assert!(errors.is_empty(), "{errors:?}");
+        let found: Vec<_> = tokens
+            .iter()
+            .map(|t| (t.token_type, t.lexeme.as_str()))
+            .collect();
+        use token::TokenType::*;
+        assert_eq!(
+            found,
+            [
+                (VAR, "var"),
+                (IDENTIFIER, "café"),
+                (EQUAL, "="),
+                (IDENTIFIER, "π"),
+                (PLUS, "+"),
+                (IDENTIFIER, "_ñ2"),
+                (PLUS, "+"),
+                (IDENTIFIER, "変数"),
+                (PLUS, "+"),
+                (IDENTIFIER, "ｖａｒ"),
+                (PLUS, "+"),
+                (IDENTIFIER, "ǅ"),
+                (SEMICOLON, ";"),
+                (EOF, "")
+            ]
+        );
+        // symbols and combining marks can't start a name
+        for source in ["😀", "\u{301}a", "·"] {
+            let mut scanner = Tokenizer::new(source.to_string());
+            let (_, errors) = scanner.scan_tokens();
+            assert!(
+                matches!(errors, [TokenizerError::UnexpectedCharacter(1, _)]),
+                "{source}: {errors:?}"
+            );
+        }
+        let mut scanner = Tokenizer::new("12é".to_string());
+        let (_, errors) = scanner.scan_tokens();
+        assert!(matches!(errors, [TokenizerError::MalformedNumber(..)]));
     }
     #[test]
     fn test_ident_and_keyw() {
         let mut scanner = Tokenizer::new("andand_ //abcde_\na".to_string());
-        println!("{:?}", scanner.scan_tokens());
+        println!("{:?}", scanner.scan_tokens().0);
     }
     #[test]
     fn test_paren() {
         let mut scanner = Tokenizer::new(r#"print("Hello, World")"#.to_string());
 
-        println!("{:#?}", scanner.scan_tokens());
+        println!("{:#?}", scanner.scan_tokens().0);
     }
     #[test]
     fn test_fail() {
         let mut scanner = Tokenizer::new("1+1=2\n\"abc".to_string());
-        println!("{:?}", scanner.scan_tokens().unwrap_err());
+        let (_, errors) = scanner.scan_tokens();
+        assert!(matches!(errors, [TokenizerError::UnterminatedString(2, _)]));
+    }
+    #[test]
+    fn test_block_comment() {
+        let source = "a /* one\n /* two\n */ still */ b\n/**/c /*/ */ d";
+        let mut scanner = Tokenizer::new(source.to_string());
+        let (tokens, errors) = scanner.scan_tokens();
+        assert!(errors.is_empty());
+        let found: Vec<_> = tokens
+            .iter()
+            .map(|t| (t.lexeme.as_str(), t.line, t.column))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("a", 1, 1),
+                ("b", 3, 14),
+                ("c", 4, 5),
+                ("d", 4, 14),
+                ("", 4, 15)
+            ]
+        );
+    }
+    #[test]
+    fn test_unterminated_comment() {
+        let source = "print 1;\n  /* outer /* inner */\nprint 2;";
+        let mut scanner = Tokenizer::new(source.to_string());
+        let (tokens, errors) = scanner.scan_tokens();
+        let [TokenizerError::UnterminatedComment(line, span)] = errors else {
+            panic!("expected an unterminated comment, got {errors:?}");
+        };
+        assert_eq!(*line, 2);
+        assert_eq!(&source[span.start..span.end], "/*");
+        assert_eq!(span.start, 11);
+        assert_eq!(tokens[tokens.len() - 2].token_type, token::TokenType::ERROR);
+    }
+    #[test]
+    fn test_escapes() {
+        let source = r#""a\tb\nc\r\\ \"q\" \0 \u{1F600}\u{e9}""#;
+        let mut scanner = Tokenizer::new(source.to_string());
+        let (tokens, errors) = scanner.scan_tokens();
+        assert!(errors.is_empty());
+        assert_eq!(
+            tokens[0].literal,
+            Some(Literal::String("a\tb\nc\r\\ \"q\" \0 😀é".to_owned()))
+        );
+        assert_eq!(tokens[0].lexeme, source);
+    }
+    #[test]
+    fn test_interpolation() {
+        let source = r#""a ${b + "${c}"} d ${ {} } e\${f}""#;
+        let mut scanner = Tokenizer::new(source.to_string());
+        let (tokens, errors) = scanner.scan_tokens();
+        assert!(errors.is_empty());
+        let found: Vec<_> = tokens
+            .iter()
+            .map(|t| (&t.token_type, t.lexeme.as_str()))
+            .collect();
+        use token::TokenType::*;
+        assert_eq!(
+            found,
+            [
+                (&INTERPOLATION, "\"a ${"),
+                (&IDENTIFIER, "b"),
+                (&PLUS, "+"),
+                (&INTERPOLATION, "\"${"),
+                (&IDENTIFIER, "c"),
+                (&STRING, "}\""),
+                (&INTERPOLATION, "} d ${"),
+                (&LEFT_BRACE, "{"),
+                (&RIGHT_BRACE, "}"),
+                (&STRING, r#"} e\${f}""#),
+                (&EOF, "")
+            ]
+        );
+        assert_eq!(tokens[0].literal, Some(Literal::String("a ".to_owned())));
+        assert_eq!(tokens[6].literal, Some(Literal::String(" d ".to_owned())));
+        assert_eq!(
+            tokens[9].literal,
+            Some(Literal::String(" e${f}".to_owned()))
+        );
+    }
+    #[test]
+    fn test_invalid_escapes() {
+        for (source, escape) in [
+            (r#"print "ok \q";"#, r"\q"),
+            (r#"print "\u{110000}";"#, r"\u{110000}"),
+            (r#"print "\u{D800}";"#, r"\u{D800}"),
+            (r#"print "\u{}";"#, r"\u{}"),
+            (r#"print "\u12";"#, r"\u"),
+            (r#"print "\u{1234567}";"#, r"\u{123456"),
+            ("print \"\\\n\";", r"\"),
+        ] {
+            let mut scanner = Tokenizer::new(source.to_string());
+            let (tokens, errors) = scanner.scan_tokens();
+            let [TokenizerError::InvalidEscape(1, span)] = errors else {
+                panic!("{source}: expected an invalid escape, got {errors:?}");
+            };
+            assert_eq!(&source[span.start..span.end], escape, "{source}");
+            // the rest of the string is skipped, not scanned as code
+            let types: Vec<_> = tokens.iter().map(|t| &t.token_type).collect();
+            assert_eq!(
+                types,
+                [
+                    &token::TokenType::PRINT,
+                    &token::TokenType::ERROR,
+                    &token::TokenType::SEMICOLON,
+                    &token::TokenType::EOF
+                ],
+                "{source}"
+            );
+        }
+    }
+    #[test]
+    fn test_spans() {
+        let source = "var é = \"ü\";\n  print é;";
+        let mut scanner = Tokenizer::new(source.to_string());
+        let (tokens, _) = scanner.scan_tokens();
+        for token in tokens.iter() {
+            assert_eq!(&source[token.span.start..token.span.end], token.lexeme);
+        }
+        let print = &tokens[5];
+        assert_eq!((print.line, print.column), (2, 3));
+        let ident = &tokens[6];
+        assert_eq!((ident.line, ident.column), (2, 9));
+    }
+    #[test]
+    fn test_collect_errors() {
+        let mut scanner = Tokenizer::new("var a = @;\nprint # 1;\n$".to_string());
+        let (tokens, errors) = scanner.scan_tokens();
+        let lines: Vec<_> = errors.iter().map(TokenizerError::line).collect();
+        assert_eq!(lines, vec![1, 2, 3]);
+        let error_tokens: Vec<_> = tokens
+            .iter()
+            .filter(|t| t.token_type == token::TokenType::ERROR)
+            .map(|t| t.lexeme.as_str())
+            .collect();
+        assert_eq!(error_tokens, vec!["@", "#", "$"]);
+        assert_eq!(tokens.last().unwrap().token_type, token::TokenType::EOF);
     }
 }
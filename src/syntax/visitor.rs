@@ -1,6 +1,8 @@
-use super::super::{environment::EnvironmentError, resolver::ResolverError};
+use super::super::{
+    compiler::CompileError, environment::EnvironmentError, resolver::ResolverError,
+};
 use super::ast::*;
-use super::token::{Literal, Token};
+use super::token::{Literal, Span, Token};
 use std::rc::Rc;
 use thiserror::Error;
 #[derive(Error, Debug)]
@@ -9,30 +11,163 @@ pub enum VisitorError {
     VistorError,
     #[error("Environment Error")]
     EnvironmentError,
-    #[error("line {}: {} ** Can only call functions and classes",.0.line,.0.lexeme)]
-    NotCallable(Token),
-    #[error("line {}: {} ** Expected {0} arguments but got {1}.",.2.line,.2.lexeme)]
-    ArityNotMatched(usize, usize, Token),
-    #[error("line {}: {} ** Operands must be two numbers",.0.line,.0.lexeme)]
-    ArithmeticError(Token),
-    #[error("line {}: {} ** Unknown {1} Operator",.0.line,.0.lexeme)]
-    UnknownOperator(Token, &'static str),
-    #[error("line {}: {} ** Unary - must be used with a number",.0.line,.0.lexeme)]
-    UnaryTypeError(Token),
-    #[error("line {}: {} ** Undefined variable",.0.line,.0.lexeme)]
-    UndefinedVariable(Token),
+    #[error("Can only call functions and classes.")]
+    NotCallable(Box<Token>),
+    #[error("Expected {0} arguments but got {1}.")]
+    ArityNotMatched(usize, usize, Box<Token>),
+    #[error("Operands must be two numbers.")]
+    ArithmeticError(Box<Token>),
+    #[error("Unknown {1} operator.")]
+    UnknownOperator(Box<Token>, &'static str),
+    #[error("Unary - must be used with a number.")]
+    UnaryTypeError(Box<Token>),
+    #[error("Undefined variable '{}'.", .0.lexeme)]
+    UndefinedVariable(Box<Token>),
     #[error("Return value: {0}")]
     ReturnValue(Literal),
-    #[error("line {}: {} ** Can't read local variable in its own initializer.",.0.line,.0.lexeme)]
-    NotInitialized(Token),
-    #[error("EnvironmentError: {0}")]
+    #[error("Break outside of a loop.")]
+    Break,
+    #[error("Continue outside of a loop.")]
+    Continue,
+    #[error("Can't read local variable in its own initializer.")]
+    NotInitialized(Box<Token>),
+    #[error(transparent)]
     Variable(#[from] EnvironmentError),
-    #[error("ResolverError: {0}")]
+    #[error(transparent)]
     Resolver(#[from] ResolverError),
-    #[error("line {}: {} ** Undefined property '{}'.",.0.line,.0.lexeme,.1)]
-    UndefinedProperty(Token, String),
-    #[error("line {0}: ** Superclass must be a class.")]
+    #[error("Undefined property '{1}'.")]
+    UndefinedProperty(Box<Token>, String),
+    #[error("Only instances have properties.")]
+    NotAnInstance(Box<Token>),
+    #[error("Only lists and maps can be indexed.")]
+    NotIndexable(Box<Token>),
+    #[error("Superclass must be a class.")]
     SuperclassMustBeAClass(usize),
+    #[error(transparent)]
+    Compile(#[from] CompileError),
+    #[error("Stack overflow.")]
+    StackOverflow(usize),
+    /// Raised by native functions. The interpreter fills in the call site.
+    #[error("{1}")]
+    RuntimeError(ErrorKind, String, Option<Box<Token>>),
+    /// The step budget set with `Interpreter::set_fuel` ran out.
+    #[error("Execution budget exhausted.")]
+    OutOfFuel,
+    /// The deadline set with `Interpreter::set_deadline` passed.
+    #[error("Execution timed out.")]
+    Timeout,
+    /// A value raised by `throw`, pointing at the throw statement.
+    #[error("{}", thrown_message(.0))]
+    Thrown(Literal, Box<Token>),
+}
+/// The class from the prelude that `catch` receives a runtime error as, so
+/// scripts can tell kinds of error apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    Error,
+    Arithmetic,
+    UndefinedProperty,
+    Arity,
+}
+impl ErrorKind {
+    pub const ALL: [ErrorKind; 4] = [
+        ErrorKind::Error,
+        ErrorKind::Arithmetic,
+        ErrorKind::UndefinedProperty,
+        ErrorKind::Arity,
+    ];
+    pub fn class_name(self) -> &'static str {
+        match self {
+            ErrorKind::Error => "Error",
+            ErrorKind::Arithmetic => "ArithmeticError",
+            ErrorKind::UndefinedProperty => "UndefinedPropertyError",
+            ErrorKind::Arity => "ArityError",
+        }
+    }
+}
+/// An `Error` instance reports its message; any other value is printed.
+fn thrown_message(value: &Literal) -> String {
+    if let Literal::Instance(instance) = value {
+        if let Some(message) = instance.borrow().field("message") {
+            return message.to_string();
+        }
+    }
+    value.to_string()
+}
+impl VisitorError {
+    /// An error for a native function to return; it is reported at the
+    /// call that invoked the native.
+    pub fn runtime(message: impl Into<String>) -> Self {
+        VisitorError::runtime_of(ErrorKind::Error, message)
+    }
+    /// Like [`VisitorError::runtime`], caught as the class for `kind`.
+    pub fn runtime_of(kind: ErrorKind, message: impl Into<String>) -> Self {
+        VisitorError::RuntimeError(kind, message.into(), None)
+    }
+    /// The class a script catches the error as.
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            VisitorError::ArithmeticError(_) | VisitorError::UnaryTypeError(_) => {
+                ErrorKind::Arithmetic
+            }
+            VisitorError::UndefinedProperty(..) => ErrorKind::UndefinedProperty,
+            VisitorError::ArityNotMatched(..) => ErrorKind::Arity,
+            VisitorError::RuntimeError(kind, ..) => *kind,
+            _ => ErrorKind::Error,
+        }
+    }
+    /// Whether `catch` can handle the error. Returns and loop jumps unwind
+    /// through `try` blocks as control flow, as do compile errors, and a
+    /// script can't catch its way past an interrupt.
+    pub fn is_catchable(&self) -> bool {
+        !matches!(
+            self,
+            VisitorError::ReturnValue(_)
+                | VisitorError::Break
+                | VisitorError::Continue
+                | VisitorError::Compile(_)
+                | VisitorError::Resolver(_)
+        ) && !self.is_interrupt()
+    }
+    /// Whether the host stopped the script, rather than the script failing.
+    pub fn is_interrupt(&self) -> bool {
+        matches!(self, VisitorError::OutOfFuel | VisitorError::Timeout)
+    }
+    /// The token the error points at, if it has one.
+    pub fn token(&self) -> Option<&Token> {
+        match self {
+            VisitorError::NotCallable(token)
+            | VisitorError::ArityNotMatched(_, _, token)
+            | VisitorError::ArithmeticError(token)
+            | VisitorError::UnknownOperator(token, _)
+            | VisitorError::UnaryTypeError(token)
+            | VisitorError::UndefinedVariable(token)
+            | VisitorError::NotInitialized(token)
+            | VisitorError::UndefinedProperty(token, _)
+            | VisitorError::NotAnInstance(token)
+            | VisitorError::NotIndexable(token)
+            | VisitorError::RuntimeError(_, _, Some(token))
+            | VisitorError::Thrown(_, token) => Some(token),
+            VisitorError::Variable(e) => e.token(),
+            VisitorError::Resolver(e) => e.token(),
+            _ => None,
+        }
+    }
+    /// Source range to underline, if the error points at scanned source.
+    pub fn span(&self) -> Option<Span> {
+        self.token()
+            .filter(|token| token.has_span())
+            .map(|token| token.span)
+    }
+    /// Source line of the error, or 0 if it has no location.
+    pub fn line(&self) -> usize {
+        match self {
+            VisitorError::SuperclassMustBeAClass(line) | VisitorError::StackOverflow(line) => *line,
+            VisitorError::Resolver(e) => e.line(),
+            VisitorError::Compile(e) => e.line(),
+            _ => self.token().map_or(0, |token| token.line),
+        }
+    }
 }
 pub type VisitorResult<T> = Result<T, VisitorError>;
 pub trait ExprVisitor {
@@ -50,20 +185,30 @@ pub trait ExprVisitor {
     fn visitor_set(&mut self, set: &Set) -> VisitorResult<Literal>;
     fn visit_this(&mut self, token: &This) -> VisitorResult<Literal>;
     fn visit_super(&mut self, s: &Super) -> VisitorResult<Literal>;
+    fn visit_lambda(&mut self, func: &Rc<FnStmt>) -> VisitorResult<Literal>;
+    fn visit_list(&mut self, items: &[Expr]) -> VisitorResult<Literal>;
+    fn visit_map(&mut self, entries: &[(Expr, Expr)], brace: &Token) -> VisitorResult<Literal>;
+    fn visit_index(&mut self, index: &Index) -> VisitorResult<Literal>;
+    fn visit_set_index(&mut self, set: &SetIndex) -> VisitorResult<Literal>;
+    fn visit_interpolation(&mut self, parts: &[Expr]) -> VisitorResult<Literal>;
 }
 pub trait StmtVisitor {
-    fn visit_while(&mut self, cond: &Expr, body: &Stmt) -> VisitorResult<()>;
+    fn visit_while(
+        &mut self,
+        cond: &Expr,
+        body: &Stmt,
+        increment: Option<&Expr>,
+    ) -> VisitorResult<()>;
     fn visit_expression(&mut self, expr: &Expr) -> VisitorResult<()>;
     fn visit_print(&mut self, expr: &Expr) -> VisitorResult<()>;
     fn visit_var(&mut self, token: &Token, expr: Option<&Expr>) -> VisitorResult<()>;
     fn visit_block(&mut self, stmts: &[Stmt]) -> VisitorResult<()>;
     fn visit_if(&mut self, cond: &Expr, body: &(Stmt, Option<Stmt>)) -> VisitorResult<()>;
-    fn visit_function(
-        &mut self,
-        name: &Token,
-        params: Rc<[Token]>,
-        body: Rc<[Stmt]>,
-    ) -> VisitorResult<()>;
+    fn visit_function(&mut self, func: &FnStmt) -> VisitorResult<()>;
     fn visit_return(&mut self, token: &Token, expr: Option<&Expr>) -> VisitorResult<()>;
     fn visit_class(&mut self, class: &ClassStmt) -> VisitorResult<()>;
+    fn visit_break(&mut self, keyword: &Token) -> VisitorResult<()>;
+    fn visit_continue(&mut self, keyword: &Token) -> VisitorResult<()>;
+    fn visit_throw(&mut self, keyword: &Token, value: &Expr) -> VisitorResult<()>;
+    fn visit_try(&mut self, stmt: &TryStmt) -> VisitorResult<()>;
 }
@@ -0,0 +1,963 @@
+mod builtins;
+pub mod chunk;
+pub mod value;
+use crate::builtins::list_index;
+use crate::compiler::Compiler;
+use crate::environment::EnvironmentError;
+use crate::interpreter::{self, DEFAULT_MAX_CALL_DEPTH, DEFAULT_STACK_LIMIT, PRELUDE};
+use crate::output::{output_error, Captured, Output};
+use crate::syntax::ast::{ErrorKind, Stmt, VisitorError, VisitorResult};
+use crate::syntax::token::{Literal, Map, MapKey, Token, TokenType};
+use chunk::OpCode;
+use rustc_hash::FxHashMap;
+use std::cell::RefCell;
+use std::fmt::Write;
+use std::rc::Rc;
+use value::*;
+struct CallFrame {
+    closure: Rc<Closure>,
+    ip: usize,
+    slots: usize,
+    /// Running `init` for a class call, which stack traces name after the
+    /// class.
+    constructing: bool,
+}
+/// Where to resume when an error reaches a `try`.
+struct Handler {
+    /// Number of frames, and stack height, when the handler was pushed.
+    frames: usize,
+    stack: usize,
+    ip: usize,
+    /// A `finally` handler receives a handle to the error in
+    /// [`Vm::pending`] rather than the error itself.
+    finally: bool,
+}
+/// Stack machine executing chunks produced by [`Compiler`].
+///
+/// Globals survive between calls to [`Vm::interpret`], so one `Vm` can back a
+/// whole REPL session.
+pub struct Vm {
+    stack: Vec<Value>,
+    frames: Vec<CallFrame>,
+    globals: FxHashMap<Rc<str>, Value>,
+    open_upvalues: Vec<UpvalueRef>,
+    init_string: Rc<str>,
+    handlers: Vec<Handler>,
+    /// Errors waiting for their `finally` block to finish, with the value
+    /// thrown for them.
+    pending: Vec<(VisitorError, Value)>,
+    /// The value raised by the `Throw` being unwound.
+    thrown: Option<Value>,
+    /// The prelude's error classes, by [`ErrorKind`], kept even if a script
+    /// shadows the globals.
+    error_classes: [Rc<RefCell<ObjClass>>; 4],
+    /// Where the last uncaught error was raised, innermost call first.
+    trace: Vec<interpreter::CallFrame>,
+    max_frames: usize,
+    stack_limit: usize,
+    output: Output,
+}
+impl Default for Vm {
+    fn default() -> Self {
+        let mut vm = Self {
+            stack: Vec::with_capacity(256),
+            frames: Vec::with_capacity(64),
+            globals: FxHashMap::default(),
+            open_upvalues: vec![],
+            init_string: Rc::from("init"),
+            handlers: vec![],
+            pending: vec![],
+            thrown: None,
+            // replaced by the prelude's class below
+            error_classes: ErrorKind::ALL
+                .map(|kind| Rc::new(RefCell::new(ObjClass::new(Rc::from(kind.class_name()))))),
+            trace: vec![],
+            // the script itself takes a frame
+            max_frames: DEFAULT_MAX_CALL_DEPTH + 1,
+            stack_limit: DEFAULT_STACK_LIMIT,
+            output: Output::default(),
+        };
+        vm.define_native("clock", 0, |_| {
+            let now = std::time::SystemTime::now();
+            let duration = now.duration_since(std::time::UNIX_EPOCH).unwrap();
+            Ok(Value::Number(duration.as_secs_f64()))
+        });
+        builtins::define(&mut vm);
+        let prelude = crate::runner::parse(PRELUDE).expect("the prelude parses");
+        vm.interpret(&prelude).expect("the prelude runs");
+        for kind in ErrorKind::ALL {
+            let Some(Value::Class(class)) = vm.globals.get(kind.class_name()) else {
+                unreachable!("the prelude defines {}", kind.class_name())
+            };
+            vm.error_classes[kind as usize] = Rc::clone(class);
+        }
+        vm
+    }
+}
+impl Vm {
+    pub fn define_native(
+        &mut self,
+        name: &str,
+        arity: usize,
+        func: impl Fn(&[Value]) -> Result<Value, String> + 'static,
+    ) {
+        self.globals.insert(
+            Rc::from(name),
+            Value::Native(Rc::new(NativeFn {
+                name: name.to_owned(),
+                arity,
+                func: Box::new(func),
+            })),
+        );
+    }
+    /// Limits how deep Lox calls may nest. Deeper calls raise a "Stack
+    /// overflow." runtime error.
+    pub fn set_max_call_depth(&mut self, depth: usize) {
+        self.max_frames = depth + 1;
+    }
+    /// Limits how many bytes of native stack compiling nested code may use.
+    /// Raise it when running on a thread with a bigger stack.
+    pub fn set_stack_limit(&mut self, bytes: usize) {
+        self.stack_limit = bytes;
+    }
+    pub fn stack_limit(&self) -> usize {
+        self.stack_limit
+    }
+    /// Sends the text of `print` statements to `output` instead of stdout.
+    pub fn set_output(&mut self, output: Output) {
+        self.output = output;
+    }
+    /// Collects printed text instead of writing it to stdout.
+    pub fn capture_output(&mut self) -> Captured {
+        let (output, captured) = Output::capture();
+        self.output = output;
+        captured
+    }
+    pub fn interpret(&mut self, stmts: &[Stmt]) -> VisitorResult<()> {
+        let function = Compiler::compile(stmts, self.stack_limit)?;
+        self.run_script(function).map(|_| ())
+    }
+    /// Like [`Vm::interpret`], but evaluates a trailing expression statement
+    /// and returns its value. Returns nil otherwise.
+    pub fn eval(&mut self, stmts: &[Stmt]) -> VisitorResult<Value> {
+        let function = Compiler::compile_eval(stmts, self.stack_limit)?;
+        self.run_script(function)
+    }
+    fn run_script(&mut self, function: Rc<ObjFunction>) -> VisitorResult<Value> {
+        self.trace.clear();
+        self.run_function(function).inspect_err(|_| {
+            self.trace = self.call_stack();
+            self.trace.reverse();
+            self.stack.clear();
+            self.frames.clear();
+            self.open_upvalues.clear();
+            self.handlers.clear();
+            self.pending.clear();
+        })
+    }
+    /// Every global variable, sorted by name.
+    pub fn globals(&self) -> Vec<(String, Value)> {
+        let mut globals: Vec<_> = self
+            .globals
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.clone()))
+            .collect();
+        globals.sort_by(|a, b| a.0.cmp(&b.0));
+        globals
+    }
+    /// The calls in progress where the last uncaught runtime error was
+    /// raised, innermost first.
+    pub fn stack_trace(&self) -> Vec<interpreter::CallFrame> {
+        self.trace.clone()
+    }
+    fn run_function(&mut self, function: Rc<ObjFunction>) -> VisitorResult<Value> {
+        let closure = Rc::new(Closure::new(function));
+        self.stack.push(Value::Closure(Rc::clone(&closure)));
+        self.call_closure(closure, 0)?;
+        self.run()
+    }
+    #[inline(always)]
+    fn frame(&mut self) -> &mut CallFrame {
+        self.frames.last_mut().unwrap()
+    }
+    #[inline(always)]
+    fn read_byte(&mut self) -> u8 {
+        let frame = self.frame();
+        frame.ip += 1;
+        frame.closure.function.chunk.code[frame.ip - 1]
+    }
+    #[inline(always)]
+    fn read_u16(&mut self) -> u16 {
+        let frame = self.frame();
+        frame.ip += 2;
+        frame.closure.function.chunk.read_u16(frame.ip - 2)
+    }
+    #[inline(always)]
+    fn read_constant(&mut self) -> Value {
+        let idx = self.read_u16() as usize;
+        self.frame().closure.function.chunk.constants[idx].clone()
+    }
+    #[inline(always)]
+    fn read_string(&mut self) -> Rc<str> {
+        let Value::String(s) = self.read_constant() else {
+            unreachable!()
+        };
+        s
+    }
+    #[inline(always)]
+    fn pop(&mut self) -> Value {
+        self.stack.pop().unwrap()
+    }
+    #[inline(always)]
+    fn peek(&self, distance: usize) -> &Value {
+        &self.stack[self.stack.len() - 1 - distance]
+    }
+    fn line(&self) -> usize {
+        let frame = self.frames.last().unwrap();
+        frame
+            .closure
+            .function
+            .chunk
+            .line_at(frame.ip.saturating_sub(1))
+    }
+    fn call_closure(&mut self, closure: Rc<Closure>, argc: usize) -> VisitorResult<()> {
+        if argc != closure.function.arity {
+            return Err(VisitorError::ArityNotMatched(
+                closure.function.arity,
+                argc,
+                Box::new(Token::synthetic(TokenType::RIGHT_PAREN, ")", self.line())),
+            ));
+        }
+        if self.frames.len() >= self.max_frames {
+            return Err(VisitorError::StackOverflow(self.line()));
+        }
+        self.frames.push(CallFrame {
+            closure,
+            ip: 0,
+            slots: self.stack.len() - argc - 1,
+            constructing: false,
+        });
+        Ok(())
+    }
+    fn call_value(&mut self, callee: Value, argc: usize) -> VisitorResult<()> {
+        match callee {
+            Value::Closure(closure) => self.call_closure(closure, argc),
+            Value::BoundMethod(bound) => {
+                let slot = self.stack.len() - argc - 1;
+                self.stack[slot] = bound.receiver.clone();
+                self.call_closure(Rc::clone(&bound.method), argc)
+            }
+            Value::Class(class) => {
+                let slot = self.stack.len() - argc - 1;
+                let init = class.borrow().methods.get(&self.init_string).cloned();
+                self.stack[slot] = Value::Instance(Rc::new(RefCell::new(ObjInstance::new(class))));
+                match init {
+                    Some(init) => {
+                        self.call_closure(init, argc)?;
+                        self.frame().constructing = true;
+                        Ok(())
+                    }
+                    None if argc != 0 => Err(VisitorError::ArityNotMatched(
+                        0,
+                        argc,
+                        Box::new(Token::synthetic(TokenType::RIGHT_PAREN, ")", self.line())),
+                    )),
+                    None => Ok(()),
+                }
+            }
+            Value::Native(native) => {
+                if argc != native.arity {
+                    return Err(VisitorError::ArityNotMatched(
+                        native.arity,
+                        argc,
+                        Box::new(Token::synthetic(TokenType::RIGHT_PAREN, ")", self.line())),
+                    ));
+                }
+                let start = self.stack.len() - argc;
+                let result = (native.func)(&self.stack[start..]).map_err(|message| {
+                    VisitorError::RuntimeError(
+                        ErrorKind::Error,
+                        message,
+                        Some(Box::new(Token::synthetic(
+                            TokenType::RIGHT_PAREN,
+                            ")",
+                            self.line(),
+                        ))),
+                    )
+                })?;
+                self.stack.truncate(start - 1);
+                self.stack.push(result);
+                Ok(())
+            }
+            _ => Err(VisitorError::NotCallable(Box::new(Token::synthetic(
+                TokenType::RIGHT_PAREN,
+                ")",
+                self.line(),
+            )))),
+        }
+    }
+    fn invoke(&mut self, name: &Rc<str>, argc: usize) -> VisitorResult<()> {
+        let Value::Instance(instance) = self.peek(argc).clone() else {
+            return Err(self.not_an_instance(name));
+        };
+        let field = instance.borrow().fields.get(name).cloned();
+        if let Some(field) = field {
+            let slot = self.stack.len() - argc - 1;
+            self.stack[slot] = field.clone();
+            return self.call_value(field, argc);
+        }
+        let method = instance.borrow().class.borrow().methods.get(name).cloned();
+        match method {
+            Some(method) => self.call_closure(method, argc),
+            None => Err(self.undefined_property(name)),
+        }
+    }
+    fn not_an_instance(&self, name: &str) -> VisitorError {
+        VisitorError::NotAnInstance(Box::new(Token::synthetic(
+            TokenType::IDENTIFIER,
+            name,
+            self.line(),
+        )))
+    }
+    fn undefined_property(&self, name: &str) -> VisitorError {
+        VisitorError::UndefinedProperty(
+            Box::new(Token::synthetic(TokenType::IDENTIFIER, name, self.line())),
+            name.to_owned(),
+        )
+    }
+    // subscript errors point at the closing bracket, as in the tree-walker
+    fn bracket(&self) -> Token {
+        Token::synthetic(TokenType::RIGHT_BRACKET, "]", self.line())
+    }
+    fn index_error(&self, message: String) -> VisitorError {
+        VisitorError::RuntimeError(ErrorKind::Error, message, Some(Box::new(self.bracket())))
+    }
+    fn capture_upvalue(&mut self, slot: usize) -> UpvalueRef {
+        let existing = self
+            .open_upvalues
+            .iter()
+            .find(|upvalue| matches!(*upvalue.borrow(), Upvalue::Open(s) if s == slot));
+        if let Some(upvalue) = existing {
+            return Rc::clone(upvalue);
+        }
+        let upvalue = Rc::new(RefCell::new(Upvalue::Open(slot)));
+        self.open_upvalues.push(Rc::clone(&upvalue));
+        upvalue
+    }
+    fn close_upvalues(&mut self, last: usize) {
+        let stack = &self.stack;
+        self.open_upvalues.retain(|upvalue| {
+            let mut upvalue = upvalue.borrow_mut();
+            match *upvalue {
+                Upvalue::Open(slot) if slot >= last => {
+                    *upvalue = Upvalue::Closed(stack[slot].clone());
+                    false
+                }
+                _ => true,
+            }
+        });
+    }
+    fn arithmetic_error(&self, op: OpCode) -> VisitorError {
+        let (token_type, lexeme) = match op {
+            OpCode::Add => (TokenType::PLUS, "+"),
+            OpCode::Subtract => (TokenType::MINUS, "-"),
+            OpCode::Multiply => (TokenType::STAR, "*"),
+            OpCode::Divide => (TokenType::SLASH, "/"),
+            OpCode::Greater => (TokenType::GREATER, ">"),
+            OpCode::GreaterEqual => (TokenType::GREATER_EQUAL, ">="),
+            OpCode::Less => (TokenType::LESS, "<"),
+            _ => (TokenType::LESS_EQUAL, "<="),
+        };
+        VisitorError::ArithmeticError(Box::new(Token::synthetic(token_type, lexeme, self.line())))
+    }
+    /// The calls in progress, outermost first, each with the line it was
+    /// made from.
+    fn call_stack(&self) -> Vec<interpreter::CallFrame> {
+        self.frames
+            .windows(2)
+            .map(|pair| {
+                let (caller, frame) = (&pair[0], &pair[1]);
+                let name = match (&self.stack[frame.slots], &frame.closure.function.name) {
+                    (Value::Instance(instance), _) if frame.constructing => {
+                        instance.borrow().class.borrow().name.to_string()
+                    }
+                    (_, Some(name)) => name.to_string(),
+                    (_, None) => "anonymous function".to_owned(),
+                };
+                let line = caller
+                    .closure
+                    .function
+                    .chunk
+                    .line_at(caller.ip.saturating_sub(1));
+                interpreter::CallFrame { name, line }
+            })
+            .collect()
+    }
+    /// Gives an `Error` instance the line it was raised at and the calls
+    /// active there, innermost first, unless it already has them.
+    fn locate_error(&self, value: &Value, line: usize) {
+        let Value::Instance(instance) = value else {
+            return;
+        };
+        if !instance
+            .borrow()
+            .class
+            .borrow()
+            .descends_from(&self.error_classes[ErrorKind::Error as usize])
+            || instance.borrow().fields.contains_key("line")
+        {
+            return;
+        }
+        let stack = self
+            .call_stack()
+            .iter()
+            .rev()
+            .map(|frame| Value::String(Rc::from(frame.to_string())))
+            .collect();
+        let mut instance = instance.borrow_mut();
+        instance
+            .fields
+            .insert(Rc::from("line"), Value::Number(line as f64));
+        instance
+            .fields
+            .insert(Rc::from("stack"), Value::List(Rc::new(RefCell::new(stack))));
+    }
+    /// The value a `catch` clause binds for `error`: the thrown value, or an
+    /// instance of the error class for a runtime error's kind.
+    fn caught_value(&mut self, error: &VisitorError) -> Value {
+        if let Some(value) = self.thrown.take() {
+            return value;
+        }
+        let mut instance = ObjInstance::new(Rc::clone(&self.error_classes[error.kind() as usize]));
+        instance.fields.insert(
+            Rc::from("message"),
+            Value::String(Rc::from(error.to_string())),
+        );
+        let value = Value::Instance(Rc::new(RefCell::new(instance)));
+        self.locate_error(&value, error.line());
+        value
+    }
+    /// Resumes at the innermost handler, or gives `error` back if there is
+    /// none.
+    fn catch(&mut self, error: VisitorError) -> VisitorResult<()> {
+        let Some(handler) = self.handlers.pop() else {
+            self.thrown = None;
+            return Err(error);
+        };
+        let value = self.caught_value(&error);
+        let value = if handler.finally {
+            self.pending.push((error, value));
+            Value::Number((self.pending.len() - 1) as f64)
+        } else {
+            value
+        };
+        self.frames.truncate(handler.frames);
+        self.close_upvalues(handler.stack);
+        self.stack.truncate(handler.stack);
+        self.frame().ip = handler.ip;
+        self.stack.push(value);
+        Ok(())
+    }
+    fn push_handler(&mut self, finally: bool) {
+        let offset = self.read_u16() as usize;
+        let ip = self.frame().ip + offset;
+        self.handlers.push(Handler {
+            frames: self.frames.len(),
+            stack: self.stack.len(),
+            ip,
+            finally,
+        });
+    }
+    /// Runs until the outermost frame returns, giving back its value.
+    fn run(&mut self) -> VisitorResult<Value> {
+        loop {
+            match self.execute() {
+                Err(error) if error.is_catchable() => self.catch(error)?,
+                result => return result,
+            }
+        }
+    }
+    fn execute(&mut self) -> VisitorResult<Value> {
+        loop {
+            let byte = self.read_byte();
+            let Some(op) = OpCode::from_byte(byte) else {
+                return Err(VisitorError::VistorError);
+            };
+            match op {
+                OpCode::Constant => {
+                    let constant = self.read_constant();
+                    self.stack.push(constant);
+                }
+                OpCode::Nil => self.stack.push(Value::Nil),
+                OpCode::True => self.stack.push(Value::Boolean(true)),
+                OpCode::False => self.stack.push(Value::Boolean(false)),
+                OpCode::Pop => {
+                    self.pop();
+                }
+                OpCode::GetLocal => {
+                    let slot = self.read_byte() as usize + self.frame().slots;
+                    self.stack.push(self.stack[slot].clone());
+                }
+                OpCode::SetLocal => {
+                    let slot = self.read_byte() as usize + self.frame().slots;
+                    self.stack[slot] = self.peek(0).clone();
+                }
+                OpCode::GetGlobal => {
+                    let name = self.read_string();
+                    match self.globals.get(&name) {
+                        Some(value) => self.stack.push(value.clone()),
+                        None => {
+                            return Err(EnvironmentError::UndefinedVariable(Box::new(
+                                Token::synthetic(TokenType::IDENTIFIER, &name, self.line()),
+                            ))
+                            .into())
+                        }
+                    }
+                }
+                OpCode::DefineGlobal => {
+                    let name = self.read_string();
+                    let value = self.pop();
+                    self.globals.insert(name, value);
+                }
+                OpCode::SetGlobal => {
+                    let name = self.read_string();
+                    let value = self.peek(0).clone();
+                    match self.globals.get_mut(&name) {
+                        Some(global) => *global = value,
+                        None => {
+                            return Err(EnvironmentError::UndefinedVariable(Box::new(
+                                Token::synthetic(TokenType::IDENTIFIER, &name, self.line()),
+                            ))
+                            .into())
+                        }
+                    }
+                }
+                OpCode::GetUpvalue => {
+                    let slot = self.read_byte() as usize;
+                    let upvalue = Rc::clone(&self.frame().closure.upvalues[slot]);
+                    let value = match &*upvalue.borrow() {
+                        Upvalue::Open(slot) => self.stack[*slot].clone(),
+                        Upvalue::Closed(value) => value.clone(),
+                    };
+                    self.stack.push(value);
+                }
+                OpCode::SetUpvalue => {
+                    let slot = self.read_byte() as usize;
+                    let upvalue = Rc::clone(&self.frame().closure.upvalues[slot]);
+                    let value = self.peek(0).clone();
+                    let mut upvalue = upvalue.borrow_mut();
+                    match &mut *upvalue {
+                        Upvalue::Open(slot) => self.stack[*slot] = value,
+                        Upvalue::Closed(closed) => *closed = value,
+                    }
+                }
+                OpCode::GetProperty => {
+                    let name = self.read_string();
+                    let Value::Instance(instance) = self.peek(0).clone() else {
+                        return Err(self.not_an_instance(&name));
+                    };
+                    let field = instance.borrow().fields.get(&name).cloned();
+                    let value = match field {
+                        Some(value) => value,
+                        None => {
+                            let method =
+                                instance.borrow().class.borrow().methods.get(&name).cloned();
+                            let Some(method) = method else {
+                                return Err(self.undefined_property(&name));
+                            };
+                            Value::BoundMethod(Rc::new(BoundMethod {
+                                receiver: Value::Instance(instance),
+                                method,
+                            }))
+                        }
+                    };
+                    self.pop();
+                    self.stack.push(value);
+                }
+                OpCode::SetProperty => {
+                    let name = self.read_string();
+                    let Value::Instance(instance) = self.peek(1).clone() else {
+                        return Err(self.not_an_instance(&name));
+                    };
+                    let value = self.pop();
+                    instance.borrow_mut().fields.insert(name, value.clone());
+                    self.pop();
+                    self.stack.push(value);
+                }
+                OpCode::GetSuper => {
+                    let name = self.read_string();
+                    let Value::Class(superclass) = self.pop() else {
+                        unreachable!()
+                    };
+                    let method = superclass.borrow().methods.get(&name).cloned();
+                    let Some(method) = method else {
+                        return Err(self.undefined_property(&name));
+                    };
+                    let receiver = self.pop();
+                    self.stack.push(Value::BoundMethod(Rc::new(BoundMethod {
+                        receiver,
+                        method,
+                    })));
+                }
+                OpCode::Equal => {
+                    let b = self.pop();
+                    let a = self.pop();
+                    self.stack.push(Value::Boolean(a == b));
+                }
+                OpCode::NotEqual => {
+                    let b = self.pop();
+                    let a = self.pop();
+                    self.stack.push(Value::Boolean(a != b));
+                }
+                OpCode::Greater
+                | OpCode::GreaterEqual
+                | OpCode::Less
+                | OpCode::LessEqual
+                | OpCode::Add
+                | OpCode::Subtract
+                | OpCode::Multiply
+                | OpCode::Divide => {
+                    let (Value::Number(a), Value::Number(b)) = (self.peek(1), self.peek(0)) else {
+                        return Err(self.arithmetic_error(op));
+                    };
+                    let (a, b) = (*a, *b);
+                    let result = match op {
+                        OpCode::Greater => Value::Boolean(a > b),
+                        OpCode::GreaterEqual => Value::Boolean(a >= b),
+                        OpCode::Less => Value::Boolean(a < b),
+                        OpCode::LessEqual => Value::Boolean(a <= b),
+                        OpCode::Add => Value::Number(a + b),
+                        OpCode::Subtract => Value::Number(a - b),
+                        OpCode::Multiply => Value::Number(a * b),
+                        _ => Value::Number(a / b),
+                    };
+                    self.pop();
+                    *self.stack.last_mut().unwrap() = result;
+                }
+                OpCode::Not => {
+                    let value = self.pop();
+                    self.stack.push(Value::Boolean(!value.is_truthy()));
+                }
+                OpCode::Negate => {
+                    let Value::Number(n) = self.pop() else {
+                        return Err(VisitorError::UnaryTypeError(Box::new(Token::synthetic(
+                            TokenType::MINUS,
+                            "-",
+                            self.line(),
+                        ))));
+                    };
+                    self.stack.push(Value::Number(-n));
+                }
+                OpCode::Print => {
+                    let value = self.pop();
+                    self.output.print(value).map_err(output_error)?;
+                }
+                OpCode::Jump => {
+                    let offset = self.read_u16() as usize;
+                    self.frame().ip += offset;
+                }
+                OpCode::JumpIfFalse => {
+                    let offset = self.read_u16() as usize;
+                    if !self.peek(0).is_truthy() {
+                        self.frame().ip += offset;
+                    }
+                }
+                OpCode::Loop => {
+                    let offset = self.read_u16() as usize;
+                    self.frame().ip -= offset;
+                }
+                OpCode::Call => {
+                    let argc = self.read_byte() as usize;
+                    let callee = self.peek(argc).clone();
+                    self.call_value(callee, argc)?;
+                }
+                OpCode::Invoke => {
+                    let name = self.read_string();
+                    let argc = self.read_byte() as usize;
+                    self.invoke(&name, argc)?;
+                }
+                OpCode::Closure => {
+                    let Value::Function(function) = self.read_constant() else {
+                        unreachable!()
+                    };
+                    let mut closure = Closure::new(function);
+                    for _ in 0..closure.function.upvalue_count {
+                        let is_local = self.read_byte() == 1;
+                        let index = self.read_byte() as usize;
+                        let upvalue = if is_local {
+                            let slot = self.frame().slots + index;
+                            self.capture_upvalue(slot)
+                        } else {
+                            Rc::clone(&self.frame().closure.upvalues[index])
+                        };
+                        closure.upvalues.push(upvalue);
+                    }
+                    self.stack.push(Value::Closure(Rc::new(closure)));
+                }
+                OpCode::CloseUpvalue => {
+                    self.close_upvalues(self.stack.len() - 1);
+                    self.pop();
+                }
+                OpCode::Return => {
+                    let result = self.pop();
+                    let frame = self.frames.pop().unwrap();
+                    while self
+                        .handlers
+                        .last()
+                        .is_some_and(|handler| handler.frames > self.frames.len())
+                    {
+                        self.handlers.pop();
+                    }
+                    self.close_upvalues(frame.slots);
+                    self.stack.truncate(frame.slots);
+                    if self.frames.is_empty() {
+                        return Ok(result);
+                    }
+                    self.stack.push(result);
+                }
+                OpCode::Class => {
+                    let name = self.read_string();
+                    self.stack
+                        .push(Value::Class(Rc::new(RefCell::new(ObjClass::new(name)))));
+                }
+                OpCode::Inherit => {
+                    let Value::Class(superclass) = self.peek(1) else {
+                        return Err(VisitorError::SuperclassMustBeAClass(self.line()));
+                    };
+                    let Value::Class(class) = self.peek(0) else {
+                        unreachable!()
+                    };
+                    let methods = superclass.borrow().methods.clone();
+                    let mut class = class.borrow_mut();
+                    class.methods.extend(methods);
+                    class.superclass = Some(Rc::clone(superclass));
+                    drop(class);
+                    self.pop();
+                }
+                OpCode::Method => {
+                    let name = self.read_string();
+                    let Value::Closure(method) = self.pop() else {
+                        unreachable!()
+                    };
+                    let Value::Class(class) = self.peek(0) else {
+                        unreachable!()
+                    };
+                    class.borrow_mut().methods.insert(name, method);
+                }
+                OpCode::BuildList => {
+                    let count = self.read_u16() as usize;
+                    let items = self.stack.split_off(self.stack.len() - count);
+                    self.stack.push(Value::List(Rc::new(RefCell::new(items))));
+                }
+                OpCode::Interpolate => {
+                    let count = self.read_u16() as usize;
+                    let mut text = String::new();
+                    for part in self.stack.drain(self.stack.len() - count..) {
+                        write!(text, "{part}").expect("writing to a String can't fail");
+                    }
+                    self.stack.push(Value::String(Rc::from(text)));
+                }
+                OpCode::BuildMap => {
+                    let count = self.read_u16() as usize;
+                    let entries = self.stack.split_off(self.stack.len() - count * 2);
+                    let mut map = Map::default();
+                    for entry in entries.chunks_exact(2) {
+                        let key = MapKey::try_from(&entry[0]).map_err(|message| {
+                            VisitorError::RuntimeError(
+                                ErrorKind::Error,
+                                message,
+                                Some(Box::new(Token::synthetic(
+                                    TokenType::RIGHT_BRACE,
+                                    "}",
+                                    self.line(),
+                                ))),
+                            )
+                        })?;
+                        map.insert(key, entry[1].clone());
+                    }
+                    self.stack.push(Value::Map(Rc::new(RefCell::new(map))));
+                }
+                OpCode::GetIndex => {
+                    let index = self.pop();
+                    let value = match self.pop() {
+                        Value::List(list) => {
+                            let list = list.borrow();
+                            let at = list_index(index.as_number(), list.len())
+                                .map_err(|message| self.index_error(message))?;
+                            list[at].clone()
+                        }
+                        Value::Map(map) => {
+                            let key = MapKey::try_from(&index)
+                                .map_err(|message| self.index_error(message))?;
+                            let value = map.borrow().get(&key).cloned();
+                            value.ok_or_else(|| {
+                                self.index_error(format!("Undefined key {}.", key))
+                            })?
+                        }
+                        _ => return Err(VisitorError::NotIndexable(Box::new(self.bracket()))),
+                    };
+                    self.stack.push(value);
+                }
+                OpCode::SetIndex => {
+                    let value = self.pop();
+                    let index = self.pop();
+                    match self.pop() {
+                        Value::List(list) => {
+                            let mut list = list.borrow_mut();
+                            let at = list_index(index.as_number(), list.len())
+                                .map_err(|message| self.index_error(message))?;
+                            list[at] = value.clone();
+                        }
+                        Value::Map(map) => {
+                            let key = MapKey::try_from(&index)
+                                .map_err(|message| self.index_error(message))?;
+                            map.borrow_mut().insert(key, value.clone());
+                        }
+                        _ => return Err(VisitorError::NotIndexable(Box::new(self.bracket()))),
+                    }
+                    self.stack.push(value);
+                }
+                OpCode::PushHandler => self.push_handler(false),
+                OpCode::PushFinally => self.push_handler(true),
+                OpCode::PopHandler => {
+                    self.handlers.pop();
+                }
+                OpCode::Throw => {
+                    let value = self.pop();
+                    let line = self.line();
+                    self.locate_error(&value, line);
+                    // the VM's values aren't literals, so an uncaught throw
+                    // reports the message alone
+                    let message = match &value {
+                        Value::Instance(instance) => instance
+                            .borrow()
+                            .fields
+                            .get("message")
+                            .map(Value::to_string),
+                        _ => None,
+                    };
+                    let message = message.unwrap_or_else(|| value.to_string());
+                    self.thrown = Some(value);
+                    return Err(VisitorError::Thrown(
+                        Literal::String(message),
+                        Box::new(Token::synthetic(TokenType::THROW, "throw", line)),
+                    ));
+                }
+                OpCode::EndFinally => {
+                    let Value::Number(handle) = self.pop() else {
+                        unreachable!()
+                    };
+                    let mut pending = self.pending.drain(handle as usize..);
+                    let (error, value) = pending.next().unwrap();
+                    drop(pending);
+                    self.thrown = Some(value);
+                    return Err(error);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::run;
+    #[test]
+    fn test_closure() {
+        let mut vm = Vm::default();
+        run(
+            r#"
+            fun makeCounter() {
+                var i = 0;
+                fun count() {
+                    i = i + 1;
+                    print i;
+                }
+                return count;
+            }
+            var counter = makeCounter();
+            counter(); // "1".
+            counter(); // "2".
+            "#,
+            &mut vm,
+        )
+        .unwrap();
+    }
+    #[test]
+    fn test_rec() {
+        let mut vm = Vm::default();
+        run(
+            r#"
+            fun fib(n) {
+                if (n <= 1) return n;
+                return fib(n - 1) + fib(n - 2);
+            }
+            for (var i = 0; i < 20; i = i + 1) {
+                print fib(i);
+            }
+            "#,
+            &mut vm,
+        )
+        .unwrap();
+    }
+    #[test]
+    fn test_inherit() {
+        let mut vm = Vm::default();
+        let output = vm.capture_output();
+        run(
+            r#"
+            class A {
+                method() {
+                    return 2;
+                }
+            }
+            class B < A {
+                init(x) {
+                    this.x = x;
+                }
+                method() {
+                    return super.method() + 10;
+                }
+                test() {
+                    print this.x;
+                    print super.method;
+                    print this.method();
+                }
+            }
+            B(1).test();
+            "#,
+            &mut vm,
+        )
+        .unwrap();
+        assert_eq!(output.text(), "1\nfunction method\n12\n");
+    }
+    #[test]
+    fn test_globals_persist() {
+        let mut vm = Vm::default();
+        run("var a = 1;", &mut vm).unwrap();
+        run("a = a + 1;", &mut vm).unwrap();
+        assert_eq!(vm.globals.get("a"), Some(&Value::Number(2.0)));
+    }
+    #[test]
+    fn test_max_call_depth() {
+        let mut vm = Vm::default();
+        vm.set_max_call_depth(10);
+        let source = "fun depth(n) { if (n == 0) return 0; return 1 + depth(n - 1); }";
+        run(source, &mut vm).unwrap();
+        run("depth(9);", &mut vm).unwrap();
+        let errors = run("depth(10);", &mut vm).unwrap_err();
+        assert_eq!(errors[0].message, "Stack overflow.");
+    }
+    #[test]
+    fn test_disassemble() {
+        let mut tokenizer = crate::syntax::tokenizer::Tokenizer::new(
+            "fun f(a) { var b = a; fun g() { return b; } return g; }".to_string(),
+        );
+        let (tokens, _) = tokenizer.scan_tokens();
+        let stmts = crate::syntax::parser::Parser::new(tokens).parse().unwrap();
+        let function = Compiler::compile(&stmts, DEFAULT_STACK_LIMIT).unwrap();
+        let listing = function.chunk.to_string();
+        assert!(listing.contains("Closure"));
+        assert!(listing.contains("local 2"));
+    }
+}
@@ -0,0 +1,55 @@
+//! The VM's side of the natives shared in [`crate::builtins`].
+use super::value::Value;
+use super::Vm;
+use crate::builtins::{list_natives, natives, NativeValue};
+use crate::syntax::token::{Map, MapKey};
+use std::cell::RefCell;
+use std::rc::Rc;
+pub(super) fn define(vm: &mut Vm) {
+    for (name, arity, func) in natives() {
+        vm.define_native(name, arity, func);
+    }
+    for (name, arity, func) in list_natives() {
+        vm.define_native(name, arity, move |args| {
+            Ok(Value::List(Rc::new(RefCell::new(func(args)?))))
+        });
+    }
+}
+impl NativeValue for Value {
+    fn nil() -> Self {
+        Value::Nil
+    }
+    fn number(n: f64) -> Self {
+        Value::Number(n)
+    }
+    fn boolean(b: bool) -> Self {
+        Value::Boolean(b)
+    }
+    fn from_key(key: &MapKey) -> Self {
+        Value::from(key)
+    }
+    fn to_number(&self) -> Option<f64> {
+        self.as_number()
+    }
+    fn str_len(&self) -> Option<usize> {
+        match self {
+            Value::String(s) => Some(s.chars().count()),
+            _ => None,
+        }
+    }
+    fn as_list(&self) -> Option<&Rc<RefCell<Vec<Self>>>> {
+        match self {
+            Value::List(list) => Some(list),
+            _ => None,
+        }
+    }
+    fn as_map(&self) -> Option<&Rc<RefCell<Map<Self>>>> {
+        match self {
+            Value::Map(map) => Some(map),
+            _ => None,
+        }
+    }
+    fn to_key(&self) -> Result<MapKey, String> {
+        MapKey::try_from(self)
+    }
+}
@@ -0,0 +1,236 @@
+use super::value::Value;
+use std::fmt::{self, Display};
+macro_rules! define_opcodes {
+    ($($x:ident),* $(,)?) => {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        #[repr(u8)]
+        pub enum OpCode {
+            $($x,)*
+        }
+        impl OpCode {
+            const ALL: &'static [OpCode] = &[$(OpCode::$x,)*];
+            #[inline(always)]
+            pub fn from_byte(byte: u8) -> Option<OpCode> {
+                Self::ALL.get(byte as usize).copied()
+            }
+        }
+    };
+}
+define_opcodes!(
+    Constant,
+    Nil,
+    True,
+    False,
+    Pop,
+    GetLocal,
+    SetLocal,
+    GetGlobal,
+    DefineGlobal,
+    SetGlobal,
+    GetUpvalue,
+    SetUpvalue,
+    GetProperty,
+    SetProperty,
+    GetSuper,
+    Equal,
+    NotEqual,
+    Greater,
+    GreaterEqual,
+    Less,
+    LessEqual,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Not,
+    Negate,
+    Print,
+    Jump,
+    JumpIfFalse,
+    Loop,
+    Call,
+    Invoke,
+    Closure,
+    CloseUpvalue,
+    Return,
+    Class,
+    Inherit,
+    Method,
+    BuildList,
+    BuildMap,
+    GetIndex,
+    SetIndex,
+    PushHandler,
+    PushFinally,
+    PopHandler,
+    Throw,
+    EndFinally,
+    Interpolate,
+);
+/// A compiled unit of bytecode. Operands follow their opcode inline: constant,
+/// global and property indices, jump offsets and list, map and interpolation
+/// lengths are two bytes (big endian), local slots, upvalue slots and argument
+/// counts are one byte.
+#[derive(Default)]
+pub struct Chunk {
+    pub code: Vec<u8>,
+    pub constants: Vec<Value>,
+    // (offset of first instruction, line), run-length encoded
+    lines: Vec<(usize, usize)>,
+}
+impl Chunk {
+    pub fn write(&mut self, byte: u8, line: usize) {
+        if self.lines.last().is_none_or(|&(_, l)| l != line) {
+            self.lines.push((self.code.len(), line));
+        }
+        self.code.push(byte);
+    }
+    pub fn write_op(&mut self, op: OpCode, line: usize) {
+        self.write(op as u8, line);
+    }
+    pub fn write_u16(&mut self, value: u16, line: usize) {
+        self.write((value >> 8) as u8, line);
+        self.write(value as u8, line);
+    }
+    pub fn add_constant(&mut self, value: Value) -> usize {
+        self.constants.push(value);
+        self.constants.len() - 1
+    }
+    #[inline(always)]
+    pub fn read_u16(&self, offset: usize) -> u16 {
+        ((self.code[offset] as u16) << 8) | self.code[offset + 1] as u16
+    }
+    pub fn line_at(&self, offset: usize) -> usize {
+        let idx = self.lines.partition_point(|&(start, _)| start <= offset);
+        self.lines[idx.saturating_sub(1)].1
+    }
+    fn disassemble_instruction(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        offset: usize,
+    ) -> Result<usize, fmt::Error> {
+        write!(f, "{:04} {:>4} ", offset, self.line_at(offset))?;
+        let Some(op) = OpCode::from_byte(self.code[offset]) else {
+            writeln!(f, "Unknown opcode {}", self.code[offset])?;
+            return Ok(offset + 1);
+        };
+        match op {
+            OpCode::Constant
+            | OpCode::GetGlobal
+            | OpCode::DefineGlobal
+            | OpCode::SetGlobal
+            | OpCode::GetProperty
+            | OpCode::SetProperty
+            | OpCode::GetSuper
+            | OpCode::Class
+            | OpCode::Method => {
+                let idx = self.read_u16(offset + 1) as usize;
+                writeln!(
+                    f,
+                    "{:<16} {:4} '{:?}'",
+                    format!("{:?}", op),
+                    idx,
+                    self.constants[idx]
+                )?;
+                Ok(offset + 3)
+            }
+            OpCode::GetLocal
+            | OpCode::SetLocal
+            | OpCode::GetUpvalue
+            | OpCode::SetUpvalue
+            | OpCode::Call => {
+                writeln!(f, "{:<16} {:4}", format!("{:?}", op), self.code[offset + 1])?;
+                Ok(offset + 2)
+            }
+            OpCode::BuildList | OpCode::BuildMap | OpCode::Interpolate => {
+                let count = self.read_u16(offset + 1);
+                writeln!(f, "{:<16} {:4}", format!("{:?}", op), count)?;
+                Ok(offset + 3)
+            }
+            OpCode::Jump | OpCode::JumpIfFalse | OpCode::PushHandler | OpCode::PushFinally => {
+                let jump = self.read_u16(offset + 1) as usize;
+                writeln!(
+                    f,
+                    "{:<16} {:4} -> {}",
+                    format!("{:?}", op),
+                    offset,
+                    offset + 3 + jump
+                )?;
+                Ok(offset + 3)
+            }
+            OpCode::Loop => {
+                let jump = self.read_u16(offset + 1) as usize;
+                writeln!(
+                    f,
+                    "{:<16} {:4} -> {}",
+                    format!("{:?}", op),
+                    offset,
+                    offset + 3 - jump
+                )?;
+                Ok(offset + 3)
+            }
+            OpCode::Invoke => {
+                let idx = self.read_u16(offset + 1) as usize;
+                let argc = self.code[offset + 3];
+                writeln!(
+                    f,
+                    "{:<16} ({} args) {:4} '{:?}'",
+                    format!("{:?}", op),
+                    argc,
+                    idx,
+                    self.constants[idx]
+                )?;
+                Ok(offset + 4)
+            }
+            OpCode::Closure => {
+                let idx = self.read_u16(offset + 1) as usize;
+                writeln!(
+                    f,
+                    "{:<16} {:4} '{:?}'",
+                    format!("{:?}", op),
+                    idx,
+                    self.constants[idx]
+                )?;
+                let Value::Function(function) = &self.constants[idx] else {
+                    return Ok(offset + 3);
+                };
+                let mut offset = offset + 3;
+                for _ in 0..function.upvalue_count {
+                    let kind = if self.code[offset] == 1 {
+                        "local"
+                    } else {
+                        "upvalue"
+                    };
+                    writeln!(
+                        f,
+                        "{:04}    |                     {} {}",
+                        offset,
+                        kind,
+                        self.code[offset + 1]
+                    )?;
+                    offset += 2;
+                }
+                Ok(offset)
+            }
+            _ => {
+                writeln!(f, "{:?}", op)?;
+                Ok(offset + 1)
+            }
+        }
+    }
+}
+impl Display for Chunk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut offset = 0;
+        while offset < self.code.len() {
+            offset = self.disassemble_instruction(f, offset)?;
+        }
+        for constant in self.constants.iter() {
+            if let Value::Function(function) = constant {
+                writeln!(f, "== {} ==", function)?;
+                write!(f, "{}", function.chunk)?;
+            }
+        }
+        Ok(())
+    }
+}
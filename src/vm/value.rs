@@ -0,0 +1,232 @@
+use super::chunk::Chunk;
+use crate::syntax::token::{Map, MapKey};
+use rustc_hash::FxHashMap;
+use std::cell::RefCell;
+use std::fmt::{self, Display};
+use std::rc::Rc;
+#[derive(Clone)]
+pub enum Value {
+    Nil,
+    Boolean(bool),
+    Number(f64),
+    String(Rc<str>),
+    Function(Rc<ObjFunction>),
+    Closure(Rc<Closure>),
+    Native(Rc<NativeFn>),
+    Class(Rc<RefCell<ObjClass>>),
+    Instance(Rc<RefCell<ObjInstance>>),
+    BoundMethod(Rc<BoundMethod>),
+    List(Rc<RefCell<Vec<Value>>>),
+    Map(Rc<RefCell<Map<Value>>>),
+}
+impl Value {
+    pub fn as_number(&self) -> Option<f64> {
+        match self {
+            Value::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            Value::Nil => false,
+            Value::Boolean(b) => *b,
+            _ => true,
+        }
+    }
+}
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Nil, Value::Nil) => true,
+            (Value::Boolean(a), Value::Boolean(b)) => a == b,
+            (Value::Number(a), Value::Number(b)) => a == b,
+            (Value::String(a), Value::String(b)) => a == b,
+            (Value::Function(a), Value::Function(b)) => Rc::ptr_eq(a, b),
+            (Value::Closure(a), Value::Closure(b)) => Rc::ptr_eq(&a.function, &b.function),
+            (Value::Native(a), Value::Native(b)) => Rc::ptr_eq(a, b),
+            (Value::Class(a), Value::Class(b)) => Rc::ptr_eq(a, b),
+            (Value::Instance(a), Value::Instance(b)) => Rc::ptr_eq(a, b),
+            (Value::BoundMethod(a), Value::BoundMethod(b)) => {
+                Rc::ptr_eq(&a.method.function, &b.method.function)
+            }
+            (Value::List(a), Value::List(b)) => Rc::ptr_eq(a, b),
+            (Value::Map(a), Value::Map(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+impl Value {
+    fn display(&self, f: &mut fmt::Formatter<'_>, seen: &mut Vec<*const ()>) -> fmt::Result {
+        match self {
+            Value::Nil => write!(f, "nil"),
+            Value::Boolean(b) => write!(f, "{}", b),
+            Value::Number(n) => write!(f, "{:.}", *n),
+            Value::String(s) => write!(f, "{}", s),
+            Value::Function(func) => write!(f, "{}", func),
+            Value::Closure(closure) => write!(f, "{}", closure.function),
+            Value::Native(native) => write!(f, "native function {}", native.name),
+            Value::Class(class) => write!(f, "{}", class.borrow().name),
+            Value::Instance(instance) => {
+                write!(f, "{} instance", instance.borrow().class.borrow().name)
+            }
+            Value::BoundMethod(bound) => write!(f, "{}", bound.method.function),
+            Value::List(list) => {
+                let ptr = Rc::as_ptr(list) as *const ();
+                if seen.contains(&ptr) {
+                    return write!(f, "[...]");
+                }
+                seen.push(ptr);
+                write!(f, "[")?;
+                for (i, item) in list.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    match item {
+                        Value::String(s) => write!(f, "\"{}\"", s)?,
+                        item => item.display(f, seen)?,
+                    }
+                }
+                seen.pop();
+                write!(f, "]")
+            }
+            Value::Map(map) => {
+                let ptr = Rc::as_ptr(map) as *const ();
+                if seen.contains(&ptr) {
+                    return write!(f, "{{...}}");
+                }
+                seen.push(ptr);
+                write!(f, "{{")?;
+                for (i, (key, value)) in map.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: ", key)?;
+                    match value {
+                        Value::String(s) => write!(f, "\"{}\"", s)?,
+                        value => value.display(f, seen)?,
+                    }
+                }
+                seen.pop();
+                write!(f, "}}")
+            }
+        }
+    }
+}
+/// Mirrors `Literal`'s `Display` so both backends print the same text.
+impl Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.display(f, &mut Vec::new())
+    }
+}
+impl TryFrom<&Value> for MapKey {
+    type Error = String;
+    fn try_from(value: &Value) -> Result<Self, String> {
+        match value {
+            Value::Nil => Ok(MapKey::Nil),
+            Value::Boolean(b) => Ok(MapKey::Boolean(*b)),
+            Value::Number(n) => MapKey::number(*n),
+            Value::String(s) => Ok(MapKey::String(s.to_string())),
+            _ => Err(MapKey::invalid()),
+        }
+    }
+}
+impl From<&MapKey> for Value {
+    fn from(key: &MapKey) -> Self {
+        match key {
+            MapKey::Nil => Value::Nil,
+            MapKey::Boolean(b) => Value::Boolean(*b),
+            MapKey::Number(bits) => Value::Number(f64::from_bits(*bits)),
+            MapKey::String(s) => Value::String(Rc::from(s.as_str())),
+        }
+    }
+}
+impl fmt::Debug for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::String(s) => write!(f, "{:?}", s),
+            _ => write!(f, "{}", self),
+        }
+    }
+}
+#[derive(Default)]
+pub struct ObjFunction {
+    /// `None` for the top-level script and for anonymous functions.
+    pub name: Option<Rc<str>>,
+    pub arity: usize,
+    pub upvalue_count: usize,
+    pub chunk: Chunk,
+}
+impl Display for ObjFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.name {
+            Some(name) => write!(f, "function {}", name),
+            // the script itself is never a value a program can print
+            None => write!(f, "anonymous function"),
+        }
+    }
+}
+/// A captured variable. It points into the value stack while the enclosing
+/// frame is alive and owns the value once that frame has returned.
+pub enum Upvalue {
+    Open(usize),
+    Closed(Value),
+}
+pub type UpvalueRef = Rc<RefCell<Upvalue>>;
+pub struct Closure {
+    pub function: Rc<ObjFunction>,
+    pub upvalues: Vec<UpvalueRef>,
+}
+impl Closure {
+    pub fn new(function: Rc<ObjFunction>) -> Self {
+        Self {
+            upvalues: Vec::with_capacity(function.upvalue_count),
+            function,
+        }
+    }
+}
+/// The body of a native; returns the message of a runtime error on failure.
+pub type NativeFnBody = dyn Fn(&[Value]) -> Result<Value, String>;
+pub struct NativeFn {
+    pub name: String,
+    pub arity: usize,
+    pub func: Box<NativeFnBody>,
+}
+pub struct ObjClass {
+    pub name: Rc<str>,
+    /// Methods including inherited ones, which `Inherit` copies down.
+    pub methods: FxHashMap<Rc<str>, Rc<Closure>>,
+    pub superclass: Option<Rc<RefCell<ObjClass>>>,
+}
+impl ObjClass {
+    pub fn new(name: Rc<str>) -> Self {
+        Self {
+            name,
+            methods: FxHashMap::default(),
+            superclass: None,
+        }
+    }
+    /// Whether this class is `ancestor` or inherits from it.
+    pub fn descends_from(&self, ancestor: &RefCell<ObjClass>) -> bool {
+        std::ptr::eq(self, ancestor.as_ptr())
+            || self
+                .superclass
+                .as_ref()
+                .is_some_and(|superclass| superclass.borrow().descends_from(ancestor))
+    }
+}
+pub struct ObjInstance {
+    pub class: Rc<RefCell<ObjClass>>,
+    pub fields: FxHashMap<Rc<str>, Value>,
+}
+impl ObjInstance {
+    pub fn new(class: Rc<RefCell<ObjClass>>) -> Self {
+        Self {
+            class,
+            fields: FxHashMap::default(),
+        }
+    }
+}
+pub struct BoundMethod {
+    pub receiver: Value,
+    pub method: Rc<Closure>,
+}
@@ -0,0 +1,172 @@
+//! Runs `.lox` fixtures and checks them against the annotations in their
+//! comments, in the format of the official Lox test suite:
+//!
+//! - `// expect: text` for each line the script prints,
+//! - `// expect runtime error: message` for an error raised on that line,
+//! - `// [line N] Error ...` or `// Error ...` for a compile error on line N
+//!   or on the comment's own line.
+//!
+//! Annotations for other implementations, like `// [c line N]`, are ignored.
+use rlox::diagnostic::{Diagnostic, Phase};
+use rlox::interpreter::Interpreter;
+use rlox::runner::run;
+use rlox::vm::Vm;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+/// Native stack for the thread running the fixtures, enough for
+/// `function/deep_recursion.lox` in a debug build.
+const STACK_SIZE: usize = 64 << 20;
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    Interpreter,
+    Vm,
+}
+/// What a fixture's annotations say running it should do.
+#[derive(Debug, Default, PartialEq)]
+pub struct Expectations {
+    /// Printed lines, with the line of the annotation.
+    pub output: Vec<(usize, String)>,
+    /// Compile error headers, like `[line 3] Error at 'x': message`.
+    pub errors: Vec<String>,
+    pub runtime_error: Option<(usize, String)>,
+}
+impl Expectations {
+    pub fn parse(source: &str) -> Self {
+        let mut expectations = Self::default();
+        for (index, text) in source.lines().enumerate() {
+            let line = index + 1;
+            if let Some(output) = after(text, "// expect: ") {
+                expectations.output.push((line, output.to_owned()));
+            } else if let Some(message) = after(text, "// expect runtime error: ") {
+                expectations.runtime_error = Some((line, message.to_owned()));
+            } else if let Some(error) = after(text, "// [line ") {
+                expectations.errors.push(format!("[line {error}"));
+            } else if let Some(error) = after(text, "// Error") {
+                expectations
+                    .errors
+                    .push(format!("[line {line}] Error{error}"));
+            }
+        }
+        expectations
+    }
+}
+fn after<'a>(text: &'a str, marker: &str) -> Option<&'a str> {
+    text.find(marker).map(|at| &text[at + marker.len()..])
+}
+/// Runs `source` and returns what it printed and the diagnostics it raised.
+pub fn run_source(source: &str, backend: Backend) -> (String, Vec<Diagnostic>) {
+    let (captured, result) = match backend {
+        Backend::Interpreter => {
+            let mut interpreter = Interpreter::default();
+            interpreter.set_stack_limit(STACK_SIZE - (4 << 20));
+            let captured = interpreter.capture_output();
+            (captured, run(source, &mut interpreter))
+        }
+        Backend::Vm => {
+            let mut vm = Vm::default();
+            let captured = vm.capture_output();
+            (captured, run(source, &mut vm))
+        }
+    };
+    (captured.text(), result.err().unwrap_or_default())
+}
+/// Compares a run of `source` with its annotations and describes every
+/// difference, one per line. Returns an empty string if they agree.
+pub fn check_source(source: &str, backend: Backend) -> String {
+    let expected = Expectations::parse(source);
+    let (output, diagnostics) = run_source(source, backend);
+    let mut problems = String::new();
+    let printed: Vec<_> = output.lines().collect();
+    for (index, (line, text)) in expected.output.iter().enumerate() {
+        match printed.get(index) {
+            Some(actual) if actual == text => {}
+            Some(actual) => writeln!(
+                problems,
+                "line {line}: expected output `{text}`, got `{actual}`"
+            )
+            .unwrap(),
+            None => writeln!(problems, "line {line}: missing output `{text}`").unwrap(),
+        }
+    }
+    for actual in printed.iter().skip(expected.output.len()) {
+        writeln!(problems, "unexpected output `{actual}`").unwrap();
+    }
+    let (runtime, compile): (Vec<_>, Vec<_>) = diagnostics
+        .iter()
+        .filter(|d| d.is_error())
+        .partition(|d| matches!(d.phase, Phase::Runtime | Phase::Interrupted));
+    let headers: Vec<_> = compile.iter().map(|d| header(d)).collect();
+    for error in &expected.errors {
+        if !headers.contains(error) {
+            writeln!(problems, "missing error `{error}`").unwrap();
+        }
+    }
+    for actual in &headers {
+        if !expected.errors.contains(actual) {
+            writeln!(problems, "unexpected error `{actual}`").unwrap();
+        }
+    }
+    match (&expected.runtime_error, runtime.first()) {
+        (Some((line, message)), Some(actual))
+            if actual.line == *line && actual.message == *message => {}
+        (Some((line, message)), Some(actual)) => writeln!(
+            problems,
+            "line {line}: expected runtime error `{message}`, got `{}`",
+            header(actual)
+        )
+        .unwrap(),
+        (Some((line, message)), None) => {
+            writeln!(problems, "line {line}: missing runtime error `{message}`").unwrap()
+        }
+        (None, Some(actual)) => {
+            writeln!(problems, "unexpected runtime error `{}`", header(actual)).unwrap()
+        }
+        (None, None) => {}
+    }
+    problems
+}
+// the first line of a diagnostic, without the snippet and trace
+fn header(diagnostic: &Diagnostic) -> String {
+    let text = diagnostic.to_string();
+    text.lines().next().unwrap_or_default().to_owned()
+}
+/// Checks every `.lox` file directly inside `dir` and panics with the
+/// differences found in all of them.
+pub fn check_dir(dir: &str, backend: Backend) {
+    let mut files: Vec<PathBuf> = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .filter(|p| p.extension().is_some_and(|ext| ext == "lox"))
+        .collect();
+    files.sort();
+    assert!(!files.is_empty(), "no fixtures in {dir}");
+    check_files(files, backend);
+}
+fn check_files(files: Vec<PathBuf>, backend: Backend) {
+    // fixtures recurse deeper than the default test thread allows
+    let report = std::thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(move || {
+            let mut report = String::new();
+            for file in &files {
+                let problems = check_source(&read(file), backend);
+                if !problems.is_empty() {
+                    writeln!(report, "{} ({backend:?}):", file.display()).unwrap();
+                    for problem in problems.lines() {
+                        writeln!(report, "  {problem}").unwrap();
+                    }
+                }
+            }
+            report
+        })
+        .unwrap()
+        .join()
+        .unwrap();
+    assert!(
+        report.is_empty(),
+        "fixtures disagree with their annotations:\n{report}"
+    );
+}
+fn read(path: &Path) -> String {
+    std::fs::read_to_string(path).unwrap_or_else(|e| panic!("{}: {e}", path.display()))
+}
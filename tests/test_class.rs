@@ -1,4 +1,6 @@
-use rlox::runner::run_file;
+use rlox::diagnostic::Phase;
+use rlox::interpreter::Interpreter;
+use rlox::runner::{run_file, run_file_with};
 #[test]
 fn empty() {
     run_file("test_data/class/empty.lox");
@@ -16,16 +18,32 @@ fn local_inherit_other() {
     run_file("test_data/class/local_inherit_other.lox");
 }
 #[test]
-#[should_panic]
 fn inherit_self() {
-    run_file("test_data/class/inherit_self.lox");
+    let errors = run_file_with(
+        "test_data/class/inherit_self.lox",
+        &mut Interpreter::default(),
+    )
+    .unwrap_err();
+    assert_eq!(errors[0].phase, Phase::Resolving);
 }
 #[test]
 fn inherited_method() {
     run_file("test_data/class/inherited_method.lox");
 }
 #[test]
-#[should_panic]
 fn local_inherit_self() {
-    run_file("test_data/class/local_inherit_self.lox");
+    let errors = run_file_with(
+        "test_data/class/local_inherit_self.lox",
+        &mut Interpreter::default(),
+    )
+    .unwrap_err();
+    assert_eq!(errors[0].phase, Phase::Resolving);
+}
+#[test]
+fn super_call() {
+    run_file_with(
+        "test_data/class/super_call.lox",
+        &mut Interpreter::default(),
+    )
+    .unwrap();
 }
@@ -0,0 +1,89 @@
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+fn rlox(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_rlox"))
+        .args(args)
+        .output()
+        .expect("failed to run rlox")
+}
+fn exit_code(args: &[&str]) -> Option<i32> {
+    rlox(args).status.code()
+}
+#[test]
+fn success() {
+    assert_eq!(exit_code(&["test_data/precedence.lox"]), Some(0));
+    assert_eq!(exit_code(&["--vm", "test_data/precedence.lox"]), Some(0));
+}
+#[test]
+fn compile_error() {
+    assert_eq!(exit_code(&["test_data/unexpected_char.lox"]), Some(65));
+    assert_eq!(exit_code(&["test_data/if/var_in_then.lox"]), Some(65));
+    assert_eq!(
+        exit_code(&["--vm", "test_data/if/var_in_then.lox"]),
+        Some(65)
+    );
+}
+#[test]
+fn runtime_error() {
+    assert_eq!(exit_code(&["test_data/list/pop_empty.lox"]), Some(70));
+    assert_eq!(
+        exit_code(&["--vm", "test_data/list/pop_empty.lox"]),
+        Some(70)
+    );
+}
+#[test]
+fn unreadable_file() {
+    let output = rlox(&["test_data/missing.lox"]);
+    assert_eq!(output.status.code(), Some(74));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.starts_with("Error: Could not read 'test_data/missing.lox': "),
+        "{stderr}"
+    );
+    assert_eq!(exit_code(&["--vm", "test_data"]), Some(74));
+}
+#[test]
+fn usage() {
+    let output = rlox(&["a.lox", "b.lox"]);
+    assert_eq!(output.status.code(), Some(64));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("[--vm] [script]"));
+}
+/// Runs the prompt on `input`, keeping its history out of the real home
+/// directory.
+fn repl(args: &[&str], input: &str) -> String {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rlox"))
+        .args(args)
+        .env("HOME", env!("CARGO_TARGET_TMPDIR"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("failed to run rlox");
+    let mut stdin = child.stdin.take().unwrap();
+    stdin.write_all(input.as_bytes()).unwrap();
+    drop(stdin);
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).unwrap()
+}
+#[test]
+fn repl_multi_line_input() {
+    let input = "fun add(a, b) {\n  return a + b;\n}\nadd(1, 2);\nvar b = 1;\n";
+    assert_eq!(repl(&[], input), "3\n");
+    assert_eq!(repl(&["--vm"], input), "3\n");
+}
+#[test]
+fn repl_commands() {
+    let input = "var a = 1;\n:env\n:ast 1 + 2 * -x\n:reset\n:env\n:quit\nprint 1;\n";
+    let output = repl(&[], input);
+    assert!(output.contains("\na = 1\n"), "{output}");
+    assert!(output.contains("(+ 1 (* 2 (- x)))\n"), "{output}");
+    // the session after :reset no longer has `a`, and :quit stops reading
+    assert_eq!(output.matches("a = 1").count(), 1, "{output}");
+    assert!(!output.lines().any(|line| line == "1"), "{output}");
+}
+#[test]
+fn repl_load_and_time() {
+    let output = repl(&[], ":load test_data/precedence.lox\n:time 2 * 3;\n");
+    assert!(output.starts_with("14\n8\n"), "{output}");
+    assert!(output.contains("6\ntook "), "{output}");
+}
@@ -0,0 +1,252 @@
+use rlox::diagnostic::Phase;
+use rlox::interpreter::CallFrame;
+use rlox::output::Output;
+use rlox::{Engine, Literal, VisitorError};
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+#[test]
+fn eval_returns_last_expression() {
+    let mut engine = Engine::new();
+    assert_eq!(engine.eval("1 + 2;").unwrap(), Literal::Number(3.0));
+    assert_eq!(engine.eval("var a = 1;").unwrap(), Literal::Nil);
+    assert_eq!(
+        engine.eval("a = a + 1; a * 10;").unwrap(),
+        Literal::Number(20.0)
+    );
+}
+#[test]
+fn eval_reports_diagnostics() {
+    let mut engine = Engine::new();
+    let errors = engine.eval("print 1 +;").unwrap_err();
+    assert_eq!(errors[0].line, 1);
+    let errors = engine.eval("-\"a\";").unwrap_err();
+    assert_eq!(errors[0].message, "Unary - must be used with a number.");
+}
+#[test]
+fn error_in_code_from_an_earlier_eval() {
+    let mut engine = Engine::new();
+    engine.eval("fun f() {\n  return 1 + nil; }").unwrap();
+    let errors = engine.eval("\nf(); // éééééééééééééééééééé").unwrap_err();
+    assert_eq!(errors[0].line, 2);
+    assert_eq!(errors[0].message, "Operands must be two numbers.");
+    // the second input's line 2 is not where the error is
+    assert_eq!(errors[0].snippet, None);
+}
+#[test]
+fn globals() {
+    let mut engine = Engine::new();
+    engine.set_global("greeting", "hello");
+    engine.set_global("count", 2.0);
+    engine.eval("var doubled = count * 2;").unwrap();
+    assert_eq!(engine.get_global("doubled"), Some(Literal::Number(4.0)));
+    assert_eq!(
+        engine.get_global("greeting"),
+        Some(Literal::String("hello".to_owned()))
+    );
+    assert_eq!(engine.get_global("missing"), None);
+}
+#[test]
+fn call_lox_function() {
+    let mut engine = Engine::new();
+    engine
+        .eval("fun add(a, b) { return a + b; } class Point { init(x) { this.x = x; } }")
+        .unwrap();
+    let sum = engine
+        .call("add", &[Literal::Number(1.0), Literal::Number(2.0)])
+        .unwrap();
+    assert_eq!(sum, Literal::Number(3.0));
+    assert!(matches!(
+        engine.call("Point", &[Literal::Number(1.0)]),
+        Ok(Literal::Instance(_))
+    ));
+    let errors = engine.call("add", &[]).unwrap_err();
+    assert_eq!(errors[0].message, "Expected 2 arguments but got 0.");
+    let errors = engine.call("nope", &[]).unwrap_err();
+    assert_eq!(errors[0].message, "Undefined variable 'nope'.");
+}
+#[test]
+fn register_rust_function() {
+    let mut engine = Engine::new();
+    engine.register("square", 1, |_, args| match args[0] {
+        Literal::Number(n) => Ok(Literal::Number(n * n)),
+        _ => Err(VisitorError::runtime("square() takes a number.")),
+    });
+    engine.register_variadic("count", |_, args| Ok(Literal::Number(args.len() as f64)));
+    assert_eq!(engine.eval("square(3);").unwrap(), Literal::Number(9.0));
+    assert_eq!(
+        engine.eval("count(1, 2, 3);").unwrap(),
+        Literal::Number(3.0)
+    );
+    let errors = engine.eval("square(nil);").unwrap_err();
+    assert_eq!(errors[0].message, "square() takes a number.");
+}
+#[test]
+fn gc_stats() {
+    let mut engine = Engine::new();
+    engine
+        .eval("class Node {} for (var i = 0; i < 10; i = i + 1) { var n = Node(); n.self = n; }")
+        .unwrap();
+    assert_eq!(engine.collect_garbage(), 10);
+    let stats = engine.gc_stats();
+    assert_eq!(stats.collections, 1);
+    assert_eq!(stats.collected, 10);
+}
+#[test]
+fn call_stack() {
+    let mut engine = Engine::new();
+    engine.register("callers", 0, |interpreter, _| {
+        let names = interpreter
+            .call_stack()
+            .iter()
+            .map(|frame| Literal::String(frame.to_string()))
+            .collect::<Vec<_>>();
+        Ok(names.into())
+    });
+    let callers = engine
+        .eval("fun inner() { return callers(); }\nfun outer() { return inner(); }\nouter();")
+        .unwrap();
+    let Literal::List(callers) = callers else {
+        panic!("callers() did not return a list");
+    };
+    assert_eq!(
+        *callers.borrow(),
+        vec![
+            Literal::String("at outer (line 3)".to_owned()),
+            Literal::String("at inner (line 2)".to_owned()),
+        ]
+    );
+    let errors = engine
+        .eval("fun fail() { return nil.x; }\nfun run() { fail(); }\nrun();")
+        .unwrap_err();
+    assert_eq!(
+        errors[0].trace,
+        vec![
+            CallFrame {
+                name: "fail".to_owned(),
+                line: 2,
+            },
+            CallFrame {
+                name: "run".to_owned(),
+                line: 3,
+            },
+        ]
+    );
+    // a call from Rust has no call site
+    let errors = engine.call("fail", &[]).unwrap_err();
+    assert_eq!(errors[0].trace[0].to_string(), "at fail");
+}
+#[test]
+fn max_call_depth() {
+    let mut engine = Engine::new();
+    engine.set_max_call_depth(10);
+    engine
+        .eval("fun depth(n) { if (n == 0) return 0; return 1 + depth(n - 1); }")
+        .unwrap();
+    assert_eq!(engine.eval("depth(9);").unwrap(), Literal::Number(9.0));
+    let errors = engine.eval("depth(10);").unwrap_err();
+    assert_eq!(errors[0].message, "Stack overflow.");
+    // the failed call unwound completely
+    assert_eq!(engine.eval("depth(9);").unwrap(), Literal::Number(9.0));
+}
+#[test]
+fn fuel() {
+    let mut engine = Engine::new();
+    engine.set_fuel(Some(100));
+    let errors = engine
+        .eval("var i = 0; while (true) { i = i + 1; }")
+        .unwrap_err();
+    assert_eq!(errors[0].phase, Phase::Interrupted);
+    assert_eq!(errors[0].message, "Execution budget exhausted.");
+    assert_eq!(engine.fuel(), Some(0));
+    assert_eq!(engine.get_global("i"), Some(Literal::Number(100.0)));
+    // more fuel lets the same engine carry on
+    engine.add_fuel(10);
+    assert_eq!(
+        engine.eval("i = i + 1; i;").unwrap(),
+        Literal::Number(101.0)
+    );
+    assert_eq!(engine.fuel(), Some(10));
+    // calls are steps too, so recursion runs out without any loop
+    engine.eval("fun forever() { forever(); }").unwrap();
+    let errors = engine.eval("forever();").unwrap_err();
+    assert_eq!(errors[0].phase, Phase::Interrupted);
+    engine.set_fuel(None);
+    assert_eq!(engine.eval("i;").unwrap(), Literal::Number(101.0));
+}
+#[test]
+fn interrupts_are_not_catchable() {
+    let mut engine = Engine::new();
+    engine.set_fuel(Some(1000));
+    let errors = engine
+        .eval(
+            "var caught = false;
+             try { while (true) {} } catch (e) { caught = true; }",
+        )
+        .unwrap_err();
+    assert_eq!(errors[0].phase, Phase::Interrupted);
+    assert_eq!(engine.get_global("caught"), Some(Literal::Boolean(false)));
+}
+// a `return` from `finally` would otherwise swallow the interrupt, and the
+// loop around it would carry on
+const RETURN_FROM_FINALLY: &str = "var n = 0;
+     fun f() { try { while (true) {} } finally { print \"finally\"; return 1; } }
+     while (true) { n = n + f(); }";
+#[test]
+fn fuel_skips_finally() {
+    let mut engine = Engine::new();
+    let output = engine.capture_output();
+    engine.set_fuel(Some(1000));
+    let errors = engine.eval(RETURN_FROM_FINALLY).unwrap_err();
+    assert_eq!(errors[0].message, "Execution budget exhausted.");
+    assert_eq!(output.text(), "");
+    assert_eq!(engine.get_global("n"), Some(Literal::Number(0.0)));
+}
+#[test]
+fn timeout_skips_finally() {
+    let mut engine = Engine::new();
+    let output = engine.capture_output();
+    engine.set_timeout(Duration::from_millis(20));
+    let start = Instant::now();
+    let errors = engine.eval(RETURN_FROM_FINALLY).unwrap_err();
+    assert_eq!(errors[0].message, "Execution timed out.");
+    assert!(start.elapsed() < Duration::from_secs(5));
+    assert_eq!(output.text(), "");
+    assert_eq!(engine.get_global("n"), Some(Literal::Number(0.0)));
+}
+#[test]
+fn timeout() {
+    let mut engine = Engine::new();
+    engine.set_timeout(Duration::from_millis(20));
+    let start = Instant::now();
+    let errors = engine.eval("while (true) {}").unwrap_err();
+    assert_eq!(errors[0].phase, Phase::Interrupted);
+    assert_eq!(errors[0].message, "Execution timed out.");
+    assert!(start.elapsed() < Duration::from_secs(5));
+    engine.set_deadline(None);
+    assert_eq!(engine.eval("1 + 1;").unwrap(), Literal::Number(2.0));
+}
+#[test]
+fn capture_output() {
+    let mut engine = Engine::new();
+    let output = engine.capture_output();
+    engine
+        .eval("print 1 + 2; print \"two\"; print [nil, true];")
+        .unwrap();
+    assert_eq!(output.take(), "3\ntwo\n[nil, true]\n");
+    engine.eval("print clock() > 0;").unwrap();
+    assert_eq!(output.text(), "true\n");
+}
+#[test]
+fn output_callback() {
+    let mut engine = Engine::new();
+    let lines = Rc::new(RefCell::new(vec![]));
+    let sink = Rc::clone(&lines);
+    engine.set_output(Output::callback(move |line| {
+        sink.borrow_mut().push(line.to_owned())
+    }));
+    engine
+        .eval("for (var i = 0; i < 3; i = i + 1) print i;")
+        .unwrap();
+    assert_eq!(*lines.borrow(), ["0", "1", "2"]);
+}
@@ -0,0 +1,136 @@
+use rlox::diagnostic::Phase;
+use rlox::interpreter::Interpreter;
+use rlox::runner::run_file_with;
+use rlox::{Engine, ErrorKind, Literal, VisitorError};
+fn run(path: &str) {
+    run_file_with(path, &mut Interpreter::default()).unwrap();
+}
+#[test]
+fn catch() {
+    run("test_data/exception/catch.lox");
+}
+#[test]
+fn catch_scope() {
+    run("test_data/exception/catch_scope.lox");
+}
+#[test]
+fn error_class() {
+    run("test_data/exception/error_class.lox");
+}
+#[test]
+fn runtime_error() {
+    run("test_data/exception/runtime_error.lox");
+}
+#[test]
+fn error_kinds() {
+    run("test_data/exception/error_kinds.lox");
+}
+#[test]
+fn stack() {
+    run("test_data/exception/stack.lox");
+}
+#[test]
+fn finally() {
+    run("test_data/exception/finally.lox");
+}
+#[test]
+fn rethrow() {
+    run("test_data/exception/rethrow.lox");
+}
+#[test]
+fn error_in_catch() {
+    run("test_data/exception/error_in_catch.lox");
+}
+fn uncaught(path: &str) -> (usize, String) {
+    let errors = run_file_with(path, &mut Interpreter::default()).unwrap_err();
+    assert_eq!(errors[0].phase, Phase::Runtime);
+    (errors[0].line, errors[0].message.clone())
+}
+#[test]
+fn uncaught_errors() {
+    assert_eq!(
+        uncaught("test_data/exception/uncaught.lox"),
+        (2, "gave up".to_owned())
+    );
+    assert_eq!(
+        uncaught("test_data/exception/uncaught_value.lox"),
+        (1, "3".to_owned())
+    );
+    // `finally` runs, then the original error carries on
+    assert_eq!(
+        uncaught("test_data/exception/uncaught_after_finally.lox"),
+        (2, "Unary - must be used with a number.".to_owned())
+    );
+}
+#[test]
+fn missing_handler() {
+    let errors = run_file_with(
+        "test_data/exception/missing_handler.lox",
+        &mut Interpreter::default(),
+    )
+    .unwrap_err();
+    assert_eq!(errors[0].phase, Phase::Parsing);
+    assert_eq!(errors[0].line, 4);
+}
+#[test]
+fn caught_error_fields() {
+    let mut engine = Engine::new();
+    let error = engine
+        .eval(
+            "fun divide(a, b) { return a / b; }
+             var caught;
+             try { divide(1, nil); } catch (e) { caught = e; }
+             caught;",
+        )
+        .unwrap();
+    let Literal::Instance(error) = error else {
+        panic!("caught value is not an instance");
+    };
+    let error = error.borrow();
+    assert_eq!(error.class.to_string(), "ArithmeticError");
+    assert_eq!(
+        error.field("message"),
+        Some(&Literal::String("Operands must be two numbers.".to_owned()))
+    );
+    assert_eq!(error.field("line"), Some(&Literal::Number(1.0)));
+    let Some(Literal::List(stack)) = error.field("stack") else {
+        panic!("error has no stack");
+    };
+    assert_eq!(
+        *stack.borrow(),
+        vec![Literal::String("at divide (line 3)".to_owned())]
+    );
+}
+#[test]
+fn shadowed_error_class() {
+    // runtime errors are still instances of the prelude's class
+    let mut engine = Engine::new();
+    engine.eval("var Error = nil;").unwrap();
+    let message = engine
+        .eval("var m; try { nil.x; } catch (e) { m = e.message; } m;")
+        .unwrap();
+    assert_eq!(
+        message,
+        Literal::String("Only instances have properties.".to_owned())
+    );
+}
+#[test]
+fn native_error_kind() {
+    let mut engine = Engine::new();
+    engine.register("sqrt", 1, |_, args| match &args[0] {
+        Literal::Number(n) if *n >= 0.0 => Ok(Literal::Number(n.sqrt())),
+        _ => Err(VisitorError::runtime_of(
+            ErrorKind::Arithmetic,
+            "Can't take the square root of a negative number.",
+        )),
+    });
+    let caught = engine
+        .eval("var e; try { sqrt(-1); } catch (error) { e = error; } \"${e}: ${e.message}\";")
+        .unwrap();
+    assert_eq!(
+        caught,
+        Literal::String(
+            "ArithmeticError instance: Can't take the square root of a negative number.".to_owned()
+        )
+    );
+}
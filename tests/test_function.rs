@@ -1,4 +1,8 @@
-use rlox::runner::run_file;
+use rlox::diagnostic::Phase;
+use rlox::interpreter::{CallFrame, Interpreter};
+use rlox::runner::{run_file, run_file_with};
+use rlox::vm::Vm;
+use rlox::{Engine, Literal};
 #[test]
 fn body_must_be_block() {
     run_file("test_data/function/body_must_be_block.lox");
@@ -48,11 +52,96 @@ fn recursion() {
     run_file("test_data/function/recursion.lox");
 }
 #[test]
-#[should_panic]
 fn too_many_arguments() {
-    run_file("test_data/function/too_many_arguments.lox");
+    let errors = run_file_with(
+        "test_data/function/too_many_arguments.lox",
+        &mut Interpreter::default(),
+    )
+    .unwrap_err();
+    assert_eq!(errors[0].phase, Phase::Parsing);
 }
 #[test]
 fn too_many_parameters() {
     run_file("test_data/function/too_many_parameters.lox");
 }
+//anonymous functions
+#[test]
+fn lambda_argument() {
+    run_file("test_data/lambda/argument.lox");
+}
+#[test]
+fn lambda_call_immediately() {
+    run_file("test_data/lambda/call_immediately.lox");
+}
+#[test]
+fn lambda_closure() {
+    run_file("test_data/lambda/closure.lox");
+}
+#[test]
+fn lambda_missing_arguments() {
+    let errors = run_file_with(
+        "test_data/lambda/missing_arguments.lox",
+        &mut Interpreter::default(),
+    )
+    .unwrap_err();
+    assert_eq!(errors[0].phase, Phase::Runtime);
+}
+#[test]
+fn lambda_print() {
+    let mut engine = Engine::new();
+    let value = engine.eval("fun (a) { return a; };").unwrap();
+    assert_eq!(value.to_string(), "anonymous function");
+    let value = engine.eval("fun (a) { return a; }(4);").unwrap();
+    assert_eq!(value, Literal::from(4.0));
+}
+#[test]
+fn stack_trace() {
+    let path = "test_data/function/error_in_nested_call.lox";
+    let frame = |name: &str, line| CallFrame {
+        name: name.to_owned(),
+        line,
+    };
+    let expected = vec![frame("add", 6), frame("sum", 10), frame("report", 13)];
+    let errors = run_file_with(path, &mut Interpreter::default()).unwrap_err();
+    assert_eq!(errors[0].line, 2);
+    assert_eq!(errors[0].trace, expected);
+    assert!(errors[0]
+        .to_string()
+        .ends_with("  at add (line 6)\n  at sum (line 10)\n  at report (line 13)\n  at script"));
+    let errors = run_file_with(path, &mut Vm::default()).unwrap_err();
+    assert_eq!(errors[0].trace, expected);
+}
+#[test]
+fn top_level_error_has_no_trace() {
+    let errors = run_file_with(
+        "test_data/function/missing_arguments.lox",
+        &mut Interpreter::default(),
+    )
+    .unwrap_err();
+    assert!(errors[0].trace.is_empty());
+}
+#[test]
+fn stack_overflow() {
+    let errors = run_file_with(
+        "test_data/function/stack_overflow.lox",
+        &mut Interpreter::default(),
+    )
+    .unwrap_err();
+    assert_eq!(errors[0].phase, Phase::Runtime);
+    assert_eq!(errors[0].message, "Stack overflow.");
+    assert_eq!(errors[0].line, 2);
+}
+#[test]
+fn deep_recursion() {
+    // deeper than the default native stack limit allows in debug builds
+    std::thread::Builder::new()
+        .stack_size(64 << 20)
+        .spawn(|| {
+            let mut interpreter = Interpreter::default();
+            interpreter.set_stack_limit(32 << 20);
+            run_file_with("test_data/function/deep_recursion.lox", &mut interpreter).unwrap();
+        })
+        .unwrap()
+        .join()
+        .unwrap();
+}
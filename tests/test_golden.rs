@@ -0,0 +1,114 @@
+mod golden;
+use golden::{check_dir, check_source, Backend, Expectations};
+fn check(dir: &str) {
+    check_dir(dir, Backend::Interpreter);
+    check_dir(dir, Backend::Vm);
+}
+#[test]
+fn top_level() {
+    check("test_data");
+}
+#[test]
+fn assignment() {
+    check("test_data/assignment");
+}
+#[test]
+fn block() {
+    check("test_data/block");
+}
+#[test]
+fn break_statement() {
+    check("test_data/break");
+}
+#[test]
+fn class() {
+    check("test_data/class");
+}
+#[test]
+fn closure() {
+    check("test_data/closure");
+}
+#[test]
+fn comments() {
+    check("test_data/comments");
+}
+#[test]
+fn exception() {
+    check("test_data/exception");
+}
+#[test]
+fn for_loop() {
+    check("test_data/for");
+}
+#[test]
+fn function() {
+    check("test_data/function");
+}
+#[test]
+fn if_statement() {
+    check("test_data/if");
+}
+#[test]
+fn lambda() {
+    check("test_data/lambda");
+}
+#[test]
+fn list() {
+    check("test_data/list");
+}
+#[test]
+fn logic() {
+    check("test_data/logic");
+}
+#[test]
+fn map() {
+    check("test_data/map");
+}
+#[test]
+fn number() {
+    check("test_data/number");
+}
+#[test]
+fn string() {
+    check("test_data/string");
+}
+#[test]
+fn this() {
+    check("test_data/this");
+}
+#[test]
+fn while_loop() {
+    check("test_data/while");
+}
+#[test]
+fn parse_annotations() {
+    let source = "print 1; // expect: 1\n\
+                  // [line 3] Error at 'x': oops\n\
+                  x; // Error: here\n\
+                  // [c line 4] Error at end: ignored\n\
+                  f(); // expect runtime error: boom";
+    let expected = Expectations::parse(source);
+    assert_eq!(expected.output, [(1, "1".to_owned())]);
+    assert_eq!(
+        expected.errors,
+        ["[line 3] Error at 'x': oops", "[line 3] Error: here"]
+    );
+    assert_eq!(expected.runtime_error, Some((5, "boom".to_owned())));
+}
+#[test]
+fn report_differences() {
+    let source = "print 1; // expect: 2\nprint 3;\nprint -nil; // expect runtime error: nope";
+    assert_eq!(
+        check_source(source, Backend::Interpreter),
+        "line 1: expected output `2`, got `1`\n\
+         unexpected output `3`\n\
+         line 3: expected runtime error `nope`, got \
+         `[line 3] Error at '-': Unary - must be used with a number.`\n"
+    );
+    let source = "print 1 +; // expect: 1";
+    assert_eq!(
+        check_source(source, Backend::Vm),
+        "line 1: missing output `1`\n\
+         unexpected error `[line 1] Error at ';': expected expression`\n"
+    );
+}
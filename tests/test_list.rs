@@ -0,0 +1,94 @@
+use rlox::diagnostic::Phase;
+use rlox::interpreter::Interpreter;
+use rlox::runner::{run_file, run_file_with};
+use rlox::{Engine, Literal};
+#[test]
+fn literal() {
+    run_file("test_data/list/literal.lox");
+}
+#[test]
+fn index() {
+    run_file("test_data/list/index.lox");
+}
+#[test]
+fn reference() {
+    run_file("test_data/list/reference.lox");
+}
+#[test]
+fn builtins() {
+    run_file("test_data/list/builtins.lox");
+}
+#[test]
+fn loop_over_list() {
+    run_file("test_data/list/loop.lox");
+}
+fn runtime_error(path: &str) -> String {
+    let errors = run_file_with(path, &mut Interpreter::default()).unwrap_err();
+    assert_eq!(errors[0].phase, Phase::Runtime);
+    errors[0].message.clone()
+}
+#[test]
+fn index_out_of_range() {
+    assert_eq!(
+        runtime_error("test_data/list/index_out_of_range.lox"),
+        "Index 3 is out of range for a list of length 3."
+    );
+    assert_eq!(
+        runtime_error("test_data/list/negative_index.lox"),
+        "Index -1 is out of range for a list of length 3."
+    );
+    assert_eq!(
+        runtime_error("test_data/list/remove_out_of_range.lox"),
+        "Index 1 is out of range for a list of length 1."
+    );
+    assert_eq!(
+        runtime_error("test_data/list/insert_out_of_range.lox"),
+        "Index 2 is out of range for a list of length 1."
+    );
+}
+#[test]
+fn invalid_index() {
+    assert_eq!(
+        runtime_error("test_data/list/fractional_index.lox"),
+        "List index must be an integer."
+    );
+    assert_eq!(
+        runtime_error("test_data/list/string_index.lox"),
+        "List index must be a number."
+    );
+    assert_eq!(
+        runtime_error("test_data/list/index_non_list.lox"),
+        "Only lists and maps can be indexed."
+    );
+}
+#[test]
+fn builtin_errors() {
+    assert_eq!(
+        runtime_error("test_data/list/pop_empty.lox"),
+        "Can't pop from an empty list."
+    );
+    assert_eq!(
+        runtime_error("test_data/list/push_non_list.lox"),
+        "push() expects a list."
+    );
+}
+#[test]
+fn missing_bracket() {
+    let errors = run_file_with(
+        "test_data/list/missing_bracket.lox",
+        &mut Interpreter::default(),
+    )
+    .unwrap_err();
+    assert_eq!(errors[0].phase, Phase::Parsing);
+}
+#[test]
+fn list_values() {
+    let mut engine = Engine::new();
+    engine.set_global("xs", vec![1.0.into(), 2.0.into()]);
+    engine.eval("push(xs, 3);").unwrap();
+    let Some(Literal::List(xs)) = engine.get_global("xs") else {
+        panic!("xs is not a list");
+    };
+    assert_eq!(xs.borrow().len(), 3);
+    assert_eq!(engine.eval("xs[2];").unwrap(), Literal::from(3.0));
+}
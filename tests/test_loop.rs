@@ -1,4 +1,6 @@
-use rlox::runner::run_file;
+use rlox::diagnostic::Phase;
+use rlox::interpreter::Interpreter;
+use rlox::runner::{run_file, run_file_with};
 #[test]
 // #[should_panic]
 fn test_while() {
@@ -49,3 +51,42 @@ fn test_for_syntax() {
 fn test_for_var_in_body() {
     run_file("test_data/for/var_in_body.lox");
 }
+//break and continue
+#[test]
+fn test_break_while() {
+    run_file("test_data/break/while.lox");
+}
+#[test]
+fn test_continue_while() {
+    run_file("test_data/break/continue_while.lox");
+}
+#[test]
+fn test_continue_runs_increment() {
+    run_file("test_data/break/continue_runs_increment.lox");
+}
+#[test]
+fn test_break_nested() {
+    run_file("test_data/break/nested.lox");
+}
+#[test]
+fn test_break_locals_and_closures() {
+    run_file("test_data/break/locals_and_closures.lox");
+}
+#[test]
+fn test_break_outside_loop() {
+    let errors = run_file_with(
+        "test_data/break/outside_loop.lox",
+        &mut Interpreter::default(),
+    )
+    .unwrap_err();
+    assert_eq!(errors[0].phase, Phase::Resolving);
+}
+#[test]
+fn test_break_in_function_in_loop() {
+    let errors = run_file_with(
+        "test_data/break/in_function_in_loop.lox",
+        &mut Interpreter::default(),
+    )
+    .unwrap_err();
+    assert_eq!(errors[0].phase, Phase::Resolving);
+}
@@ -0,0 +1,78 @@
+use rlox::diagnostic::Phase;
+use rlox::interpreter::Interpreter;
+use rlox::runner::{run_file, run_file_with};
+use rlox::{Engine, Literal};
+#[test]
+fn literal() {
+    run_file("test_data/map/literal.lox");
+}
+#[test]
+fn index() {
+    run_file("test_data/map/index.lox");
+}
+#[test]
+fn order() {
+    run_file("test_data/map/order.lox");
+}
+#[test]
+fn builtins() {
+    run_file("test_data/map/builtins.lox");
+}
+#[test]
+fn reference() {
+    run_file("test_data/map/reference.lox");
+}
+#[test]
+fn block_statement() {
+    run_file("test_data/map/block_statement.lox");
+}
+fn runtime_error(path: &str) -> String {
+    let errors = run_file_with(path, &mut Interpreter::default()).unwrap_err();
+    assert_eq!(errors[0].phase, Phase::Runtime);
+    errors[0].message.clone()
+}
+#[test]
+fn missing_key() {
+    assert_eq!(
+        runtime_error("test_data/map/missing_key.lox"),
+        "Undefined key \"b\"."
+    );
+}
+#[test]
+fn invalid_key() {
+    let message = "Map keys must be strings, numbers, booleans or nil.";
+    assert_eq!(runtime_error("test_data/map/invalid_key.lox"), message);
+    assert_eq!(
+        runtime_error("test_data/map/invalid_literal_key.lox"),
+        message
+    );
+    assert_eq!(
+        runtime_error("test_data/map/nan_key.lox"),
+        "Map keys can't be NaN."
+    );
+}
+#[test]
+fn builtin_errors() {
+    assert_eq!(
+        runtime_error("test_data/map/has_non_map.lox"),
+        "has() expects a map."
+    );
+}
+#[test]
+fn missing_colon() {
+    let errors = run_file_with(
+        "test_data/map/missing_colon.lox",
+        &mut Interpreter::default(),
+    )
+    .unwrap_err();
+    assert_eq!(errors[0].phase, Phase::Parsing);
+}
+#[test]
+fn map_values() {
+    let mut engine = Engine::new();
+    let Literal::Map(map) = engine.eval("var m = {\"b\": 1, \"a\": 2}; m;").unwrap() else {
+        panic!("not a map");
+    };
+    let keys: Vec<Literal> = map.borrow().keys().map(Literal::from).collect();
+    assert_eq!(keys, vec![Literal::from("b"), Literal::from("a")]);
+}
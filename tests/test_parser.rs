@@ -1,8 +1,11 @@
-use rlox::runner::run_file;
+use rlox::diagnostic::Phase;
+use rlox::interpreter::Interpreter;
+use rlox::runner::{parse_expression, run, run_file, run_file_with};
 #[test]
-#[should_panic]
 fn test_parse() {
-    run_file("test_data/unexpected_char.lox");
+    let errors =
+        run_file_with("test_data/unexpected_char.lox", &mut Interpreter::default()).unwrap_err();
+    assert_eq!(errors[0].phase, Phase::Scanning);
 }
 #[test]
 fn test_assignment() {
@@ -77,3 +80,17 @@ fn test_precedence() {
 fn test_empty() {
     run_file("test_data/empty.lox");
 }
+#[test]
+fn test_error_caret() {
+    let source = "var a = \"x\";\nprint (a) * 2;";
+    let errors = run(source, &mut Interpreter::default()).unwrap_err();
+    assert_eq!(errors[0].column, Some(7));
+    assert!(errors[0].to_string().ends_with("\n      |       ^^^^^^^"));
+}
+#[test]
+fn test_interpolation() {
+    let expr = parse_expression(r#""a ${b + 1} c ${"d"}""#).unwrap();
+    assert_eq!(expr.to_string(), "(str a  (+ b 1)  c  d)");
+    let errors = parse_expression(r#""${}""#).unwrap_err();
+    assert_eq!(errors[0].message, "expected expression");
+}
@@ -1,7 +1,36 @@
-use rlox::runner::run_file;
+use rlox::diagnostic::Phase;
+use rlox::interpreter::Interpreter;
+use rlox::runner::{exit_code, run, run_file, run_file_with};
 
 #[test]
-#[should_panic]
 fn run_error() {
-    run_file("test_data/unexpected_char.lox");
+    let errors =
+        run_file_with("test_data/unexpected_char.lox", &mut Interpreter::default()).unwrap_err();
+    assert_eq!(errors[0].phase, Phase::Scanning);
+}
+#[test]
+fn run_collects_all_errors() {
+    let source = "print @;\nprint 1 +;\nvar # = 2;";
+    let errors = run(source, &mut Interpreter::default()).unwrap_err();
+    let found: Vec<_> = errors.iter().map(|e| (e.line, e.phase)).collect();
+    assert_eq!(
+        found,
+        vec![
+            (1, Phase::Scanning),
+            (2, Phase::Parsing),
+            (3, Phase::Scanning)
+        ]
+    );
+}
+#[test]
+fn missing_file() {
+    let errors = run_file_with("test_data/missing.lox", &mut Interpreter::default()).unwrap_err();
+    assert_eq!(errors[0].phase, Phase::Reading);
+    assert_eq!(exit_code(&errors), 74);
+}
+#[test]
+fn run_file_returns_exit_status() {
+    assert_eq!(run_file("test_data/precedence.lox"), 0);
+    assert_eq!(run_file("test_data/unexpected_char.lox"), 65);
+    assert_eq!(run_file("test_data/missing.lox"), 74);
 }
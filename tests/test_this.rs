@@ -1,4 +1,6 @@
-use rlox::runner::run_file;
+use rlox::diagnostic::Phase;
+use rlox::interpreter::Interpreter;
+use rlox::runner::{run_file, run_file_with};
 #[test]
 fn closure() {
     run_file("test_data/this/closure.lox");
@@ -12,16 +14,24 @@ fn nested_class() {
     run_file("test_data/this/nested_class.lox");
 }
 #[test]
-#[should_panic]
 fn this_at_top_level() {
-    run_file("test_data/this/this_at_top_level.lox");
+    let errors = run_file_with(
+        "test_data/this/this_at_top_level.lox",
+        &mut Interpreter::default(),
+    )
+    .unwrap_err();
+    assert_eq!(errors[0].phase, Phase::Resolving);
 }
 #[test]
 fn this_in_method() {
     run_file("test_data/this/this_in_method.lox");
 }
 #[test]
-#[should_panic]
 fn this_in_top_level_function() {
-    run_file("test_data/this/this_in_top_level_function.lox");
-}
\ No newline at end of file
+    let errors = run_file_with(
+        "test_data/this/this_in_top_level_function.lox",
+        &mut Interpreter::default(),
+    )
+    .unwrap_err();
+    assert_eq!(errors[0].phase, Phase::Resolving);
+}
@@ -0,0 +1,235 @@
+use rlox::compiler::Compiler;
+use rlox::interpreter::Interpreter;
+use rlox::resolver::Resolver;
+use rlox::runner::{eval, run, Backend};
+use rlox::syntax::{parser::Parser, tokenizer::Tokenizer};
+use rlox::vm::Vm;
+use std::path::Path;
+use std::process::{Command, Output};
+fn run_binary(file: &Path, vm: bool) -> Output {
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_rlox"));
+    if vm {
+        cmd.arg("--vm");
+    }
+    cmd.arg(file).output().expect("failed to run rlox")
+}
+// the VM only tracks lines, so compare the "[line N] Error..." headers and
+// not the source snippets the tree-walker underlines
+fn error_headers(stderr: &[u8]) -> Vec<String> {
+    String::from_utf8_lossy(stderr)
+        .lines()
+        .filter(|line| line.starts_with("[line"))
+        .map(str::to_owned)
+        .collect()
+}
+fn same_output(dir: &str) {
+    let mut entries: Vec<_> = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .filter(|p| p.extension().is_some_and(|ext| ext == "lox"))
+        .collect();
+    entries.sort();
+    assert!(!entries.is_empty());
+    for file in entries {
+        let tree = run_binary(&file, false);
+        let vm = run_binary(&file, true);
+        assert_eq!(
+            String::from_utf8_lossy(&tree.stdout),
+            String::from_utf8_lossy(&vm.stdout),
+            "stdout differs for {}",
+            file.display()
+        );
+        assert_eq!(
+            tree.status.success(),
+            vm.status.success(),
+            "exit status differs for {}",
+            file.display()
+        );
+        if !tree.status.success() {
+            // panic messages carry thread ids and backtraces
+            continue;
+        }
+        assert_eq!(
+            error_headers(&tree.stderr),
+            error_headers(&vm.stderr),
+            "stderr differs for {}",
+            file.display()
+        );
+    }
+}
+#[test]
+fn assignment() {
+    same_output("test_data/assignment");
+}
+#[test]
+fn block() {
+    same_output("test_data/block");
+}
+#[test]
+fn break_continue() {
+    same_output("test_data/break");
+}
+#[test]
+fn class() {
+    same_output("test_data/class");
+}
+#[test]
+fn closure() {
+    same_output("test_data/closure");
+}
+#[test]
+fn exception() {
+    same_output("test_data/exception");
+}
+#[test]
+fn for_loop() {
+    same_output("test_data/for");
+}
+#[test]
+fn function() {
+    same_output("test_data/function");
+}
+#[test]
+fn if_stmt() {
+    same_output("test_data/if");
+}
+#[test]
+fn lambda() {
+    same_output("test_data/lambda");
+}
+#[test]
+fn list() {
+    same_output("test_data/list");
+}
+#[test]
+fn logic() {
+    same_output("test_data/logic");
+}
+#[test]
+fn map() {
+    same_output("test_data/map");
+}
+#[test]
+fn this() {
+    same_output("test_data/this");
+}
+#[test]
+fn while_loop() {
+    same_output("test_data/while");
+}
+#[test]
+fn capture_output() {
+    let source = "fun greet(name) { print name; } greet(\"vm\"); print 1 / 4;";
+    let mut vm = Vm::default();
+    let captured = vm.capture_output();
+    run(source, &mut vm).unwrap();
+    let mut interpreter = Interpreter::default();
+    let expected = interpreter.capture_output();
+    run(source, &mut interpreter).unwrap();
+    assert_eq!(captured.text(), "vm\n0.25\n");
+    assert_eq!(captured.text(), expected.text());
+}
+// the benches are sized for a release build and print timings, so shrink
+// them and swap in a clock that ticks once per call
+fn bench_source(name: &str, params: &[(&str, &str)]) -> String {
+    let path = format!("test_data/benches/{name}.lox");
+    let mut source = std::fs::read_to_string(path).unwrap();
+    for (from, to) in params {
+        assert!(source.contains(from), "{name} has no {from:?}");
+        source = source.replace(from, to);
+    }
+    format!("var ticks = 0; fun clock() {{ ticks = ticks + 1; return ticks; }}\n{source}")
+}
+fn same_bench_output(name: &str, params: &[(&str, &str)]) {
+    let source = bench_source(name, params);
+    let mut interpreter = Interpreter::default();
+    let expected = interpreter.capture_output();
+    run(&source, &mut interpreter).unwrap();
+    let mut vm = Vm::default();
+    let captured = vm.capture_output();
+    run(&source, &mut vm).unwrap();
+    assert!(!expected.text().is_empty());
+    assert_eq!(
+        captured.text(),
+        expected.text(),
+        "output differs for {name}"
+    );
+}
+#[test]
+fn benches() {
+    same_bench_output("binary_trees", &[("maxDepth = 14;", "maxDepth = 6;")]);
+    same_bench_output("equality", &[("10000000", "1000")]);
+    same_bench_output("zoo_batch", &[("i < 10000;", "i < 100;")]);
+}
+fn echoes(backend: &mut impl Backend) {
+    assert_eq!(eval("1 + 2;", backend).unwrap(), Some("3".to_owned()));
+    assert_eq!(eval("var a = [1];", backend).unwrap(), None);
+    assert_eq!(
+        eval("push(a, 2);\na;", backend).unwrap(),
+        Some("[1, 2]".to_owned())
+    );
+    assert_eq!(eval("print a;", backend).unwrap(), None);
+    let errors = eval("a + 1;", backend).unwrap_err();
+    assert_eq!(errors[0].message, "Operands must be two numbers.");
+    // the backend is still usable after an error
+    assert_eq!(
+        eval("fun f() { return a; } len(f());", backend).unwrap(),
+        Some("2".to_owned())
+    );
+}
+#[test]
+fn eval_echoes_expressions() {
+    let mut interpreter = Interpreter::default();
+    interpreter.capture_output();
+    echoes(&mut interpreter);
+    let mut vm = Vm::default();
+    vm.capture_output();
+    echoes(&mut vm);
+}
+fn rejects_deep_nesting(backend: &mut impl Backend) {
+    let depth = 200_000;
+    let parens = format!("print {}1{};", "(".repeat(depth), ")".repeat(depth));
+    let errors = run(&parens, backend).unwrap_err();
+    assert_eq!(errors[0].message, "code is nested too deeply");
+    let minus = format!("print {}1;", "-".repeat(300_000));
+    let errors = run(&minus, backend).unwrap_err();
+    assert_eq!(errors[0].message, "code is nested too deeply");
+    assert_eq!(errors.len(), 1);
+}
+#[test]
+fn deep_nesting_is_an_error() {
+    rejects_deep_nesting(&mut Interpreter::default());
+    rejects_deep_nesting(&mut Vm::default());
+}
+#[test]
+fn every_pass_limits_its_stack() {
+    // parsed with room to spare, then handed to passes allowed far less
+    let source = format!("var a;\n\nprint {}1;", "-".repeat(2000));
+    std::thread::Builder::new()
+        .stack_size(64 << 20)
+        .spawn(move || {
+            let mut scanner = Tokenizer::new(source);
+            let (tokens, _) = scanner.scan_tokens();
+            let mut parser = Parser::new(tokens);
+            parser.set_stack_limit(32 << 20);
+            let stmts = parser.parse().unwrap();
+            let limit = 64 << 10;
+            let mut resolver = Resolver::new();
+            resolver.set_stack_limit(limit);
+            let errors = resolver.resolve_all(&stmts).unwrap_err();
+            assert_eq!(errors[0].to_string(), "Code is nested too deeply.");
+            assert_eq!(errors[0].line(), 3);
+            let Err(error) = Compiler::compile(&stmts, limit) else {
+                panic!("compiled code nested too deeply");
+            };
+            assert_eq!(error.to_string(), "Code is nested too deeply.");
+            assert_eq!(error.line(), 3);
+            let mut interpreter = Interpreter::default();
+            interpreter.set_stack_limit(limit);
+            let error = interpreter.interpret(&stmts).unwrap_err();
+            assert_eq!(error.to_string(), "Stack overflow.");
+        })
+        .unwrap()
+        .join()
+        .unwrap();
+}